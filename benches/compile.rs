@@ -0,0 +1,75 @@
+//! Compares [`Expr::solve_for`] with evaluating the same expression compiled to bytecode,
+//! one point at a time and in batches.
+//! Run with `cargo bench --bench compile`.
+
+use rsde::expr::syntax::*;
+use rsde::expr::Expr;
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 1_000_000;
+
+fn time(mut f: impl FnMut(f64) -> f64) -> Duration {
+    let start = Instant::now();
+    let mut sum = 0.0;
+    for i in 0..ITERATIONS {
+        sum += f(f64::from(i) * 1e-6);
+    }
+    black_box(sum);
+    start.elapsed()
+}
+
+fn main() {
+    let y = var('y');
+    // a damped oscillator right hand side, typical of what the ODE loops evaluate
+    let e: Expr = num(3)
+        .times(y.clone())
+        .neg()
+        .minus(num(2).times(X).times(func("exp", X.neg().div(num(10)))))
+        .plus(sin(num(2).times(PI).times(X)).pow(num(2)))
+        .div(num(1).plus(X.times(X)));
+
+    let mut vars = HashMap::from([('x', 0.0), ('y', 0.5)]);
+    let tree = time(|x| {
+        vars.insert('x', x);
+        e.solve_for(&vars).unwrap()
+    });
+
+    let compiled = e.compile(&['x', 'y']).unwrap();
+    let mut stack = Vec::new();
+    let bytecode = time(|x| compiled.eval_with(&[x, 0.5], &mut stack));
+
+    let xs: Vec<f64> = (0..ITERATIONS).map(|i| f64::from(i) * 1e-6).collect();
+    let ys = vec![0.5; xs.len()];
+    let mut out = vec![0.0; xs.len()];
+    let start = Instant::now();
+    compiled.eval_batch(&[&xs, &ys], &mut out).unwrap();
+    let batch = start.elapsed();
+    let threads = std::thread::available_parallelism().map_or(1, usize::from);
+    let start = Instant::now();
+    compiled
+        .eval_batch_parallel(&[&xs, &ys], &mut out, threads)
+        .unwrap();
+    let parallel = start.elapsed();
+    black_box(&out);
+
+    println!("solve_for: {:?} for {} evaluations", tree, ITERATIONS);
+    println!("compiled:  {:?} for {} evaluations", bytecode, ITERATIONS);
+    println!("batch:     {:?} for {} evaluations", batch, ITERATIONS);
+    println!(
+        "parallel:  {:?} for {} evaluations on {} threads",
+        parallel, ITERATIONS, threads
+    );
+    for (name, duration) in [
+        ("compiled", bytecode),
+        ("batch", batch),
+        ("parallel", parallel),
+    ] {
+        println!(
+            "speedup of {} over solve_for: {:.1}x",
+            name,
+            tree.as_secs_f64() / duration.as_secs_f64()
+        );
+    }
+}
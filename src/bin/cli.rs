@@ -1,12 +1,62 @@
+use rsde::expr::assumptions::{Assumptions, Conditional, Predicate};
+use rsde::expr::bigfloat::{max_precision, BigFloat};
+use rsde::expr::complex::Complex;
+use rsde::expr::uncertainty::{Measurement, Propagation};
 use rsde::expr::Expr;
 use rsde::out::*;
 use rsde::parser;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::env::args;
 use std::time::Instant;
 
 fn help() {
-    println!("help!");
+    println!(
+        "\
+usage: cli --expr <expression> [options]
+
+Evaluates the expression at the point given with --at, or transforms it with one of the
+operations below. Variables are single letters other than e and i, which name constants.
+
+operations:
+  -e, --expr <expression>        the expression to work on
+  -d, --derivative               differentiate, with --with-respect-to
+  -r, --with-respect-to <x>      the variable to differentiate with respect to
+  -s, --simplify                 simplify the expression
+      --trig                     also apply trigonometric identities, with -s or -d
+      --expand                   multiply out products and integer powers
+      --collect <x>              collect the terms of a sum by powers of x
+      --factor                   factor the expression over the rationals
+      --together                 combine a sum of fractions into one fraction
+      --apart <x>                decompose into partial fractions in x
+
+values:
+      --at <x>=<value>           evaluate at x = value, e.g. x=2, or x=2±0.1 (x=2+-0.1)
+                                 to propagate an uncertainty; repeat for more variables
+      --covariance <x>,<y>=<c>   the covariance of two uncertain values given with --at
+      --subs <x>=<expression>    replace x by an expression before anything else
+      --assume <x>=<predicate>   assume x is positive, nonnegative, real, integer or
+                                 nonzero when simplifying or differentiating; without
+                                 it, cancelled factors are reported as assumptions
+      --complex                  evaluate over the complex numbers, where i and
+                                 sqrt(-1) have a value
+      --precision <digits>       evaluate to this many significant digits rather than
+                                 as a float; not with --complex
+
+output:
+  -o, --output <kind>            how to write expressions:
+                                   standard       plain text, the default
+                                   latex          LaTeX
+                                   mathml         presentation MathML
+                                   mathml-content content MathML
+                                   pretty         2D text with Unicode symbols
+                                   pretty-ascii   2D text in ASCII
+                                   dot            a Graphviz graph of the tree
+                                   dot-shared     the graph with repeated subtrees
+                                                  highlighted
+      --cse                      bind repeated subexpressions to variables first
+  -t, --time                     print how long the operation took
+  -h, --help                     print this help"
+    );
 }
 
 struct ApplicationArgs {
@@ -14,14 +64,41 @@ struct ApplicationArgs {
     derivative: bool,
     derivative_over: Option<char>,
     simplify: bool,
+    trig: bool,
+    expand: bool,
+    collect_over: Option<char>,
+    factor: bool,
+    together: bool,
+    apart_over: Option<char>,
     vars: HashMap<char, f64>,
+    propagation: Propagation,
+    uncertain: bool,
+    subs: BTreeMap<char, Expr>,
+    assumptions: Assumptions,
     out: Box<dyn Out>,
-    timed: bool
+    cse: bool,
+    complex: bool,
+    precision: Option<u32>,
+    timed: bool,
 }
 
 impl ApplicationArgs {
-    fn add_var(&mut self, name: char, value: f64) {
-        self.vars.insert(name, value);
+    fn add_var(&mut self, name: char, value: Measurement) {
+        self.vars.insert(name, value.value);
+        self.propagation = std::mem::take(&mut self.propagation).input(name, value);
+        self.uncertain |= value.sigma != 0.0;
+    }
+
+    fn add_covariance(&mut self, a: char, b: char, covariance: f64) {
+        self.propagation = std::mem::take(&mut self.propagation).covariance(a, b, covariance);
+    }
+
+    fn add_subs(&mut self, name: char, with: Expr) {
+        self.subs.insert(name, with);
+    }
+
+    fn assume(&mut self, name: char, predicate: Predicate) {
+        self.assumptions = std::mem::take(&mut self.assumptions).assume(name, predicate);
     }
 
     fn set_expr(&mut self, expr: Expr) {
@@ -36,6 +113,30 @@ impl ApplicationArgs {
         self.simplify = true;
     }
 
+    fn trig(&mut self) {
+        self.trig = true;
+    }
+
+    fn expand(&mut self) {
+        self.expand = true;
+    }
+
+    fn collect(&mut self, var: char) {
+        self.collect_over = Some(var);
+    }
+
+    fn factor(&mut self) {
+        self.factor = true;
+    }
+
+    fn together(&mut self) {
+        self.together = true;
+    }
+
+    fn apart(&mut self, var: char) {
+        self.apart_over = Some(var);
+    }
+
     fn over(&mut self, var: char) {
         self.derivative_over = Some(var);
     }
@@ -43,15 +144,27 @@ impl ApplicationArgs {
     fn out(&mut self, output: Box<dyn Out>) {
         self.out = output;
     }
-    
+
+    fn cse(&mut self) {
+        self.cse = true;
+    }
+
+    fn complex(&mut self) {
+        self.complex = true;
+    }
+
+    fn precision(&mut self, digits: u32) {
+        self.precision = Some(digits);
+    }
+
     fn time(&mut self, time: bool) {
         self.timed = time;
     }
 }
 
-fn parse_var(key_value_pair: String) -> Result<(char, f64), String> {
+fn parse_var(key_value_pair: String) -> Result<(char, Measurement), String> {
     if let [key_str, value_str] = key_value_pair.split("=").collect::<Vec<_>>()[..] {
-        match (key_str.chars().next(), value_str.parse::<f64>()) {
+        match (key_str.chars().next(), Measurement::parse(value_str)) {
             (Some(var_name), Ok(value)) => Ok((var_name, value)),
             _ => Err(format!("invalid argument pattern: [{}]", key_value_pair)),
         }
@@ -60,15 +173,90 @@ fn parse_var(key_value_pair: String) -> Result<(char, f64), String> {
     }
 }
 
+fn parse_covariance(key_value_pair: String) -> Result<(char, char, f64), String> {
+    let invalid = || format!("invalid argument pattern: [{}]", key_value_pair);
+    let (names, value) = key_value_pair.split_once("=").ok_or_else(invalid)?;
+    let mut names = names
+        .split(',')
+        .map(|name| name.chars().collect::<Vec<_>>());
+    match (
+        names.next().as_deref(),
+        names.next().as_deref(),
+        names.next(),
+    ) {
+        (Some(&[a]), Some(&[b]), None) => Ok((a, b, value.parse().map_err(|_| invalid())?)),
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_subs(key_value_pair: String) -> Result<(char, Expr), String> {
+    match key_value_pair.split_once("=") {
+        Some((key_str, value_str)) if key_str.chars().count() == 1 => Ok((
+            key_str.chars().next().unwrap(),
+            parser::parse_str(value_str.to_string())?,
+        )),
+        _ => Err(format!("invalid argument pattern: [{}]", key_value_pair)),
+    }
+}
+
+fn parse_assumption(key_value_pair: String) -> Result<(char, Predicate), String> {
+    if let [key_str, value_str] = key_value_pair.split("=").collect::<Vec<_>>()[..] {
+        match key_str.chars().next() {
+            Some(var_name) => Ok((var_name, Predicate::try_from(value_str)?)),
+            None => Err(format!("invalid argument pattern: [{}]", key_value_pair)),
+        }
+    } else {
+        Err(format!("invalid argument pattern: [{}]", key_value_pair))
+    }
+}
+
+/// Without `--assume`, quotients are cancelled generically and the cancelled factors are
+/// reported.
+fn or_generic(assumptions: Assumptions) -> Assumptions {
+    if assumptions == Assumptions::new() {
+        Assumptions::generic()
+    } else {
+        assumptions
+    }
+}
+
+fn print_conditions(conditional: &Conditional) {
+    for condition in &conditional.conditions {
+        println!("assuming {}", condition);
+    }
+}
+
+/// `e` in `out`'s notation, with repeated subexpressions bound to variables if `cse`.
+fn render(out: &dyn Out, e: &Expr, cse: bool) -> Result<String, String> {
+    if cse {
+        out.output_bindings(&e.cse())
+    } else {
+        out.output(e)
+    }
+}
+
 fn parse_app_args() -> Result<ApplicationArgs, String> {
     let mut result = ApplicationArgs {
         expr: None,
         derivative: false,
         derivative_over: None,
         simplify: false,
+        trig: false,
+        expand: false,
+        collect_over: None,
+        factor: false,
+        together: false,
+        apart_over: None,
         vars: HashMap::new(),
+        propagation: Propagation::new(),
+        uncertain: false,
+        subs: BTreeMap::new(),
+        assumptions: Assumptions::new(),
         out: Box::new(standard()),
-        timed: false
+        cse: false,
+        complex: false,
+        precision: None,
+        timed: false,
     };
     let mut a = args().collect::<VecDeque<_>>();
 
@@ -92,6 +280,33 @@ fn parse_app_args() -> Result<ApplicationArgs, String> {
                     return Err("No value provided after --at".into());
                 }
             },
+            "--covariance" => match a.pop_front() {
+                Some(key_value_pair) => {
+                    let (first, second, covariance) = parse_covariance(key_value_pair)?;
+                    result.add_covariance(first, second, covariance);
+                }
+                None => {
+                    return Err("No covariance provided after --covariance".into());
+                }
+            },
+            "--subs" => match a.pop_front() {
+                Some(key_value_pair) => {
+                    let (var_name, with) = parse_subs(key_value_pair)?;
+                    result.add_subs(var_name, with);
+                }
+                None => {
+                    return Err("No substitution provided after --subs".into());
+                }
+            },
+            "--assume" => match a.pop_front() {
+                Some(key_value_pair) => {
+                    let (var_name, predicate) = parse_assumption(key_value_pair)?;
+                    result.assume(var_name, predicate);
+                }
+                None => {
+                    return Err("No assumption provided after --assume".into());
+                }
+            },
             "--derivative" | "-d" => {
                 result.der(true);
             }
@@ -111,6 +326,34 @@ fn parse_app_args() -> Result<ApplicationArgs, String> {
             "--simplify" | "-s" => {
                 result.simplify();
             }
+            "--trig" => {
+                result.trig();
+            }
+            "--expand" => {
+                result.expand();
+            }
+            "--collect" => match a.pop_front().and_then(|v| v.chars().next()) {
+                Some(var_name) => {
+                    result.collect(var_name);
+                }
+                None => {
+                    return Err("No variable specified after --collect".into());
+                }
+            },
+            "--factor" => {
+                result.factor();
+            }
+            "--together" => {
+                result.together();
+            }
+            "--apart" => match a.pop_front().and_then(|v| v.chars().next()) {
+                Some(var_name) => {
+                    result.apart(var_name);
+                }
+                None => {
+                    return Err("No variable specified after --apart".into());
+                }
+            },
             "--help" | "-h" => {
                 help();
             }
@@ -121,6 +364,24 @@ fn parse_app_args() -> Result<ApplicationArgs, String> {
                 "latex" => {
                     result.out(Box::new(latex()));
                 }
+                "mathml" => {
+                    result.out(Box::new(mathml()));
+                }
+                "mathml-content" => {
+                    result.out(Box::new(content_mathml()));
+                }
+                "pretty" => {
+                    result.out(Box::new(pretty()));
+                }
+                "pretty-ascii" => {
+                    result.out(Box::new(ascii_pretty()));
+                }
+                "dot" => {
+                    result.out(Box::new(dot()));
+                }
+                "dot-shared" => {
+                    result.out(Box::new(dot().highlight_shared()));
+                }
                 "" => {
                     return Err("no output specified, run with --help".into());
                 }
@@ -128,31 +389,69 @@ fn parse_app_args() -> Result<ApplicationArgs, String> {
                     return Err(format!("not a valid output {}", other));
                 }
             },
+            "--complex" => {
+                result.complex();
+            }
+            "--precision" => match a.pop_front().map(|v| v.parse::<u32>()) {
+                Some(Ok(digits)) if digits > 0 => result.precision(digits),
+                _ => {
+                    return Err("a positive number of digits has to follow --precision".into());
+                }
+            },
+            "--cse" => {
+                result.cse();
+            }
             "--time" | "--timed" | "-t" => {
                 result.time(true);
             }
             _ => {}
         }
     }
+    if result.complex && result.precision.is_some() {
+        return Err("--precision only evaluates real numbers, not with --complex".into());
+    }
 
     Ok(result)
 }
 
 fn main() -> Result<(), String> {
     let start = Instant::now();
-    match parse_app_args()? {
+    let mut args = parse_app_args()?;
+    // substitutions apply before any other operation
+    if !args.subs.is_empty() {
+        args.expr = args.expr.map(|e| e.subs_all(&args.subs));
+    }
+    match args {
         // derivative
         ApplicationArgs {
             expr: Some(e),
             derivative: true,
             derivative_over: Some(var),
+            trig,
             vars,
+            assumptions,
             out,
+            cse,
             timed,
             ..
         } => {
-            let der = e.derivative().with_respect_to(var)?.simplified();
-            println!("derivative formula: {}", out.output(&der)?);
+            let assumptions = or_generic(assumptions);
+            let mut der = e.derivative().with_respect_to_assuming(var, &assumptions)?;
+            if trig {
+                let simplified = der.expr.trig_simplified_assuming(&assumptions);
+                der.expr = simplified.expr;
+                der.conditions.extend(simplified.conditions);
+            }
+            print_conditions(&der);
+            let der = der.expr;
+            let formula = render(&*out, &der, cse)?;
+            // the notation and the result only make an equation on a single line
+            if out.is_inline() && !cse {
+                let notation = out.output_derivative(&e, var)?;
+                println!("derivative formula: {} = {}", notation, formula);
+            } else {
+                println!("derivative formula:\n{}", formula);
+            }
             if vars.contains_key(&var) {
                 let value = der.solve_for(&vars)?;
                 println!("derivative at specified point(s) is {}", value);
@@ -163,35 +462,188 @@ fn main() -> Result<(), String> {
             }
             Ok(())
         }
+        // expand
+        ApplicationArgs {
+            expr: Some(e),
+            derivative: false,
+            expand: true,
+            out,
+            cse,
+            timed,
+            ..
+        } => {
+            let expanded = e.expanded()?;
+            println!("expanded expression:\n{}", render(&*out, &expanded, cse)?);
+            if timed {
+                let end = Instant::now();
+                println!("operation took {:?}", end.duration_since(start));
+            }
+            Ok(())
+        }
+        // collect
+        ApplicationArgs {
+            expr: Some(e),
+            derivative: false,
+            collect_over: Some(var),
+            out,
+            cse,
+            timed,
+            ..
+        } => {
+            let collected = e.collected(var)?;
+            println!("collected expression:\n{}", render(&*out, &collected, cse)?);
+            if timed {
+                let end = Instant::now();
+                println!("operation took {:?}", end.duration_since(start));
+            }
+            Ok(())
+        }
+        // factor
+        ApplicationArgs {
+            expr: Some(e),
+            derivative: false,
+            factor: true,
+            out,
+            cse,
+            timed,
+            ..
+        } => {
+            let factored = e.factored()?;
+            println!("factored expression:\n{}", render(&*out, &factored, cse)?);
+            if timed {
+                let end = Instant::now();
+                println!("operation took {:?}", end.duration_since(start));
+            }
+            Ok(())
+        }
+        // together
+        ApplicationArgs {
+            expr: Some(e),
+            derivative: false,
+            together: true,
+            out,
+            cse,
+            timed,
+            ..
+        } => {
+            let combined = e.together()?;
+            println!("combined expression:\n{}", render(&*out, &combined, cse)?);
+            if timed {
+                let end = Instant::now();
+                println!("operation took {:?}", end.duration_since(start));
+            }
+            Ok(())
+        }
+        // apart
+        ApplicationArgs {
+            expr: Some(e),
+            derivative: false,
+            apart_over: Some(var),
+            out,
+            cse,
+            timed,
+            ..
+        } => {
+            let fractions = e.apart(var)?;
+            println!("partial fractions:\n{}", render(&*out, &fractions, cse)?);
+            if timed {
+                let end = Instant::now();
+                println!("operation took {:?}", end.duration_since(start));
+            }
+            Ok(())
+        }
         // simplify
         ApplicationArgs {
             expr: Some(e),
             derivative: false,
             simplify: true,
+            trig,
+            assumptions,
+            out,
+            cse,
+            timed,
+            ..
+        } => {
+            let assumptions = or_generic(assumptions);
+            let simplified = if trig {
+                e.trig_simplified_assuming(&assumptions)
+            } else {
+                e.simplified_assuming(&assumptions)
+            };
+            print_conditions(&simplified);
+            let simplified = simplified.expr;
+            println!(
+                "simplified expression:\n{}",
+                render(&*out, &simplified, cse)?
+            );
+            if timed {
+                let end = Instant::now();
+                println!("operation took {:?}", end.duration_since(start));
+            }
+            Ok(())
+        }
+        // substitute, folding what became constant
+        ApplicationArgs {
+            expr: Some(e),
+            derivative: false,
+            simplify: false,
+            subs,
+            vars,
             out,
+            cse,
+            timed,
+            ..
+        } if !subs.is_empty() && vars.is_empty() => {
+            let substituted = e.evaluated_partially(&BTreeMap::new());
+            println!(
+                "substituted expression:\n{}",
+                render(&*out, &substituted, cse)?
+            );
+            if timed {
+                let end = Instant::now();
+                println!("operation took {:?}", end.duration_since(start));
+            }
+            Ok(())
+        }
+        // solve with uncertain inputs
+        ApplicationArgs {
+            expr: Some(e),
+            derivative: false,
+            simplify: false,
+            propagation,
+            uncertain: true,
             timed,
             ..
         } => {
-            let simplified = e.simplified();
-            println!("simplified expression:\n{}", out.output(&simplified)?);
+            println!(
+                "expression value at given point(s) is {}",
+                propagation.propagate(&e)?
+            );
             if timed {
                 let end = Instant::now();
                 println!("operation took {:?}", end.duration_since(start));
             }
             Ok(())
         }
-        // solve
         ApplicationArgs {
             expr: Some(e),
             derivative: false,
             simplify: false,
             vars,
+            precision: Some(digits),
             timed,
             ..
         } => {
+            // the shortest decimals of the given values are what was typed after --at
+            let bits = max_precision(digits);
+            let vars = vars
+                .iter()
+                .map(|(&name, value)| Ok((name, BigFloat::parse(&value.to_string(), bits)?)))
+                .collect::<Result<HashMap<_, _>, String>>()?;
+            let value = e.solve_precise(&vars, digits)?;
             println!(
                 "expression value at given point(s) is {}",
-                e.solve_for(&vars)?
+                value.to_decimal(digits)
             );
             if timed {
                 let end = Instant::now();
@@ -199,6 +651,34 @@ fn main() -> Result<(), String> {
             }
             Ok(())
         }
+        ApplicationArgs {
+            expr: Some(e),
+            derivative: false,
+            simplify: false,
+            vars,
+            complex,
+            timed,
+            ..
+        } => {
+            // values like `i` or `sqrt(-1)` are only complex numbers with --complex, and
+            // domain errors otherwise
+            if complex {
+                let vars = vars
+                    .iter()
+                    .map(|(&name, &value)| (name, Complex::real(value)))
+                    .collect();
+                let value = e.solve_complex(&vars)?;
+                println!("expression value at given point(s) is {}", value);
+            } else {
+                let value = e.solve_for(&vars)?;
+                println!("expression value at given point(s) is {}", value);
+            }
+            if timed {
+                let end = Instant::now();
+                println!("operation took {:?}", end.duration_since(start));
+            }
+            Ok(())
+        }
         _ => Err("unrecognizable CLI args pattern".into()),
     }
 }
@@ -0,0 +1,260 @@
+use crate::expr::rational::Rational;
+use crate::expr::Expr;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+
+/// A property a variable, or an expression built from variables, can be known to have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Predicate {
+    Positive,
+    Nonnegative,
+    Real,
+    Integer,
+    Nonzero,
+}
+
+impl Predicate {
+    /// The predicates that follow from this one, including itself.
+    fn implied(self) -> &'static [Predicate] {
+        match self {
+            Predicate::Positive => &[
+                Predicate::Positive,
+                Predicate::Nonnegative,
+                Predicate::Real,
+                Predicate::Nonzero,
+            ],
+            Predicate::Nonnegative => &[Predicate::Nonnegative, Predicate::Real],
+            Predicate::Integer => &[Predicate::Integer, Predicate::Real],
+            Predicate::Real => &[Predicate::Real],
+            Predicate::Nonzero => &[Predicate::Nonzero],
+        }
+    }
+}
+
+impl TryFrom<&str> for Predicate {
+    type Error = String;
+
+    fn try_from(name: &str) -> Result<Self, Self::Error> {
+        match name {
+            "positive" => Ok(Predicate::Positive),
+            "nonnegative" => Ok(Predicate::Nonnegative),
+            "real" => Ok(Predicate::Real),
+            "integer" => Ok(Predicate::Integer),
+            "nonzero" => Ok(Predicate::Nonzero),
+            other => Err(format!("unknown assumption [{}]", other)),
+        }
+    }
+}
+
+/// A predicate some result relies on holding for an expression.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Condition {
+    pub expr: Expr,
+    pub predicate: Predicate,
+}
+
+impl Condition {
+    pub fn new(expr: Expr, predicate: Predicate) -> Self {
+        Condition { expr, predicate }
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let e = crate::out::describe(&self.expr);
+        match self.predicate {
+            Predicate::Positive => write!(f, "{} > 0", e),
+            Predicate::Nonnegative => write!(f, "{} ≥ 0", e),
+            Predicate::Real => write!(f, "{} is real", e),
+            Predicate::Integer => write!(f, "{} is an integer", e),
+            Predicate::Nonzero => write!(f, "{} ≠ 0", e),
+        }
+    }
+}
+
+/// An expression that is only equal to the one it was derived from where `conditions` hold.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conditional {
+    pub expr: Expr,
+    pub conditions: BTreeSet<Condition>,
+}
+
+/// What is known about the variables of an expression. Properties of compound expressions
+/// are inferred from their parts, e.g. `x^2 + 1` is positive when `x` is real.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Assumptions {
+    vars: BTreeMap<char, BTreeSet<Predicate>>,
+    generic: bool,
+}
+
+impl Assumptions {
+    pub fn new() -> Self {
+        Assumptions::default()
+    }
+
+    /// Takes every expression that isn't a number to be nonzero, the way [`simplify`]
+    /// cancels quotients, so that the cancelled factors are reported as conditions.
+    ///
+    /// [`simplify`]: crate::expr::simplify::simplify
+    pub fn generic() -> Self {
+        Assumptions {
+            generic: true,
+            ..Assumptions::default()
+        }
+    }
+
+    pub fn assume(mut self, var: char, predicate: Predicate) -> Self {
+        self.vars
+            .entry(var)
+            .or_default()
+            .extend(predicate.implied().iter().copied());
+        self
+    }
+
+    fn var_is(&self, var: char, predicate: Predicate) -> bool {
+        self.vars
+            .get(&var)
+            .is_some_and(|known| known.contains(&predicate))
+    }
+
+    /// Whether `condition` follows from the assumptions.
+    pub fn implies(&self, condition: &Condition) -> bool {
+        match condition.predicate {
+            Predicate::Positive => self.is_positive(&condition.expr),
+            Predicate::Nonnegative => self.is_nonnegative(&condition.expr),
+            Predicate::Real => self.is_real(&condition.expr),
+            Predicate::Integer => self.is_integer(&condition.expr),
+            Predicate::Nonzero => {
+                self.is_nonzero(&condition.expr)
+                    || (self.generic && Rational::from_expr(&condition.expr).is_none())
+            }
+        }
+    }
+
+    pub fn is_positive(&self, e: &Expr) -> bool {
+        if let Some(r) = Rational::from_expr(e) {
+            return !r.is_zero() && !r.is_negative();
+        }
+        match e {
+            Expr::E | Expr::Pi | Expr::Tau | Expr::Phi => true,
+            Expr::Var(v) => self.var_is(*v, Predicate::Positive),
+            Expr::Add(lhs, rhs) => {
+                (self.is_positive(lhs) && self.is_nonnegative(rhs))
+                    || (self.is_nonnegative(lhs) && self.is_positive(rhs))
+            }
+            Expr::Mul(lhs, rhs) | Expr::Div(lhs, rhs) => {
+                self.is_positive(lhs) && self.is_positive(rhs)
+            }
+            Expr::Pow(base, exponent) => {
+                (self.is_positive(base) && self.is_real(exponent))
+                    || (is_even(exponent) && self.is_real(base) && self.is_nonzero(base))
+            }
+            Expr::Func(name, arg) => name == "exp" && self.is_real(arg),
+            _ => false,
+        }
+    }
+
+    pub fn is_nonnegative(&self, e: &Expr) -> bool {
+        match e {
+            Expr::Const(_) => true,
+            Expr::Var(v) if self.var_is(*v, Predicate::Nonnegative) => true,
+            Expr::Pow(base, exponent) if is_even(exponent) => self.is_real(base),
+            Expr::Add(lhs, rhs) | Expr::Mul(lhs, rhs) => {
+                self.is_nonnegative(lhs) && self.is_nonnegative(rhs)
+            }
+            _ => self.is_positive(e),
+        }
+    }
+
+    pub fn is_real(&self, e: &Expr) -> bool {
+        if Rational::from_expr(e).is_some() {
+            return true;
+        }
+        match e {
+            Expr::E | Expr::Pi | Expr::Tau | Expr::Phi => true,
+            Expr::Var(v) => self.var_is(*v, Predicate::Real),
+            Expr::Add(lhs, rhs) | Expr::Sub(lhs, rhs) | Expr::Mul(lhs, rhs) => {
+                self.is_real(lhs) && self.is_real(rhs)
+            }
+            Expr::Div(lhs, rhs) => self.is_real(lhs) && self.is_real(rhs) && self.is_nonzero(rhs),
+            Expr::Neg(inner) => self.is_real(inner),
+            Expr::Pow(base, exponent) => {
+                (self.is_positive(base) && self.is_real(exponent))
+                    || (self.is_real(base) && matches!(exponent.as_ref(), Expr::Const(_)))
+            }
+            Expr::Func(name, arg) => match name.as_str() {
+                "sin" | "cos" | "exp" => self.is_real(arg),
+                "ln" | "sqrt" => self.is_positive(arg),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    pub fn is_integer(&self, e: &Expr) -> bool {
+        if let Some(r) = Rational::from_expr(e) {
+            return r.is_integer();
+        }
+        match e {
+            Expr::Var(v) => self.var_is(*v, Predicate::Integer),
+            Expr::Add(lhs, rhs) | Expr::Sub(lhs, rhs) | Expr::Mul(lhs, rhs) => {
+                self.is_integer(lhs) && self.is_integer(rhs)
+            }
+            Expr::Neg(inner) => self.is_integer(inner),
+            Expr::Pow(base, exponent) => {
+                self.is_integer(base) && matches!(exponent.as_ref(), Expr::Const(_))
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_nonzero(&self, e: &Expr) -> bool {
+        if let Some(r) = Rational::from_expr(e) {
+            return !r.is_zero();
+        }
+        match e {
+            Expr::E | Expr::Pi | Expr::Tau | Expr::Phi | Expr::I | Expr::Inf => true,
+            Expr::Var(v) => self.var_is(*v, Predicate::Nonzero),
+            Expr::Neg(inner) => self.is_nonzero(inner),
+            Expr::Mul(lhs, rhs) => self.is_nonzero(lhs) && self.is_nonzero(rhs),
+            Expr::Div(lhs, _) => self.is_nonzero(lhs),
+            Expr::Pow(base, _) => self.is_nonzero(base),
+            _ => self.is_positive(e),
+        }
+    }
+}
+
+fn is_even(e: &Expr) -> bool {
+    matches!(e, Expr::Const(n) if n % 2 == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::syntax::*;
+
+    #[test]
+    fn predicates_follow_from_the_parts() {
+        let assumptions = Assumptions::new()
+            .assume('x', Predicate::Real)
+            .assume('y', Predicate::Positive);
+        let y = var('y');
+        assert!(assumptions.is_positive(&y.clone().pow(X).plus(PI)));
+        assert!(assumptions.is_real(&X.times(y.clone()).minus(num(1))));
+        assert!(assumptions.is_nonzero(&y.clone().times(num(2))));
+        assert!(!assumptions.is_nonzero(&X));
+        assert!(!assumptions.is_positive(&X.pow(num(2))));
+        assert!(assumptions.is_positive(&X.pow(num(2)).plus(num(1))));
+        assert!(!assumptions.is_integer(&y));
+    }
+
+    #[test]
+    fn conditions_are_displayed() {
+        assert_eq!(Condition::new(X, Predicate::Nonzero).to_string(), "x ≠ 0");
+        assert_eq!(
+            Condition::new(PI, Predicate::Positive).to_string(),
+            "pi > 0"
+        );
+        assert_eq!(Predicate::try_from("positive"), Ok(Predicate::Positive));
+    }
+}
@@ -0,0 +1,493 @@
+use crate::expr::domain;
+use crate::expr::rational::Rational;
+use crate::expr::Expr;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// A value together with its derivative with respect to one variable, propagated with the
+/// chain rule by every operation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dual {
+    pub value: f64,
+    pub derivative: f64,
+}
+
+impl Dual {
+    pub fn constant(value: f64) -> Self {
+        Dual {
+            value,
+            derivative: 0.0,
+        }
+    }
+
+    /// The variable differentiated by, whose derivative is 1.
+    pub fn variable(value: f64) -> Self {
+        Dual {
+            value,
+            derivative: 1.0,
+        }
+    }
+
+    /// Applies a function with derivative `derivative` at this value.
+    fn chain(self, value: f64, derivative: f64) -> Self {
+        Dual {
+            value,
+            derivative: derivative * self.derivative,
+        }
+    }
+
+    pub fn powf(self, exponent: Dual) -> Self {
+        let value = self.value.powf(exponent.value);
+        // constant exponents don't need the logarithm of the base, which may be negative;
+        // x^0 is constant even where x^-1 is not finite
+        let derivative = if exponent.derivative == 0.0 && exponent.value == 0.0 {
+            0.0
+        } else if exponent.derivative == 0.0 {
+            exponent.value * self.value.powf(exponent.value - 1.0) * self.derivative
+        } else {
+            value
+                * (exponent.derivative * self.value.ln()
+                    + exponent.value * self.derivative / self.value)
+        };
+        Dual { value, derivative }
+    }
+
+    /// `self^exponent` for a fraction with an odd denominator, which is real for negative
+    /// values too.
+    pub fn odd_root(self, exponent: Rational) -> Self {
+        let r = exponent.to_f64();
+        let value = domain::real_pow(self.value, r, Some(exponent));
+        let below = exponent.checked_sub(Rational::ONE);
+        self.chain(value, r * domain::real_pow(self.value, r - 1.0, below))
+    }
+
+    pub fn sin(self) -> Self {
+        self.chain(self.value.sin(), self.value.cos())
+    }
+
+    pub fn cos(self) -> Self {
+        self.chain(self.value.cos(), -self.value.sin())
+    }
+
+    pub fn tan(self) -> Self {
+        let tan = self.value.tan();
+        self.chain(tan, 1.0 + tan * tan)
+    }
+
+    pub fn cot(self) -> Self {
+        let cot = 1.0 / self.value.tan();
+        self.chain(cot, -(1.0 + cot * cot))
+    }
+
+    pub fn ln(self) -> Self {
+        self.chain(self.value.ln(), 1.0 / self.value)
+    }
+
+    pub fn exp(self) -> Self {
+        let exp = self.value.exp();
+        self.chain(exp, exp)
+    }
+
+    pub fn sqrt(self) -> Self {
+        let sqrt = self.value.sqrt();
+        self.chain(sqrt, 0.5 / sqrt)
+    }
+}
+
+impl Add for Dual {
+    type Output = Dual;
+
+    fn add(self, rhs: Dual) -> Dual {
+        Dual {
+            value: self.value + rhs.value,
+            derivative: self.derivative + rhs.derivative,
+        }
+    }
+}
+
+impl Sub for Dual {
+    type Output = Dual;
+
+    fn sub(self, rhs: Dual) -> Dual {
+        Dual {
+            value: self.value - rhs.value,
+            derivative: self.derivative - rhs.derivative,
+        }
+    }
+}
+
+impl Mul for Dual {
+    type Output = Dual;
+
+    fn mul(self, rhs: Dual) -> Dual {
+        Dual {
+            value: self.value * rhs.value,
+            derivative: self.derivative * rhs.value + self.value * rhs.derivative,
+        }
+    }
+}
+
+impl Div for Dual {
+    type Output = Dual;
+
+    fn div(self, rhs: Dual) -> Dual {
+        Dual {
+            value: self.value / rhs.value,
+            derivative: (self.derivative * rhs.value - self.value * rhs.derivative)
+                / (rhs.value * rhs.value),
+        }
+    }
+}
+
+impl Neg for Dual {
+    type Output = Dual;
+
+    fn neg(self) -> Dual {
+        Dual {
+            value: -self.value,
+            derivative: -self.derivative,
+        }
+    }
+}
+
+/// Evaluates `e` at `vars` like [`Expr::solve_for`], along with its derivative with respect
+/// to `var`, without building the derivative expression.
+pub fn forward(e: &Expr, vars: &HashMap<char, f64>, var: char) -> Result<Dual, String> {
+    let eval = |e: &Expr| forward(e, vars, var);
+    match e {
+        Expr::Var(name) => {
+            let value = vars
+                .get(name)
+                .copied()
+                .ok_or(format!("could not find variable [{}]", name))?;
+            Ok(if *name == var {
+                Dual::variable(value)
+            } else {
+                Dual::constant(value)
+            })
+        }
+        Expr::Add(lhs, rhs) => Ok(eval(lhs)? + eval(rhs)?),
+        Expr::Sub(lhs, rhs) => Ok(eval(lhs)? - eval(rhs)?),
+        Expr::Mul(lhs, rhs) => Ok(eval(lhs)? * eval(rhs)?),
+        Expr::Div(lhs, rhs) => {
+            let (dividend, divisor) = (eval(lhs)?, eval(rhs)?);
+            domain::check(e, &[dividend.value, divisor.value])?;
+            Ok(dividend / divisor)
+        }
+        Expr::Pow(lhs, rhs) => {
+            let (base, exponent) = (eval(lhs)?, eval(rhs)?);
+            domain::check(e, &[base.value, exponent.value])?;
+            Ok(match domain::odd_root(rhs) {
+                Some(r) => base.odd_root(r),
+                None => base.powf(exponent),
+            })
+        }
+        Expr::Neg(inner) => Ok(-eval(inner)?),
+        Expr::Func(name, arg) => {
+            let x = eval(arg)?;
+            domain::check(e, &[x.value])?;
+            match name.as_str() {
+                "sin" => Ok(x.sin()),
+                "cos" => Ok(x.cos()),
+                "tan" => Ok(x.tan()),
+                "cot" => Ok(x.cot()),
+                "ln" => Ok(x.ln()),
+                "exp" => Ok(x.exp()),
+                "sqrt" => Ok(x.sqrt()),
+                _ => Err(format!("Unrecognized function [{}({:?})]", name, arg)),
+            }
+        }
+        constant => Ok(Dual::constant(constant.solve_for(vars)?)),
+    }
+}
+
+/// The partial derivatives of `e` with respect to every variable in `vars`, one forward
+/// pass each.
+pub fn gradient(e: &Expr, vars: &HashMap<char, f64>) -> Result<BTreeMap<char, f64>, String> {
+    vars.keys()
+        .map(|&var| Ok((var, forward(e, vars, var)?.derivative)))
+        .collect()
+}
+
+/// One operation recorded by [`reverse`]: the partial derivative of its value with respect
+/// to each operand that depends on a variable.
+struct Entry {
+    edges: Vec<(usize, f64)>,
+}
+
+/// The operations of one evaluation in the order they happened. Subexpressions that don't
+/// depend on any variable are evaluated but not recorded.
+#[derive(Default)]
+struct Tape {
+    entries: Vec<Entry>,
+    vars: HashMap<char, usize>,
+}
+
+impl Tape {
+    fn push(&mut self, edges: Vec<(usize, f64)>) -> usize {
+        self.entries.push(Entry { edges });
+        self.entries.len() - 1
+    }
+
+    /// The value of an operation, recorded if any of `operands` was.
+    fn op(&mut self, value: f64, operands: &[(Option<usize>, f64)]) -> (f64, Option<usize>) {
+        let edges: Vec<(usize, f64)> = operands
+            .iter()
+            .filter_map(|&(index, partial)| index.map(|i| (i, partial)))
+            .collect();
+        if edges.is_empty() {
+            (value, None)
+        } else {
+            (value, Some(self.push(edges)))
+        }
+    }
+
+    fn record(
+        &mut self,
+        e: &Expr,
+        vars: &HashMap<char, f64>,
+    ) -> Result<(f64, Option<usize>), String> {
+        match e {
+            Expr::Var(name) => {
+                let value = vars
+                    .get(name)
+                    .copied()
+                    .ok_or(format!("could not find variable [{}]", name))?;
+                let index = match self.vars.get(name) {
+                    Some(&index) => index,
+                    None => {
+                        let index = self.push(vec![]);
+                        self.vars.insert(*name, index);
+                        index
+                    }
+                };
+                Ok((value, Some(index)))
+            }
+            Expr::Add(lhs, rhs) => {
+                let ((a, i), (b, j)) = (self.record(lhs, vars)?, self.record(rhs, vars)?);
+                Ok(self.op(a + b, &[(i, 1.0), (j, 1.0)]))
+            }
+            Expr::Sub(lhs, rhs) => {
+                let ((a, i), (b, j)) = (self.record(lhs, vars)?, self.record(rhs, vars)?);
+                Ok(self.op(a - b, &[(i, 1.0), (j, -1.0)]))
+            }
+            Expr::Mul(lhs, rhs) => {
+                let ((a, i), (b, j)) = (self.record(lhs, vars)?, self.record(rhs, vars)?);
+                Ok(self.op(a * b, &[(i, b), (j, a)]))
+            }
+            Expr::Div(lhs, rhs) => {
+                let ((a, i), (b, j)) = (self.record(lhs, vars)?, self.record(rhs, vars)?);
+                domain::check(e, &[a, b])?;
+                Ok(self.op(a / b, &[(i, 1.0 / b), (j, -a / (b * b))]))
+            }
+            Expr::Pow(lhs, rhs) => {
+                let ((a, i), (b, j)) = (self.record(lhs, vars)?, self.record(rhs, vars)?);
+                domain::check(e, &[a, b])?;
+                let odd_root = domain::odd_root(rhs);
+                let value = domain::real_pow(a, b, odd_root);
+                let below = odd_root.and_then(|r| r.checked_sub(Rational::ONE));
+                // the logarithm is only taken when the exponent is recorded, so negative
+                // bases with constant exponents keep finite derivatives
+                let exponent_partial = if j.is_some() { value * a.ln() } else { 0.0 };
+                let base_partial = if b == 0.0 {
+                    0.0
+                } else {
+                    b * domain::real_pow(a, b - 1.0, below)
+                };
+                Ok(self.op(value, &[(i, base_partial), (j, exponent_partial)]))
+            }
+            Expr::Neg(inner) => {
+                let (a, i) = self.record(inner, vars)?;
+                Ok(self.op(-a, &[(i, -1.0)]))
+            }
+            Expr::Func(name, arg) => {
+                let (a, i) = self.record(arg, vars)?;
+                domain::check(e, &[a])?;
+                let (value, partial) = match name.as_str() {
+                    "sin" => (a.sin(), a.cos()),
+                    "cos" => (a.cos(), -a.sin()),
+                    "tan" => (a.tan(), 1.0 + a.tan() * a.tan()),
+                    "cot" => {
+                        let cot = 1.0 / a.tan();
+                        (cot, -(1.0 + cot * cot))
+                    }
+                    "ln" => (a.ln(), 1.0 / a),
+                    "exp" => (a.exp(), a.exp()),
+                    "sqrt" => (a.sqrt(), 0.5 / a.sqrt()),
+                    _ => return Err(format!("Unrecognized function [{}({:?})]", name, arg)),
+                };
+                Ok(self.op(value, &[(i, partial)]))
+            }
+            constant => Ok((constant.solve_for(vars)?, None)),
+        }
+    }
+}
+
+/// Evaluates `e` at `vars` along with its partial derivatives with respect to every variable
+/// in `vars`, in one pass recording the evaluation and one pass back through it, however
+/// many variables there are.
+pub fn reverse(e: &Expr, vars: &HashMap<char, f64>) -> Result<(f64, BTreeMap<char, f64>), String> {
+    let mut tape = Tape::default();
+    let (value, root) = tape.record(e, vars)?;
+    let mut adjoints = vec![0.0; tape.entries.len()];
+    if let Some(root) = root {
+        adjoints[root] = 1.0;
+        for (i, entry) in tape.entries.iter().enumerate().rev() {
+            if adjoints[i] == 0.0 {
+                continue;
+            }
+            for &(operand, partial) in &entry.edges {
+                adjoints[operand] += adjoints[i] * partial;
+            }
+        }
+    }
+    let partials = vars
+        .keys()
+        .map(|name| (*name, tape.vars.get(name).map_or(0.0, |&i| adjoints[i])))
+        .collect();
+    Ok((value, partials))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::syntax::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-9 * a.abs().max(b.abs()).max(1.0)
+    }
+
+    #[test]
+    fn dual_numbers_carry_the_derivative() {
+        // d/dx x^3 sin(x) at x = 2
+        let e = X.pow(num(3)).times(sin(X));
+        let dual = e.value_and_derivative(&[('x', 2.0)].into(), 'x').unwrap();
+        assert_eq!(dual.value, 8.0 * 2f64.sin());
+        assert!(close(dual.derivative, 12.0 * 2f64.sin() + 8.0 * 2f64.cos()));
+        // negative bases with constant exponents
+        let square = X
+            .pow(num(2))
+            .value_and_derivative(&[('x', -3.0)].into(), 'x');
+        assert_eq!(square.map(|d| d.derivative), Ok(-6.0));
+        // x^0 is constant at x = 0 too
+        let point = HashMap::from([('x', 0.0)]);
+        let one = X.pow(num(0)).value_and_derivative(&point, 'x').unwrap();
+        assert_eq!((one.value, one.derivative), (1.0, 0.0));
+        let (value, partials) = X.pow(num(0)).value_and_gradient(&point).unwrap();
+        assert_eq!((value, partials[&'x']), (1.0, 0.0));
+    }
+
+    #[test]
+    fn matches_symbolic_derivatives() {
+        let exprs = [
+            X.times(X.pow(num(2)).plus(num(1))).div(X.plus(num(2))),
+            X.pow(num(3)).minus(num(2).div(X.pow(num(2)).plus(num(1)))),
+            X.plus(num(3))
+                .pow(num(1).div(num(2)))
+                .times(X.minus(num(4))),
+            X.pow(num(2)).minus(num(1)).neg().pow(num(3)),
+        ];
+        for e in exprs {
+            let symbolic = e.derivative().with_respect_to('x').unwrap();
+            for x in [-0.4, 0.6, 1.3, 2.5] {
+                let point = HashMap::from([('x', x)]);
+                let dual = e.value_and_derivative(&point, 'x').unwrap();
+                let expected = symbolic.solve_for(&point).unwrap();
+                assert!(close(dual.derivative, expected), "{:?} at {}", e, x);
+            }
+        }
+    }
+
+    #[test]
+    fn matches_symbolic_derivatives_of_functions_and_constant_parts() {
+        let y = var('y');
+        let exprs = [
+            X.times(sin(X)).div(X.plus(num(1))),
+            func("exp", X.times(num(2))).minus(func("ln", X.pow(num(2)))),
+            func("sqrt", X.pow(num(3)).plus(num(1))).times(func("cot", X)),
+            tan(X).times(cos(X)).neg().pow(num(3)),
+            // parts without x, in bases, exponents and function arguments
+            num(2).pow(X).plus(X.pow(sin(y.clone()))),
+            func("exp", y.clone())
+                .times(X)
+                .minus(num(3).pow(X.times(y))),
+        ];
+        for e in exprs {
+            let symbolic = e.derivative().with_respect_to('x').unwrap();
+            for x in [0.6, 1.3, 2.5] {
+                let point = HashMap::from([('x', x), ('y', 0.7)]);
+                let dual = e.value_and_derivative(&point, 'x').unwrap();
+                let expected = symbolic.solve_for(&point).unwrap();
+                assert!(close(dual.derivative, expected), "{:?} at {}", e, x);
+            }
+        }
+    }
+
+    #[test]
+    fn odd_roots_and_domains_match_solve_for() {
+        // d/dx x^(2/3) = 2/3 x^(-1/3), which is -1/3 at x = -8
+        let e = X.pow(num(2).div(num(3)));
+        let point = HashMap::from([('x', -8.0)]);
+        let dual = e.value_and_derivative(&point, 'x').unwrap();
+        assert!(close(dual.value, 4.0) && close(dual.derivative, -1.0 / 3.0));
+        let (value, partials) = e.value_and_gradient(&point).unwrap();
+        assert!(close(value, 4.0) && close(partials[&'x'], -1.0 / 3.0));
+        let log = func("ln", X);
+        assert!(log.value_and_derivative(&point, 'x').is_err());
+        assert!(log.value_and_gradient(&point).is_err());
+    }
+
+    #[test]
+    fn gradients_have_every_partial_derivative() {
+        // x^y + x y^2 at (2, 3)
+        let y = var('y');
+        let e = X.pow(y.clone()).plus(X.times(y.clone().pow(num(2))));
+        let grad = e.gradient_at(&[('x', 2.0), ('y', 3.0)].into()).unwrap();
+        assert!(close(grad[&'x'], 3.0 * 4.0 + 9.0));
+        assert!(close(grad[&'y'], 8.0 * 2f64.ln() + 12.0));
+    }
+
+    #[test]
+    fn missing_variables_are_errors() {
+        assert!(X
+            .plus(var('z'))
+            .value_and_derivative(&[('x', 1.0)].into(), 'x')
+            .is_err());
+    }
+
+    #[test]
+    fn reverse_mode_matches_forward_mode() {
+        let (y, z) = (var('y'), var('z'));
+        let e = X
+            .times(y.clone())
+            .times(sin(z.clone()))
+            .plus(func("exp", X.div(y.clone())))
+            .minus(func("sqrt", z.clone().pow(num(2)).plus(X)))
+            .pow(num(2));
+        let point = HashMap::from([('x', 0.8), ('y', -1.5), ('z', 2.2), ('w', 4.0)]);
+        let (value, partials) = e.value_and_gradient(&point).unwrap();
+        assert_eq!(value, e.solve_for(&point).unwrap());
+        let forward = e.gradient_at(&point).unwrap();
+        assert_eq!(partials.len(), 4);
+        for (var, partial) in partials {
+            assert!(close(partial, forward[&var]), "d/d{}", var);
+        }
+    }
+
+    #[test]
+    fn gradients_of_many_variables() {
+        // sum of (v - k)^2 over 40 variables has partials 2(v - k)
+        let names: Vec<char> = ('a'..='z').chain('A'..='N').collect();
+        let loss = names
+            .iter()
+            .enumerate()
+            .map(|(k, &v)| var(v).minus(num(k as u32)).pow(num(2)))
+            .reduce(Expr::plus)
+            .unwrap();
+        let point: HashMap<char, f64> = names.iter().map(|&v| (v, 0.5)).collect();
+        let (_, partials) = loss.value_and_gradient(&point).unwrap();
+        for (k, v) in names.iter().enumerate() {
+            assert!(close(partials[v], 2.0 * (0.5 - k as f64)));
+        }
+    }
+}
@@ -0,0 +1,907 @@
+use crate::expr::domain::{self, EvalError};
+use crate::expr::natural::Natural;
+use crate::expr::rational::Rational;
+use crate::expr::Expr;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+/// Extra bits the series of [`BigFloat::exp`], [`BigFloat::ln`], [`BigFloat::sin`] and
+/// [`BigFloat::cos`] are summed with, so that their results are accurate to the last bit or
+/// so of the requested precision.
+const GUARD_BITS: u64 = 32;
+
+/// Times [`BigFloat::exp`] halves its reduced argument before summing the series, squaring
+/// the result as often afterwards.
+const EXP_HALVINGS: u64 = 12;
+
+/// Times [`evaluate_to_digits`] doubles its working precision before giving up on the
+/// digits settling.
+const MAX_DOUBLINGS: u32 = 4;
+
+/// A binary floating point number with a mantissa of arbitrary, chosen precision:
+/// `±mantissa * 2^exponent`, where the mantissa has at most `precision` bits.
+///
+/// Addition, subtraction, multiplication, division and square roots are correctly rounded
+/// to the nearest number of the precision of the more precise operand, ties to even. The
+/// other functions are computed from series with extra precision, so are accurate to about
+/// the last bit but not always correctly rounded.
+#[derive(Debug, Clone)]
+pub struct BigFloat {
+    negative: bool,
+    mantissa: Natural,
+    exponent: i64,
+    precision: u64,
+}
+
+/// Number of bits holding `digits` significant decimal digits.
+pub fn bits_for_digits(digits: u32) -> u64 {
+    (f64::from(digits) * std::f64::consts::LOG2_10).ceil() as u64
+}
+
+/// The working precision [`evaluate_to_digits`] starts with: a few bits more than the
+/// digits hold, so they come out right after rounding.
+fn initial_precision(digits: u32) -> u64 {
+    bits_for_digits(digits) + 16
+}
+
+/// The most bits [`evaluate_to_digits`] works with, so the precision values of variables
+/// need to not limit its accuracy.
+pub fn max_precision(digits: u32) -> u64 {
+    initial_precision(digits) << MAX_DOUBLINGS
+}
+
+impl BigFloat {
+    pub fn zero(precision: u64) -> Self {
+        BigFloat {
+            negative: false,
+            mantissa: Natural::zero(),
+            exponent: 0,
+            precision,
+        }
+    }
+
+    pub fn from_integer(n: i64, precision: u64) -> Self {
+        Self::round(n < 0, Natural::from(n.unsigned_abs()), 0, false, precision)
+    }
+
+    /// The exact value of `x`, rounded to `precision` if that has fewer bits than `f64`.
+    pub fn from_f64(x: f64, precision: u64) -> Result<Self, String> {
+        if !x.is_finite() {
+            return Err(format!("{} has no arbitrary-precision value", x));
+        }
+        let bits = x.to_bits();
+        let biased = ((bits >> 52) & 0x7ff) as i64;
+        let fraction = bits & ((1 << 52) - 1);
+        let (mantissa, exponent) = match biased {
+            0 => (fraction, -1074),
+            _ => (fraction | 1 << 52, biased - 1075),
+        };
+        Ok(Self::round(
+            x < 0.0,
+            Natural::from(mantissa),
+            exponent,
+            false,
+            precision,
+        ))
+    }
+
+    /// The correctly rounded value of a fraction.
+    pub fn from_rational(r: Rational, precision: u64) -> Self {
+        let natural = |n: i128| {
+            let n = n.unsigned_abs();
+            Natural::from((n >> 64) as u64)
+                .shl(64)
+                .add(&Natural::from(n as u64))
+        };
+        Self::quotient(
+            r.is_negative(),
+            &natural(r.numer()),
+            &natural(r.denom()),
+            0,
+            precision,
+        )
+    }
+
+    /// The correctly rounded value of a decimal like `-12.5e-3`.
+    pub fn parse(s: &str, precision: u64) -> Result<Self, String> {
+        let invalid = || format!("invalid decimal number [{}]", s);
+        let (negative, unsigned) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (digits_part, exponent) = match unsigned.split_once(['e', 'E']) {
+            Some((digits, exponent)) => (digits, exponent.parse::<i64>().map_err(|_| invalid())?),
+            None => (unsigned, 0),
+        };
+        let (whole, fraction) = digits_part.split_once('.').unwrap_or((digits_part, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return Err(invalid());
+        }
+        let mut digits = Natural::zero();
+        for c in whole.chars().chain(fraction.chars()) {
+            let digit = c.to_digit(10).ok_or_else(invalid)?;
+            digits = digits.mul_small(10).add(&Natural::from(u64::from(digit)));
+        }
+        let scale = exponent - fraction.len() as i64;
+        let power = |n: i64| {
+            u32::try_from(n)
+                .map(Natural::pow10)
+                .map_err(|_| format!("exponent of [{}] is too large", s))
+        };
+        Ok(if scale >= 0 {
+            Self::round(negative, digits.mul(&power(scale)?), 0, false, precision)
+        } else {
+            Self::quotient(negative, &digits, &power(-scale)?, 0, precision)
+        })
+    }
+
+    pub fn precision(&self) -> u64 {
+        self.precision
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa.is_zero()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// Rounds `±mantissa * 2^exponent` to `precision` bits, to nearest with ties to even.
+    /// `sticky` says the exact value is slightly larger in magnitude than that, in which
+    /// case the mantissa must have at least two bits more than `precision`.
+    fn round(
+        negative: bool,
+        mantissa: Natural,
+        exponent: i64,
+        sticky: bool,
+        precision: u64,
+    ) -> Self {
+        if mantissa.is_zero() {
+            return BigFloat::zero(precision);
+        }
+        let (mut mantissa, mut exponent) = (mantissa, exponent);
+        let bits = mantissa.bits();
+        if bits > precision {
+            let dropped = bits - precision;
+            let half = mantissa.bit(dropped - 1);
+            let rest = sticky || mantissa.low_bits_nonzero(dropped - 1);
+            let mut kept = mantissa.shr(dropped);
+            if half && (rest || kept.bit(0)) {
+                kept = kept.add(&Natural::from(1));
+            }
+            mantissa = kept;
+            exponent += dropped as i64;
+        }
+        // canonical form, without trailing zero bits
+        let zeros = mantissa.trailing_zeros();
+        BigFloat {
+            negative,
+            mantissa: mantissa.shr(zeros),
+            exponent: exponent + zeros as i64,
+            precision,
+        }
+    }
+
+    /// The correctly rounded value of `±dividend / divisor * 2^exponent`.
+    fn quotient(
+        negative: bool,
+        dividend: &Natural,
+        divisor: &Natural,
+        exponent: i64,
+        precision: u64,
+    ) -> Self {
+        // enough quotient bits to round, the remainder tells whether it's exact
+        let shift = (precision + 2 + divisor.bits()).saturating_sub(dividend.bits());
+        let (quotient, remainder) = dividend.shl(shift).div_rem(divisor);
+        Self::round(
+            negative,
+            quotient,
+            exponent - shift as i64,
+            !remainder.is_zero(),
+            precision,
+        )
+    }
+
+    pub fn with_precision(&self, precision: u64) -> Self {
+        Self::round(
+            self.negative,
+            self.mantissa.clone(),
+            self.exponent,
+            false,
+            precision,
+        )
+    }
+
+    /// Multiplies by `2^n`, which is exact.
+    fn scaled(&self, n: i64) -> Self {
+        let mut result = self.clone();
+        if !result.is_zero() {
+            result.exponent += n;
+        }
+        result
+    }
+
+    /// One more than the position of the highest set bit: the value is below `2^magnitude`
+    /// and at least half that.
+    fn magnitude(&self) -> i64 {
+        if self.is_zero() {
+            i64::MIN / 2
+        } else {
+            self.exponent + self.mantissa.bits() as i64
+        }
+    }
+
+    pub fn neg(&self) -> Self {
+        let mut result = self.clone();
+        result.negative = !result.negative && !result.is_zero();
+        result
+    }
+
+    pub fn abs(&self) -> Self {
+        let mut result = self.clone();
+        result.negative = false;
+        result
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        if self.is_zero() {
+            return 0.0;
+        }
+        let shift = self.mantissa.bits().saturating_sub(64);
+        let top = self.mantissa.shr(shift).low_u64() as f64;
+        let exponent = (self.exponent + shift as i64).clamp(-2000, 2000) as i32;
+        // in two steps, as 2^exponent alone may not be representable
+        let value = top * 2f64.powi(exponent / 2) * 2f64.powi(exponent - exponent / 2);
+        if self.negative {
+            -value
+        } else {
+            value
+        }
+    }
+
+    fn add_signed(&self, that: &BigFloat, negate_that: bool) -> Self {
+        let precision = self.precision.max(that.precision);
+        let that_negative = that.negative != negate_that;
+        if that.is_zero() {
+            return self.with_precision(precision);
+        }
+        if self.is_zero() {
+            let mut result = that.with_precision(precision);
+            result.negative = that_negative;
+            return result;
+        }
+        let ((big, big_negative), (small, small_negative)) = if self.magnitude() >= that.magnitude()
+        {
+            ((self, self.negative), (that, that_negative))
+        } else {
+            ((that, that_negative), (self, self.negative))
+        };
+        // an operand far below the last bit of the result only decides the rounding, like
+        // any other value below a quarter of that bit would, so it's replaced by one
+        let lowest = big.exponent.min(big.magnitude() - precision as i64);
+        let (small_mantissa, small_exponent) = if small.magnitude() < lowest - 2 {
+            (Natural::from(1), lowest - 3)
+        } else {
+            (small.mantissa.clone(), small.exponent)
+        };
+        let exponent = big.exponent.min(small_exponent);
+        let a = big.mantissa.shl((big.exponent - exponent) as u64);
+        let b = small_mantissa.shl((small_exponent - exponent) as u64);
+        let (negative, mantissa) = if big_negative == small_negative {
+            (big_negative, a.add(&b))
+        } else if a >= b {
+            (big_negative, a.sub(&b))
+        } else {
+            (small_negative, b.sub(&a))
+        };
+        Self::round(negative, mantissa, exponent, false, precision)
+    }
+
+    pub fn add(&self, that: &BigFloat) -> Self {
+        self.add_signed(that, false)
+    }
+
+    pub fn sub(&self, that: &BigFloat) -> Self {
+        self.add_signed(that, true)
+    }
+
+    pub fn mul(&self, that: &BigFloat) -> Self {
+        Self::round(
+            self.negative != that.negative,
+            self.mantissa.mul(&that.mantissa),
+            self.exponent + that.exponent,
+            false,
+            self.precision.max(that.precision),
+        )
+    }
+
+    pub fn div(&self, divisor: &BigFloat) -> Result<Self, String> {
+        if divisor.is_zero() {
+            return Err("division by zero".into());
+        }
+        Ok(Self::quotient(
+            self.negative != divisor.negative,
+            &self.mantissa,
+            &divisor.mantissa,
+            self.exponent - divisor.exponent,
+            self.precision.max(divisor.precision),
+        ))
+    }
+
+    /// Correctly rounded square root.
+    pub fn sqrt(&self) -> Result<Self, String> {
+        if self.is_zero() {
+            return Ok(self.clone());
+        }
+        if self.negative {
+            return Err(format!("square root of negative number {}", self));
+        }
+        // a mantissa with twice the bits of the root and an even exponent to halve
+        let mut shift = (2 * (self.precision + 2)).saturating_sub(self.mantissa.bits());
+        if (self.exponent - shift as i64) % 2 != 0 {
+            shift += 1;
+        }
+        let scaled = self.mantissa.shl(shift);
+        let root = scaled.isqrt();
+        let exact = root.mul(&root) == scaled;
+        Ok(Self::round(
+            false,
+            root,
+            (self.exponent - shift as i64) / 2,
+            !exact,
+            self.precision,
+        ))
+    }
+
+    /// Whether the value is an integer, which it is for any exponent that isn't negative
+    /// as there are no trailing zero bits.
+    fn is_integer(&self) -> bool {
+        self.exponent >= 0 || self.is_zero()
+    }
+
+    pub fn pow(&self, exponent: &BigFloat) -> Result<Self, String> {
+        let precision = self.precision.max(exponent.precision);
+        if exponent.is_integer() && exponent.magnitude() < 31 {
+            let n = exponent.to_f64() as i64;
+            let working = precision + 2 * 64 + GUARD_BITS;
+            let mut result = BigFloat::from_integer(1, working);
+            let mut base = self.with_precision(working);
+            let mut k = n.unsigned_abs();
+            while k > 0 {
+                if k % 2 == 1 {
+                    result = result.mul(&base);
+                }
+                base = base.mul(&base);
+                k /= 2;
+            }
+            if n < 0 {
+                result = BigFloat::from_integer(1, working).div(&result)?;
+            }
+            return Ok(result.with_precision(precision));
+        }
+        if self.is_zero() {
+            return match exponent.negative {
+                false => Ok(BigFloat::zero(precision)),
+                true => Err("zero to a negative power".into()),
+            };
+        }
+        if self.negative {
+            return Err(format!(
+                "non-integer power {} of negative number {}",
+                exponent, self
+            ));
+        }
+        let working = precision + GUARD_BITS;
+        let log = self.with_precision(working).ln()?;
+        Ok(log
+            .mul(&exponent.with_precision(working))
+            .exp()?
+            .with_precision(precision))
+    }
+
+    /// Sums `first + first * ratio(1) + first * ratio(1) * ratio(2) + ...` until the terms
+    /// no longer change the sum at `precision`.
+    fn series(
+        first: BigFloat,
+        precision: u64,
+        ratio: impl Fn(u64, &BigFloat) -> Result<BigFloat, String>,
+    ) -> Result<BigFloat, String> {
+        let mut sum = first.clone();
+        let mut term = first;
+        for k in 1.. {
+            term = ratio(k, &term)?;
+            if term.is_zero() || term.magnitude() < sum.magnitude() - precision as i64 - 2 {
+                break;
+            }
+            sum = sum.add(&term);
+        }
+        Ok(sum)
+    }
+
+    /// `ln 2 = 2 atanh(1/3)`.
+    pub fn ln2(precision: u64) -> Self {
+        let working = precision + GUARD_BITS;
+        let third = BigFloat::from_rational(Rational::new(1, 3), working);
+        let sum = Self::atanh_series(&third, working).expect("1/3 is in range");
+        sum.scaled(1).with_precision(precision)
+    }
+
+    /// `atanh(z) = z + z^3/3 + z^5/5 + ...` for small `z`.
+    fn atanh_series(z: &BigFloat, precision: u64) -> Result<BigFloat, String> {
+        let square = z.mul(z);
+        let mut power = z.clone();
+        let mut sum = z.clone();
+        for k in 1u64.. {
+            power = power.mul(&square);
+            let term = power.div(&BigFloat::from_integer(2 * k as i64 + 1, precision))?;
+            if term.is_zero() || term.magnitude() < sum.magnitude() - precision as i64 - 2 {
+                break;
+            }
+            sum = sum.add(&term);
+        }
+        Ok(sum)
+    }
+
+    /// `atan(1/n)`, as an alternating series.
+    fn atan_inverse(n: i64, precision: u64) -> BigFloat {
+        let x = BigFloat::from_rational(Rational::new(1, i128::from(n)), precision);
+        let square = x.mul(&x);
+        let mut power = x.clone();
+        let mut sum = x;
+        for k in 1.. {
+            power = power.mul(&square);
+            let term = power
+                .div(&BigFloat::from_integer(2 * k + 1, precision))
+                .expect("nonzero divisor");
+            if term.magnitude() < sum.magnitude() - precision as i64 - 2 {
+                break;
+            }
+            sum = if k % 2 == 1 {
+                sum.sub(&term)
+            } else {
+                sum.add(&term)
+            };
+        }
+        sum
+    }
+
+    /// Machin's formula `pi = 16 atan(1/5) - 4 atan(1/239)`.
+    pub fn pi(precision: u64) -> Self {
+        let working = precision + GUARD_BITS;
+        let fifth = Self::atan_inverse(5, working).scaled(4);
+        let other = Self::atan_inverse(239, working).scaled(2);
+        fifth.sub(&other).with_precision(precision)
+    }
+
+    pub fn exp(&self) -> Result<Self, String> {
+        let precision = self.precision;
+        let approx = self.to_f64();
+        if approx.abs() > 1e15 {
+            return Err(format!("exp({}) is out of range", self));
+        }
+        // x = n ln 2 + r with |r| <= ln 2 / 2, so exp(x) = 2^n exp(r)
+        let n = (approx / std::f64::consts::LN_2).round() as i64;
+        let working = precision + GUARD_BITS + EXP_HALVINGS + 64;
+        let reduced = self
+            .with_precision(working)
+            .sub(&Self::ln2(working).mul(&BigFloat::from_integer(n, working)))
+            .scaled(-(EXP_HALVINGS as i64));
+        let one = BigFloat::from_integer(1, working);
+        let mut sum = Self::series(one, working, |k, term| {
+            term.mul(&reduced)
+                .div(&BigFloat::from_integer(k as i64, working))
+        })?;
+        for _ in 0..EXP_HALVINGS {
+            sum = sum.mul(&sum);
+        }
+        Ok(sum.scaled(n).with_precision(precision))
+    }
+
+    pub fn ln(&self) -> Result<Self, String> {
+        if self.is_zero() || self.negative {
+            return Err(format!("logarithm of nonpositive number {}", self));
+        }
+        let precision = self.precision;
+        let working = precision + GUARD_BITS;
+        // x = 2^n y with y in [1/2, 1), but without splitting off a power of two near 1
+        // where ln x is small and would cancel out of n ln 2 + ln y
+        let n = match self.magnitude() {
+            0 | 1 => 0,
+            magnitude => magnitude,
+        };
+        let y = self.with_precision(working).scaled(-n);
+        let one = BigFloat::from_integer(1, working);
+        // ln y = 2 atanh((y - 1) / (y + 1))
+        let z = y.sub(&one).div(&y.add(&one))?;
+        let log = Self::atanh_series(&z, working)?.scaled(1);
+        let power = Self::ln2(working).mul(&BigFloat::from_integer(n, working));
+        Ok(power.add(&log).with_precision(precision))
+    }
+
+    /// `x - k 2pi` in `[-pi, pi]`, with enough precision that the subtraction doesn't lose
+    /// any of `working`.
+    fn reduced_angle(&self, working: u64) -> Result<BigFloat, String> {
+        let approx = self.to_f64();
+        if approx.abs() > 1e15 {
+            return Err(format!(
+                "trigonometric function of {} is out of range",
+                self
+            ));
+        }
+        let k = (approx / std::f64::consts::TAU).round() as i64;
+        let wide = working + 64;
+        let tau = Self::pi(wide).scaled(1);
+        Ok(self
+            .with_precision(wide)
+            .sub(&tau.mul(&BigFloat::from_integer(k, wide)))
+            .with_precision(working))
+    }
+
+    pub fn sin(&self) -> Result<Self, String> {
+        let working = self.precision + GUARD_BITS;
+        let x = self.reduced_angle(working)?;
+        let square = x.mul(&x).neg();
+        let sum = Self::series(x, working, |k, term| {
+            term.mul(&square).div(&BigFloat::from_integer(
+                (2 * k * (2 * k + 1)) as i64,
+                working,
+            ))
+        })?;
+        Ok(sum.with_precision(self.precision))
+    }
+
+    pub fn cos(&self) -> Result<Self, String> {
+        let working = self.precision + GUARD_BITS;
+        let x = self.reduced_angle(working)?;
+        let square = x.mul(&x).neg();
+        let one = BigFloat::from_integer(1, working);
+        let sum = Self::series(one, working, |k, term| {
+            term.mul(&square).div(&BigFloat::from_integer(
+                ((2 * k - 1) * 2 * k) as i64,
+                working,
+            ))
+        })?;
+        Ok(sum.with_precision(self.precision))
+    }
+
+    pub fn tan(&self) -> Result<Self, String> {
+        let working = self.with_precision(self.precision + GUARD_BITS);
+        Ok(working
+            .sin()?
+            .div(&working.cos()?)?
+            .with_precision(self.precision))
+    }
+
+    /// The value rounded to `digits` significant decimal digits, in scientific notation if
+    /// it is very large or small, without trailing zeros.
+    pub fn to_decimal(&self, digits: u32) -> String {
+        if self.is_zero() {
+            return "0".into();
+        }
+        let digits = digits.max(1);
+        // estimate of the decimal exponent, corrected below if it's off by one
+        let mut exponent =
+            ((self.magnitude() - 1) as f64 * std::f64::consts::LOG10_2).floor() as i64;
+        loop {
+            let scale = i64::from(digits) - 1 - exponent;
+            let (mut numerator, mut denominator) = (self.mantissa.clone(), Natural::from(1));
+            if self.exponent >= 0 {
+                numerator = numerator.shl(self.exponent as u64);
+            } else {
+                denominator = denominator.shl(self.exponent.unsigned_abs());
+            }
+            if scale >= 0 {
+                numerator = numerator.mul(&Natural::pow10(scale as u32));
+            } else {
+                denominator = denominator.mul(&Natural::pow10(scale.unsigned_abs() as u32));
+            }
+            let (mut quotient, remainder) = numerator.div_rem(&denominator);
+            let twice = remainder.shl(1);
+            if twice > denominator || (twice == denominator && quotient.bit(0)) {
+                quotient = quotient.add(&Natural::from(1));
+            }
+            let text = quotient.to_decimal();
+            match text.len().cmp(&(digits as usize)) {
+                Ordering::Greater => exponent += 1,
+                Ordering::Less => exponent -= 1,
+                Ordering::Equal => return self.format_decimal(&text, exponent),
+            }
+        }
+    }
+
+    fn format_decimal(&self, digits: &str, exponent: i64) -> String {
+        let sign = if self.negative { "-" } else { "" };
+        let trimmed = |s: String| {
+            if s.contains('.') {
+                s.trim_end_matches('0').trim_end_matches('.').to_string()
+            } else {
+                s
+            }
+        };
+        let n = digits.len() as i64;
+        if (0..n).contains(&exponent) {
+            let (whole, fraction) = digits.split_at(exponent as usize + 1);
+            format!("{}{}", sign, trimmed(format!("{}.{}", whole, fraction)))
+        } else if (-5..0).contains(&exponent) {
+            let zeros = "0".repeat((-exponent - 1) as usize);
+            format!("{}{}", sign, trimmed(format!("0.{}{}", zeros, digits)))
+        } else {
+            let mantissa = trimmed(format!("{}.{}", &digits[..1], &digits[1..]));
+            format!("{}{}e{}", sign, mantissa, exponent)
+        }
+    }
+}
+
+impl PartialEq for BigFloat {
+    /// Compares values, whatever their precision.
+    fn eq(&self, other: &Self) -> bool {
+        self.negative == other.negative
+            && self.mantissa == other.mantissa
+            && self.exponent == other.exponent
+    }
+}
+
+impl Display for BigFloat {
+    /// All the decimal digits the precision holds.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let digits = (self.precision as f64 * std::f64::consts::LOG10_2).floor() as u32;
+        write!(f, "{}", self.to_decimal(digits))
+    }
+}
+
+/// `x` as an `f64` for [`domain::check`], keeping the sign of values too small for one.
+fn approximate(x: &BigFloat) -> f64 {
+    let value = x.to_f64();
+    if value != 0.0 || x.is_zero() {
+        value
+    } else if x.is_negative() {
+        -f64::MIN_POSITIVE
+    } else {
+        f64::MIN_POSITIVE
+    }
+}
+
+/// [`Expr::solve_for`] with `precision` bits. Numeric subexpressions like `1/3` are
+/// evaluated exactly and rounded once, values of variables are rounded to `precision`.
+/// Rounding errors add up and cancel like they do in floating point, so fewer of the bits
+/// than `precision` may be right; see [`evaluate_to_digits`]. Operations outside their
+/// domain fail with the same [`EvalError::Domain`] as in [`Expr::solve_for`].
+pub fn evaluate(
+    e: &Expr,
+    vars: &HashMap<char, BigFloat>,
+    precision: u64,
+) -> Result<BigFloat, EvalError> {
+    if let Some(r) = Rational::from_expr(e) {
+        return Ok(BigFloat::from_rational(r, precision));
+    }
+    let eval = |e: &Expr| evaluate(e, vars, precision);
+    // the same domain errors as solve_for, rather than those of the BigFloat operations
+    let check = |args: &[&BigFloat]| {
+        let values: Vec<f64> = args.iter().map(|x| approximate(x)).collect();
+        domain::check(e, &values)
+    };
+    match e {
+        Expr::Var(name) => vars
+            .get(name)
+            .map(|value| value.with_precision(precision))
+            .ok_or(EvalError::UnboundVariable(*name)),
+        Expr::E => Ok(BigFloat::from_integer(1, precision).exp()?),
+        Expr::Pi => Ok(BigFloat::pi(precision)),
+        Expr::Tau => Ok(BigFloat::pi(precision).scaled(1)),
+        Expr::Phi => {
+            let root = BigFloat::from_integer(5, precision + 2).sqrt()?;
+            Ok(root
+                .add(&BigFloat::from_integer(1, precision + 2))
+                .scaled(-1)
+                .with_precision(precision))
+        }
+        Expr::I => Err("imaginary unit [i] has no real value".to_string().into()),
+        Expr::Inf => Err("infinity has no arbitrary-precision value"
+            .to_string()
+            .into()),
+        Expr::Const(n) => Ok(BigFloat::from_integer(i64::from(*n), precision)),
+        Expr::Add(lhs, rhs) => Ok(eval(lhs)?.add(&eval(rhs)?)),
+        Expr::Sub(lhs, rhs) => Ok(eval(lhs)?.sub(&eval(rhs)?)),
+        Expr::Mul(lhs, rhs) => Ok(eval(lhs)?.mul(&eval(rhs)?)),
+        Expr::Div(lhs, rhs) => {
+            let (dividend, divisor) = (eval(lhs)?, eval(rhs)?);
+            check(&[&dividend, &divisor])?;
+            Ok(dividend.div(&divisor)?)
+        }
+        Expr::Pow(lhs, rhs) => {
+            let (base, exponent) = (eval(lhs)?, eval(rhs)?);
+            check(&[&base, &exponent])?;
+            match domain::odd_root(rhs) {
+                Some(r) if base.is_negative() => {
+                    let root = base.neg().pow(&exponent)?;
+                    Ok(if r.numer() % 2 == 0 { root } else { root.neg() })
+                }
+                _ => Ok(base.pow(&exponent)?),
+            }
+        }
+        Expr::Neg(inner) => Ok(eval(inner)?.neg()),
+        Expr::Func(name, arg) => {
+            let x = eval(arg)?;
+            check(&[&x])?;
+            let value = match name.as_str() {
+                "sin" => x.sin(),
+                "cos" => x.cos(),
+                "tan" => x.tan(),
+                "cot" => x
+                    .tan()
+                    .and_then(|tan| BigFloat::from_integer(1, precision).div(&tan)),
+                "ln" => x.ln(),
+                "exp" => x.exp(),
+                "sqrt" => x.sqrt(),
+                _ => Err(format!("Unrecognized function [{}({:?})]", name, arg)),
+            };
+            Ok(value?)
+        }
+    }
+}
+
+/// [`evaluate`] to `digits` significant decimal digits, doubling the working precision until
+/// two evaluations in a row round to the same digits, which makes up for cancellation like
+/// in `exp(10^-30) - 1`. A value that is zero at the highest precision tried is taken to be
+/// zero; one whose digits haven't settled by then, like `sin(pi)` which is zero but never
+/// exactly at any precision, is an error.
+pub fn evaluate_to_digits(
+    e: &Expr,
+    vars: &HashMap<char, BigFloat>,
+    digits: u32,
+) -> Result<BigFloat, EvalError> {
+    let mut precision = initial_precision(digits);
+    let mut previous = evaluate(e, vars, precision);
+    for _ in 0..MAX_DOUBLINGS {
+        precision *= 2;
+        let value = evaluate(e, vars, precision);
+        if let (Ok(a), Ok(b)) = (&previous, &value) {
+            if !b.is_zero() && a.to_decimal(digits) == b.to_decimal(digits) {
+                return value;
+            }
+        }
+        previous = value;
+    }
+    match previous? {
+        zero if zero.is_zero() => Ok(zero),
+        _ => Err(format!(
+            "can't evaluate expression to {} digits, it may be zero",
+            digits
+        )
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::syntax::*;
+
+    const PI_50: &str = "3.1415926535897932384626433832795028841971693993751";
+
+    fn digits(e: Expr, n: u32) -> String {
+        e.solve_precise(&HashMap::new(), n).unwrap().to_decimal(n)
+    }
+
+    #[test]
+    fn basic_operations_round_like_f64() {
+        // correctly rounded operations at 53 bits agree with IEEE arithmetic
+        let values = [0.1, 0.2, 1.0 / 3.0, -7.25, 1e10, 3.0e-8, 123456.789, -0.7];
+        let big = |x: f64| BigFloat::from_f64(x, 53).unwrap();
+        for a in values {
+            for b in values {
+                assert_eq!(big(a).add(&big(b)).to_f64(), a + b, "{} + {}", a, b);
+                assert_eq!(big(a).sub(&big(b)).to_f64(), a - b, "{} - {}", a, b);
+                assert_eq!(big(a).mul(&big(b)).to_f64(), a * b, "{} * {}", a, b);
+                assert_eq!(
+                    big(a).div(&big(b)).unwrap().to_f64(),
+                    a / b,
+                    "{} / {}",
+                    a,
+                    b
+                );
+            }
+            if a > 0.0 {
+                assert_eq!(big(a).sqrt().unwrap().to_f64(), a.sqrt());
+            }
+        }
+    }
+
+    #[test]
+    fn constants_to_many_digits() {
+        assert_eq!(digits(PI, 50), PI_50);
+        assert_eq!(digits(E, 40), "2.718281828459045235360287471352662497757");
+        assert_eq!(
+            digits(func("sqrt", num(2)), 40),
+            "1.41421356237309504880168872420969807857"
+        );
+        assert_eq!(
+            digits(func("ln", num(2)), 40),
+            "0.6931471805599453094172321214581765680755"
+        );
+        assert_eq!(digits(num(1).div(num(3)), 20), "0.33333333333333333333");
+        assert_eq!(
+            digits(num(2).pow(num(100)), 31),
+            "1267650600228229401496703205376"
+        );
+    }
+
+    #[test]
+    fn transcendental_functions() {
+        assert_eq!(digits(sin(num(1)), 30), "0.84147098480789650665250232163");
+        assert_eq!(
+            digits(cos(num(10)), 30),
+            "-0.839071529076452452258863947824"
+        );
+        assert_eq!(
+            digits(func("exp", num(1).div(num(10)).neg()), 30),
+            "0.904837418035959573164249059446"
+        );
+        // sin(pi) is zero to the working precision
+        let tiny = PI
+            .solve_precise(&HashMap::new(), 40)
+            .unwrap()
+            .sin()
+            .unwrap();
+        assert!(tiny.to_f64().abs() < 1e-40);
+        // x^(1/3) through exp and ln
+        let at = HashMap::from([('x', BigFloat::parse("27", 200).unwrap())]);
+        let root = X.pow(num(1).div(num(3))).solve_precise(&at, 50).unwrap();
+        assert_eq!(root.to_decimal(50), "3");
+        // odd roots of negative numbers are real, like with solve_for
+        let at = HashMap::from([('x', BigFloat::from_integer(-27, 60))]);
+        let root = X.pow(num(1).div(num(3))).solve_precise(&at, 20).unwrap();
+        assert_eq!(root.to_decimal(20), "-3");
+    }
+
+    #[test]
+    fn precision_grows_until_the_digits_settle() {
+        let tiny = |n: u32| num(10).pow(num(n).neg());
+        assert_eq!(digits(func("exp", tiny(30)).minus(num(1)), 20), "1e-30");
+        assert_eq!(digits(num(1).minus(cos(tiny(20))), 20), "5e-41");
+        assert!(sin(PI).solve_precise(&HashMap::new(), 20).is_err());
+        let at = HashMap::from([('x', BigFloat::from_integer(2, 60))]);
+        let zero = X.minus(X).solve_precise(&at, 20).unwrap();
+        assert!(zero.is_zero());
+    }
+
+    #[test]
+    fn domain_errors_match_solve_for() {
+        let tiny = num(10).pow(num(400).neg());
+        let undefined = [
+            func("ln", num(0)),
+            num(1).div(num(0)),
+            func("sqrt", num(2).neg()),
+            num(0).pow(num(1).neg()),
+            num(1).div(tiny.clone().minus(tiny.clone())),
+        ];
+        for e in undefined {
+            let precise = e.solve_precise(&HashMap::new(), 10).unwrap_err();
+            assert!(matches!(precise, EvalError::Domain(_)));
+            assert_eq!(Err(precise), e.solve_for(&HashMap::new()));
+        }
+        // too small for an f64, but not zero
+        assert!(num(1).div(tiny).solve_precise(&HashMap::new(), 10).is_ok());
+    }
+
+    #[test]
+    fn decimals() {
+        let tenth = BigFloat::parse("0.1", 53).unwrap();
+        assert_eq!(tenth.to_f64(), 0.1);
+        assert_eq!(
+            BigFloat::parse("-1.25e3", 20).unwrap().to_decimal(10),
+            "-1250"
+        );
+        assert_eq!(
+            BigFloat::parse("6.02214076e23", 100).unwrap().to_decimal(9),
+            "6.02214076e23"
+        );
+        assert_eq!(
+            BigFloat::parse("0.000123", 60).unwrap().to_decimal(3),
+            "0.000123"
+        );
+        assert!(BigFloat::parse("1.2.3", 60).is_err());
+    }
+}
@@ -0,0 +1,420 @@
+use crate::expr::domain;
+use crate::expr::rational::Rational;
+use crate::expr::Expr;
+use std::collections::HashMap;
+use std::thread;
+
+/// Points [`Compiled::eval_batch`] evaluates per pass over the bytecode.
+const BLOCK: usize = 256;
+
+/// One step of a [`Compiled`] expression, operating on a stack of values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instr {
+    Const(f64),
+    /// Pushes the value in the given slot.
+    Load(usize),
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    /// Raises to a constant integer power, cheaper than [`Instr::Pow`].
+    PowI(i32),
+    /// Raises to a constant fraction with an odd denominator, which is real for negative
+    /// bases too.
+    OddRoot(Rational),
+    Neg,
+    Sin,
+    Cos,
+    Tan,
+    Cot,
+    Ln,
+    Exp,
+    Sqrt,
+}
+
+/// An expression compiled to flat stack-machine bytecode, with its variables resolved to
+/// slots so evaluating it takes a slice of values instead of a map. Subexpressions without
+/// variables are folded while compiling. Meant for evaluating the same expression many
+/// times, e.g. in ODE or plotting loops.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Compiled {
+    code: Vec<Instr>,
+    vars: Vec<char>,
+    stack_size: usize,
+}
+
+impl Compiled {
+    /// Compiles `e` with the value of `vars[i]` read from slot `i`. Fails if a variable is
+    /// missing from `vars`, a function is unknown or a subexpression without variables has
+    /// no value. Odd roots of negative numbers are real, as in [`Expr::solve_for`], but
+    /// domains aren't checked when evaluating: where `solve_for` fails, e.g. `ln(x)` at 0,
+    /// the compiled expression gives NaN or an infinity instead.
+    pub fn new(e: &Expr, vars: &[char]) -> Result<Self, String> {
+        let mut compiler = Compiler {
+            slots: vars.iter().enumerate().map(|(i, &v)| (v, i)).collect(),
+            code: Vec::new(),
+            depth: 0,
+            stack_size: 0,
+        };
+        compiler.compile(e)?;
+        Ok(Compiled {
+            code: compiler.code,
+            vars: vars.to_vec(),
+            stack_size: compiler.stack_size,
+        })
+    }
+
+    /// The variables in slot order.
+    pub fn vars(&self) -> &[char] {
+        &self.vars
+    }
+
+    pub fn code(&self) -> &[Instr] {
+        &self.code
+    }
+
+    /// Evaluates with `values[i]` as the value of the variable in slot `i`.
+    ///
+    /// # Panics
+    /// If there are fewer values than slots.
+    pub fn eval(&self, values: &[f64]) -> f64 {
+        let mut stack = Vec::with_capacity(self.stack_size);
+        self.eval_with(values, &mut stack)
+    }
+
+    /// [`Compiled::eval`] reusing `stack` between calls instead of allocating one.
+    pub fn eval_with(&self, values: &[f64], stack: &mut Vec<f64>) -> f64 {
+        assert!(
+            values.len() >= self.vars.len(),
+            "expected {} values, got {}",
+            self.vars.len(),
+            values.len()
+        );
+        stack.clear();
+        for instr in &self.code {
+            let value = match *instr {
+                Instr::Const(c) => c,
+                Instr::Load(slot) => values[slot],
+                Instr::Add | Instr::Sub | Instr::Mul | Instr::Div | Instr::Pow => {
+                    let rhs = stack.pop().unwrap();
+                    let lhs = stack.pop().unwrap();
+                    match instr {
+                        Instr::Add => lhs + rhs,
+                        Instr::Sub => lhs - rhs,
+                        Instr::Mul => lhs * rhs,
+                        Instr::Div => lhs / rhs,
+                        _ => lhs.powf(rhs),
+                    }
+                }
+                unary => {
+                    let arg = stack.pop().unwrap();
+                    match unary {
+                        Instr::PowI(n) => arg.powi(n),
+                        Instr::OddRoot(r) => domain::real_pow(arg, r.to_f64(), Some(r)),
+                        Instr::Neg => -arg,
+                        Instr::Sin => arg.sin(),
+                        Instr::Cos => arg.cos(),
+                        Instr::Tan => arg.tan(),
+                        Instr::Cot => 1.0 / arg.tan(),
+                        Instr::Ln => arg.ln(),
+                        Instr::Exp => arg.exp(),
+                        _ => arg.sqrt(),
+                    }
+                }
+            };
+            stack.push(value);
+        }
+        stack.pop().unwrap()
+    }
+
+    /// Evaluates at many points at once: `columns[i]` holds the values of the variable in
+    /// slot `i` and `out` receives one result per point. Each instruction is applied to a
+    /// block of points at a time, so the cost of dispatching it is shared between them.
+    pub fn eval_batch(&self, columns: &[&[f64]], out: &mut [f64]) -> Result<(), String> {
+        self.check_columns(columns, out.len())?;
+        self.eval_range(columns, 0, out);
+        Ok(())
+    }
+
+    /// [`Compiled::eval_batch`] splitting the points into `threads` chunks evaluated in
+    /// parallel.
+    pub fn eval_batch_parallel(
+        &self,
+        columns: &[&[f64]],
+        out: &mut [f64],
+        threads: usize,
+    ) -> Result<(), String> {
+        self.check_columns(columns, out.len())?;
+        let chunk = out
+            .len()
+            .div_ceil(threads.max(1))
+            .next_multiple_of(BLOCK)
+            .max(BLOCK);
+        thread::scope(|scope| {
+            for (i, part) in out.chunks_mut(chunk).enumerate() {
+                scope.spawn(move || self.eval_range(columns, i * chunk, part));
+            }
+        });
+        Ok(())
+    }
+
+    fn check_columns(&self, columns: &[&[f64]], points: usize) -> Result<(), String> {
+        if columns.len() < self.vars.len() {
+            return Err(format!(
+                "expected {} columns, got {}",
+                self.vars.len(),
+                columns.len()
+            ));
+        }
+        match columns.iter().position(|column| column.len() != points) {
+            Some(i) => Err(format!(
+                "column {} has {} values for {} points",
+                i,
+                columns[i].len(),
+                points
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Evaluates the points from `start` on into `out`, a block at a time.
+    fn eval_range(&self, columns: &[&[f64]], start: usize, out: &mut [f64]) {
+        let mut stack = vec![[0.0; BLOCK]; self.stack_size];
+        for (i, block) in out.chunks_mut(BLOCK).enumerate() {
+            self.eval_block(columns, start + i * BLOCK, block, &mut stack);
+        }
+    }
+
+    fn eval_block(
+        &self,
+        columns: &[&[f64]],
+        start: usize,
+        out: &mut [f64],
+        stack: &mut [[f64; BLOCK]],
+    ) {
+        let n = out.len();
+        let mut top = 0;
+        for instr in &self.code {
+            match *instr {
+                Instr::Const(c) => {
+                    stack[top][..n].fill(c);
+                    top += 1;
+                }
+                Instr::Load(slot) => {
+                    stack[top][..n].copy_from_slice(&columns[slot][start..start + n]);
+                    top += 1;
+                }
+                Instr::Add | Instr::Sub | Instr::Mul | Instr::Div | Instr::Pow => {
+                    top -= 1;
+                    let (below, above) = stack.split_at_mut(top);
+                    let (lhs, rhs) = (&mut below[top - 1][..n], &above[0][..n]);
+                    match instr {
+                        Instr::Add => binary(lhs, rhs, |a, b| a + b),
+                        Instr::Sub => binary(lhs, rhs, |a, b| a - b),
+                        Instr::Mul => binary(lhs, rhs, |a, b| a * b),
+                        Instr::Div => binary(lhs, rhs, |a, b| a / b),
+                        _ => binary(lhs, rhs, f64::powf),
+                    }
+                }
+                unary => {
+                    let arg = &mut stack[top - 1][..n];
+                    match unary {
+                        Instr::PowI(k) => arg.iter_mut().for_each(|a| *a = a.powi(k)),
+                        Instr::OddRoot(r) => {
+                            let exponent = r.to_f64();
+                            let pow = |a: f64| domain::real_pow(a, exponent, Some(r));
+                            arg.iter_mut().for_each(|a| *a = pow(*a))
+                        }
+                        Instr::Neg => arg.iter_mut().for_each(|a| *a = -*a),
+                        Instr::Sin => arg.iter_mut().for_each(|a| *a = a.sin()),
+                        Instr::Cos => arg.iter_mut().for_each(|a| *a = a.cos()),
+                        Instr::Tan => arg.iter_mut().for_each(|a| *a = a.tan()),
+                        Instr::Cot => arg.iter_mut().for_each(|a| *a = 1.0 / a.tan()),
+                        Instr::Ln => arg.iter_mut().for_each(|a| *a = a.ln()),
+                        Instr::Exp => arg.iter_mut().for_each(|a| *a = a.exp()),
+                        _ => arg.iter_mut().for_each(|a| *a = a.sqrt()),
+                    }
+                }
+            }
+        }
+        out.copy_from_slice(&stack[0][..n]);
+    }
+}
+
+fn binary(lhs: &mut [f64], rhs: &[f64], op: impl Fn(f64, f64) -> f64) {
+    for (l, r) in lhs.iter_mut().zip(rhs) {
+        *l = op(*l, *r);
+    }
+}
+
+struct Compiler {
+    slots: HashMap<char, usize>,
+    code: Vec<Instr>,
+    depth: usize,
+    stack_size: usize,
+}
+
+impl Compiler {
+    fn emit(&mut self, instr: Instr, pops: usize) {
+        self.depth = self.depth + 1 - pops;
+        self.stack_size = self.stack_size.max(self.depth);
+        self.code.push(instr);
+    }
+
+    fn compile(&mut self, e: &Expr) -> Result<(), String> {
+        if !has_vars(e) {
+            let value = e.solve_for(&HashMap::new())?;
+            self.emit(Instr::Const(value), 0);
+            return Ok(());
+        }
+        match e {
+            Expr::Var(name) => {
+                let slot = *self
+                    .slots
+                    .get(name)
+                    .ok_or(format!("could not find variable [{}]", name))?;
+                self.emit(Instr::Load(slot), 0);
+            }
+            Expr::Pow(base, exponent) => match (exponent.as_ref(), domain::odd_root(exponent)) {
+                (Expr::Const(n), _) if i32::try_from(*n).is_ok() => {
+                    self.compile(base)?;
+                    self.emit(Instr::PowI(*n as i32), 1);
+                }
+                (_, Some(r)) => {
+                    self.compile(base)?;
+                    self.emit(Instr::OddRoot(r), 1);
+                }
+                _ => self.binary(base, exponent, Instr::Pow)?,
+            },
+            Expr::Add(lhs, rhs) => self.binary(lhs, rhs, Instr::Add)?,
+            Expr::Sub(lhs, rhs) => self.binary(lhs, rhs, Instr::Sub)?,
+            Expr::Mul(lhs, rhs) => self.binary(lhs, rhs, Instr::Mul)?,
+            Expr::Div(lhs, rhs) => self.binary(lhs, rhs, Instr::Div)?,
+            Expr::Neg(inner) => {
+                self.compile(inner)?;
+                self.emit(Instr::Neg, 1);
+            }
+            Expr::Func(name, arg) => {
+                let instr = match name.as_str() {
+                    "sin" => Instr::Sin,
+                    "cos" => Instr::Cos,
+                    "tan" => Instr::Tan,
+                    "cot" => Instr::Cot,
+                    "ln" => Instr::Ln,
+                    "exp" => Instr::Exp,
+                    "sqrt" => Instr::Sqrt,
+                    _ => return Err(format!("Unrecognized function [{}({:?})]", name, arg)),
+                };
+                self.compile(arg)?;
+                self.emit(instr, 1);
+            }
+            _ => unreachable!("constants are folded"),
+        }
+        Ok(())
+    }
+
+    fn binary(&mut self, lhs: &Expr, rhs: &Expr, instr: Instr) -> Result<(), String> {
+        self.compile(lhs)?;
+        self.compile(rhs)?;
+        self.emit(instr, 2);
+        Ok(())
+    }
+}
+
+fn has_vars(e: &Expr) -> bool {
+    match e {
+        Expr::Var(_) => true,
+        Expr::Add(lhs, rhs)
+        | Expr::Sub(lhs, rhs)
+        | Expr::Mul(lhs, rhs)
+        | Expr::Div(lhs, rhs)
+        | Expr::Pow(lhs, rhs) => has_vars(lhs) || has_vars(rhs),
+        Expr::Neg(inner) | Expr::Func(_, inner) => has_vars(inner),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::syntax::*;
+
+    #[test]
+    fn compiled_expressions_match_solve_for() {
+        let y = var('y');
+        let exprs = [
+            X.pow(num(3)).minus(num(2).times(X).times(y.clone())),
+            sin(X).div(cos(y.clone())).plus(func("exp", X.neg())),
+            func("sqrt", X.times(X).plus(y.clone())).pow(PI),
+            func("ln", y.clone()).times(func("cot", X)).minus(TAU),
+            // odd roots are real for negative x
+            X.pow(num(1).div(num(3)))
+                .plus(X.pow(num(2).div(num(3)).neg())),
+        ];
+        for e in exprs {
+            let compiled = e.compile(&['x', 'y']).unwrap();
+            for (x, y) in [(0.5, 2.0), (-1.25, 3.5), (3.0, 0.75)] {
+                let expected = e.solve_for(&[('x', x), ('y', y)].into()).unwrap();
+                assert_eq!(compiled.eval(&[x, y]), expected, "{:?}", e);
+                let mut out = [0.0];
+                compiled.eval_batch(&[&[x], &[y]], &mut out).unwrap();
+                assert_eq!(out[0], expected, "{:?}", e);
+            }
+        }
+    }
+
+    #[test]
+    fn constants_are_folded() {
+        let e = X.times(num(2).times(PI)).plus(num(1).div(num(4)));
+        let compiled = e.compile(&['x']).unwrap();
+        assert_eq!(
+            compiled.code(),
+            &[
+                Instr::Load(0),
+                Instr::Const(std::f64::consts::TAU),
+                Instr::Mul,
+                Instr::Const(0.25),
+                Instr::Add
+            ]
+        );
+        assert_eq!(compiled.eval(&[1.0]), std::f64::consts::TAU + 0.25);
+    }
+
+    #[test]
+    fn batches_match_single_points() {
+        let y = var('y');
+        let e = sin(X).times(y.clone()).plus(X.pow(num(2)).div(y.clone()));
+        let compiled = e.compile(&['x', 'y']).unwrap();
+        // more than one block, and not a multiple of the block size
+        let xs: Vec<f64> = (0..1000).map(|i| f64::from(i) * 0.01).collect();
+        let ys: Vec<f64> = (0..1000).map(|i| 1.0 + f64::from(i % 7)).collect();
+        let expected: Vec<f64> = xs
+            .iter()
+            .zip(&ys)
+            .map(|(&x, &y)| compiled.eval(&[x, y]))
+            .collect();
+
+        let mut out = vec![0.0; xs.len()];
+        compiled.eval_batch(&[&xs, &ys], &mut out).unwrap();
+        assert_eq!(out, expected);
+        let mut parallel = vec![0.0; xs.len()];
+        compiled
+            .eval_batch_parallel(&[&xs, &ys], &mut parallel, 3)
+            .unwrap();
+        assert_eq!(parallel, expected);
+
+        assert!(compiled.eval_batch(&[&xs], &mut out).is_err());
+        assert!(compiled.eval_batch(&[&xs, &ys[1..]], &mut out).is_err());
+    }
+
+    #[test]
+    fn unknown_variables_and_functions_are_errors() {
+        assert_eq!(
+            X.plus(var('z')).compile(&['x']),
+            Err("could not find variable [z]".to_string())
+        );
+        assert!(func("erf", X).compile(&['x']).is_err());
+        assert!(X.times(I).compile(&['x']).is_err());
+    }
+}
@@ -0,0 +1,314 @@
+use crate::expr::assumptions::{Condition, Predicate};
+use crate::expr::domain::EvalError;
+use crate::expr::Expr;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// A complex number. Multivalued functions take their principal branch, with the argument
+/// of a number in `(-pi, pi]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub const I: Complex = Complex { re: 0.0, im: 1.0 };
+
+    pub fn new(re: f64, im: f64) -> Self {
+        Complex { re, im }
+    }
+
+    pub fn real(re: f64) -> Self {
+        Complex { re, im: 0.0 }
+    }
+
+    /// Without negative zeros, which would put e.g. `-8 + 0i` on the other side of the
+    /// branch cut of `ln`.
+    fn unsigned_zeros(re: f64, im: f64) -> Self {
+        Complex::new(re + 0.0, im + 0.0)
+    }
+
+    pub fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    /// The argument in `(-pi, pi]`.
+    pub fn arg(self) -> f64 {
+        self.im.atan2(self.re)
+    }
+
+    pub fn is_real(self) -> bool {
+        self.im == 0.0
+    }
+
+    pub fn exp(self) -> Self {
+        let scale = self.re.exp();
+        Complex::new(scale * self.im.cos(), scale * self.im.sin())
+    }
+
+    /// The principal logarithm, `ln|z| + i arg z`.
+    pub fn ln(self) -> Self {
+        Complex::new(self.abs().ln(), self.arg())
+    }
+
+    /// The principal square root, with a nonnegative real part.
+    pub fn sqrt(self) -> Self {
+        if self.is_real() && self.re >= 0.0 {
+            return Complex::real(self.re.sqrt());
+        }
+        let abs = self.abs();
+        let re = ((abs + self.re) / 2.0).sqrt();
+        let im = ((abs - self.re) / 2.0).sqrt();
+        Complex::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    /// The principal power `exp(w ln z)`, computed by repeated multiplication for integer
+    /// exponents so that e.g. `(-2)^3` stays real.
+    pub fn pow(self, exponent: Complex) -> Self {
+        if exponent.is_real() && exponent.re.fract() == 0.0 && exponent.re.abs() <= 64.0 {
+            return self.powi(exponent.re as i32);
+        }
+        if self == Complex::real(0.0) {
+            return if exponent.re > 0.0 {
+                Complex::real(0.0)
+            } else {
+                Complex::real(f64::NAN)
+            };
+        }
+        if self.is_real() && self.re > 0.0 && exponent.is_real() {
+            return Complex::real(self.re.powf(exponent.re));
+        }
+        (exponent * self.ln()).exp()
+    }
+
+    fn powi(self, n: i32) -> Self {
+        if n < 0 {
+            return Complex::real(1.0) / self.powi(-n);
+        }
+        let (mut result, mut base, mut n) = (Complex::real(1.0), self, n);
+        while n > 0 {
+            if n % 2 == 1 {
+                result = result * base;
+            }
+            base = base * base;
+            n /= 2;
+        }
+        result
+    }
+
+    pub fn sin(self) -> Self {
+        Complex::new(
+            self.re.sin() * self.im.cosh(),
+            self.re.cos() * self.im.sinh(),
+        )
+    }
+
+    pub fn cos(self) -> Self {
+        Complex::new(
+            self.re.cos() * self.im.cosh(),
+            -self.re.sin() * self.im.sinh(),
+        )
+    }
+
+    pub fn tan(self) -> Self {
+        self.sin() / self.cos()
+    }
+
+    pub fn cot(self) -> Self {
+        self.cos() / self.sin()
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, rhs: Complex) -> Complex {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex::unsigned_zeros(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+
+    fn div(self, rhs: Complex) -> Complex {
+        if rhs.is_real() {
+            return Complex::unsigned_zeros(self.re / rhs.re, self.im / rhs.re);
+        }
+        let norm = rhs.re * rhs.re + rhs.im * rhs.im;
+        Complex::unsigned_zeros(
+            (self.re * rhs.re + self.im * rhs.im) / norm,
+            (self.im * rhs.re - self.re * rhs.im) / norm,
+        )
+    }
+}
+
+impl Neg for Complex {
+    type Output = Complex;
+
+    fn neg(self) -> Complex {
+        Complex::unsigned_zeros(-self.re, -self.im)
+    }
+}
+
+/// `im` times `i`, written `i` rather than `1i` for a unit factor.
+fn imaginary(im: f64) -> String {
+    if im == 1.0 {
+        "i".to_string()
+    } else if im == -1.0 {
+        "-i".to_string()
+    } else {
+        format!("{}i", im)
+    }
+}
+
+impl Display for Complex {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (re, im) = (self.re, self.im);
+        if im == 0.0 {
+            write!(f, "{}", re)
+        } else if re == 0.0 {
+            write!(f, "{}", imaginary(im))
+        } else if im < 0.0 {
+            write!(f, "{} - {}", re, imaginary(-im))
+        } else {
+            write!(f, "{} + {}", re, imaginary(im))
+        }
+    }
+}
+
+/// [`Expr::solve_for`] over the complex numbers, where `i` has a value and e.g. `sqrt(-1)`
+/// and `ln(-2)` take their principal values instead of being NaN. Division by zero and
+/// negative powers of zero fail like they do there.
+pub fn solve_complex(e: &Expr, vars: &HashMap<char, Complex>) -> Result<Complex, String> {
+    let eval = |e: &Expr| solve_complex(e, vars);
+    let zero = Complex::real(0.0);
+    let undefined =
+        |expr: &Expr| EvalError::domain(e, Condition::new(expr.clone(), Predicate::Nonzero));
+    match e {
+        Expr::Var(name) => vars
+            .get(name)
+            .copied()
+            .ok_or(format!("could not find variable [{}]", name)),
+        Expr::I => Ok(Complex::I),
+        Expr::Add(lhs, rhs) => Ok(eval(lhs)? + eval(rhs)?),
+        Expr::Sub(lhs, rhs) => Ok(eval(lhs)? - eval(rhs)?),
+        Expr::Mul(lhs, rhs) => Ok(eval(lhs)? * eval(rhs)?),
+        Expr::Div(lhs, rhs) => match eval(rhs)? {
+            divisor if divisor == zero => Err(undefined(rhs).into()),
+            divisor => Ok(eval(lhs)? / divisor),
+        },
+        Expr::Pow(lhs, rhs) => match (eval(lhs)?, eval(rhs)?) {
+            (base, exponent) if base == zero && exponent.re < 0.0 => Err(undefined(lhs).into()),
+            (base, exponent) => Ok(base.pow(exponent)),
+        },
+        Expr::Neg(inner) => Ok(-eval(inner)?),
+        Expr::Func(name, arg) => match name.as_str() {
+            "sin" => Ok(eval(arg)?.sin()),
+            "cos" => Ok(eval(arg)?.cos()),
+            "tan" => Ok(eval(arg)?.tan()),
+            "cot" => Ok(eval(arg)?.cot()),
+            "ln" => Ok(eval(arg)?.ln()),
+            "exp" => Ok(eval(arg)?.exp()),
+            "sqrt" => Ok(eval(arg)?.sqrt()),
+            _ => Err(format!("Unrecognized function [{}({:?})]", name, arg)),
+        },
+        real => Ok(Complex::real(real.solve_for(&HashMap::new())?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::syntax::*;
+
+    fn close(a: Complex, b: Complex) -> bool {
+        (a - b).abs() <= 1e-12 * a.abs().max(1.0)
+    }
+
+    fn value(e: Expr) -> Complex {
+        e.solve_complex(&HashMap::new()).unwrap()
+    }
+
+    #[test]
+    fn principal_branches() {
+        let minus = |n: u32| num(n).neg();
+        // (-8)^(1/3) = 1 + sqrt(3) i
+        let cube_root = value(minus(8).pow(num(1).div(num(3))));
+        assert!(close(cube_root, Complex::new(1.0, 3f64.sqrt())));
+        assert_eq!(value(func("sqrt", minus(1))), Complex::I);
+        let log = value(func("ln", minus(2)));
+        assert!(close(log, Complex::new(2f64.ln(), std::f64::consts::PI)));
+        assert_eq!(value(minus(2).pow(num(3))), Complex::real(-8.0));
+        // the real divisor leaves no negative zero to cross the branch cut with
+        let divided = value(num(8).div(minus(1)).pow(num(1).div(num(3))));
+        assert_eq!(divided, cube_root);
+        assert!(close(
+            value(I.pow(I)),
+            Complex::real((-std::f64::consts::FRAC_PI_2).exp())
+        ));
+    }
+
+    #[test]
+    fn trigonometric_functions_of_imaginary_arguments() {
+        // cos(i) = cosh(1), sin(i) = i sinh(1), and sin^2 + cos^2 = 1 everywhere
+        assert!(close(value(cos(I)), Complex::real(1f64.cosh())));
+        assert!(close(value(sin(I)), Complex::new(0.0, 1f64.sinh())));
+        let z = HashMap::from([('x', Complex::new(0.3, -1.7))]);
+        let identity = sin(X).pow(num(2)).plus(cos(X).pow(num(2)));
+        assert!(close(
+            identity.solve_complex(&z).unwrap(),
+            Complex::real(1.0)
+        ));
+    }
+
+    #[test]
+    fn impedance_of_an_rc_circuit() {
+        // Z = R + 1/(i w C) with R = 2, w = 4, C = 1/8
+        let (r, w, c) = (var('r'), var('w'), var('c'));
+        let z = r.plus(num(1).div(I.times(w).times(c)));
+        let at = HashMap::from([
+            ('r', Complex::real(2.0)),
+            ('w', Complex::real(4.0)),
+            ('c', Complex::real(0.125)),
+        ]);
+        assert!(close(
+            z.solve_complex(&at).unwrap(),
+            Complex::new(2.0, -2.0)
+        ));
+        assert_eq!(Complex::new(2.0, -2.0).to_string(), "2 - 2i");
+        assert_eq!(Complex::new(0.0, 1.5).to_string(), "1.5i");
+        assert_eq!(Complex::I.to_string(), "i");
+        assert_eq!(Complex::new(0.0, -1.0).to_string(), "-i");
+        assert_eq!(Complex::new(2.0, -1.0).to_string(), "2 - i");
+        assert_eq!(Complex::new(2.0, 1.0).to_string(), "2 + i");
+    }
+
+    #[test]
+    fn division_by_zero_fails() {
+        let at = HashMap::new();
+        assert!(num(1).div(num(0)).solve_complex(&at).is_err());
+        assert!(num(0).pow(I.minus(num(1))).solve_complex(&at).is_err());
+        assert_eq!(value(num(0).pow(num(0))), Complex::real(1.0));
+    }
+}
@@ -0,0 +1,283 @@
+use crate::expr::Expr;
+use crate::lex::is_constant_name;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Index of a node in a [`Dag`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+/// A node of a [`Dag`], whose operands are other nodes. Leaves keep the [`Expr`] they stand
+/// for.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Node {
+    Leaf(Expr),
+    Add(NodeId, NodeId),
+    Sub(NodeId, NodeId),
+    Mul(NodeId, NodeId),
+    Div(NodeId, NodeId),
+    Pow(NodeId, NodeId),
+    Neg(NodeId),
+    Func(String, NodeId),
+}
+
+impl Node {
+    fn children(&self) -> Vec<NodeId> {
+        match self {
+            Node::Leaf(_) => vec![],
+            Node::Add(lhs, rhs)
+            | Node::Sub(lhs, rhs)
+            | Node::Mul(lhs, rhs)
+            | Node::Div(lhs, rhs)
+            | Node::Pow(lhs, rhs) => vec![*lhs, *rhs],
+            Node::Neg(inner) | Node::Func(_, inner) => vec![*inner],
+        }
+    }
+}
+
+/// Hash-consed expressions: every distinct subexpression is stored once, however often it
+/// occurs, so expressions that repeat subtrees, like high derivatives, stay small. Nodes are
+/// only ever added after their operands.
+#[derive(Debug, Clone, Default)]
+pub struct Dag {
+    nodes: Vec<Node>,
+    ids: HashMap<Node, NodeId>,
+}
+
+impl Dag {
+    pub fn new() -> Self {
+        Dag::default()
+    }
+
+    /// Adds `e`, sharing the subexpressions already in the graph.
+    pub fn add(&mut self, e: &Expr) -> NodeId {
+        let node = match e {
+            Expr::Add(lhs, rhs) => Node::Add(self.add(lhs), self.add(rhs)),
+            Expr::Sub(lhs, rhs) => Node::Sub(self.add(lhs), self.add(rhs)),
+            Expr::Mul(lhs, rhs) => Node::Mul(self.add(lhs), self.add(rhs)),
+            Expr::Div(lhs, rhs) => Node::Div(self.add(lhs), self.add(rhs)),
+            Expr::Pow(lhs, rhs) => Node::Pow(self.add(lhs), self.add(rhs)),
+            Expr::Neg(inner) => Node::Neg(self.add(inner)),
+            Expr::Func(name, arg) => Node::Func(name.clone(), self.add(arg)),
+            leaf => Node::Leaf(leaf.clone()),
+        };
+        self.add_node(node)
+    }
+
+    pub fn add_node(&mut self, node: Node) -> NodeId {
+        if let Some(&id) = self.ids.get(&node) {
+            return id;
+        }
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(node.clone());
+        self.ids.insert(node, id);
+        id
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    /// Number of distinct subexpressions.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The expression tree of `id`, with shared nodes copied wherever they occur.
+    pub fn to_expr(&self, id: NodeId) -> Expr {
+        self.to_expr_with(id, &BTreeMap::new())
+    }
+
+    /// [`Dag::to_expr`] writing the nodes in `names` as variables.
+    fn to_expr_with(&self, id: NodeId, names: &BTreeMap<NodeId, char>) -> Expr {
+        if let Some(&name) = names.get(&id) {
+            return Expr::Var(name);
+        }
+        let build = |child: &NodeId| Box::new(self.to_expr_with(*child, names));
+        match self.node(id) {
+            Node::Leaf(e) => e.clone(),
+            Node::Add(lhs, rhs) => Expr::Add(build(lhs), build(rhs)),
+            Node::Sub(lhs, rhs) => Expr::Sub(build(lhs), build(rhs)),
+            Node::Mul(lhs, rhs) => Expr::Mul(build(lhs), build(rhs)),
+            Node::Div(lhs, rhs) => Expr::Div(build(lhs), build(rhs)),
+            Node::Pow(lhs, rhs) => Expr::Pow(build(lhs), build(rhs)),
+            Node::Neg(inner) => Expr::Neg(build(inner)),
+            Node::Func(name, arg) => Expr::Func(name.clone(), build(arg)),
+        }
+    }
+
+    /// The nodes reachable from `root`, each before the nodes that use it.
+    fn reachable(&self, root: NodeId) -> BTreeSet<NodeId> {
+        let mut seen = BTreeSet::new();
+        let mut todo = vec![root];
+        while let Some(id) = todo.pop() {
+            if seen.insert(id) {
+                todo.extend(self.node(id).children());
+            }
+        }
+        seen
+    }
+
+    /// Binds every compound subexpression of `root` used more than once to a fresh variable.
+    pub fn eliminate_common_subexpressions(&self, root: NodeId) -> LetForm {
+        let reachable = self.reachable(root);
+        let mut uses: BTreeMap<NodeId, usize> = BTreeMap::new();
+        for &id in &reachable {
+            for child in self.node(id).children() {
+                *uses.entry(child).or_default() += 1;
+            }
+        }
+        let shared: Vec<NodeId> = reachable
+            .iter()
+            .copied()
+            .filter(|id| {
+                !matches!(self.node(*id), Node::Leaf(_)) && uses.get(id).is_some_and(|&n| n > 1)
+            })
+            .collect();
+
+        let mut taken = BTreeSet::new();
+        for id in &reachable {
+            if let Node::Leaf(Expr::Var(name)) = self.node(*id) {
+                taken.insert(*name);
+            }
+        }
+        let mut fresh = fresh_names().filter(|name| !taken.contains(name));
+
+        let mut names = BTreeMap::new();
+        let mut bindings = Vec::new();
+        // ids grow with the order nodes were added in, so operands are bound first
+        for id in shared {
+            // should even those run out, the remaining shared subexpressions stay inline
+            let Some(name) = fresh.next() else {
+                break;
+            };
+            let value = self.to_expr_with(id, &names);
+            names.insert(id, name);
+            bindings.push((name, value));
+        }
+        LetForm {
+            bindings,
+            body: self.to_expr_with(root, &names),
+        }
+    }
+}
+
+/// Names for the variables bound by [`Dag::eliminate_common_subexpressions`], skipping `e`
+/// and `i`, which read like the constants.
+const FRESH_NAMES: &str = "uvwpqrstabcdfghjklmnoyzxαβγδεζηθκλμνξρσςυχψωABCDFGHJKLMNOPQRSTUVWXYZ";
+
+/// [`FRESH_NAMES`], then every other letter Unicode has that doesn't read as a constant.
+fn fresh_names() -> impl Iterator<Item = char> {
+    let others = ('\u{100}'..=char::MAX)
+        .filter(|c| c.is_alphabetic() && !FRESH_NAMES.contains(*c) && !is_constant_name(*c));
+    FRESH_NAMES.chars().chain(others)
+}
+
+/// An expression written as a sequence of `let` bindings followed by a body that may refer
+/// to them, each binding also to the ones before it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LetForm {
+    pub bindings: Vec<(char, Expr)>,
+    pub body: Expr,
+}
+
+impl LetForm {
+    /// The expression without bindings, substituting them back in.
+    pub fn inlined(&self) -> Expr {
+        let mut e = self.body.clone();
+        for (name, value) in self.bindings.iter().rev() {
+            e = e.subs(*name, value.clone());
+        }
+        e
+    }
+}
+
+/// Number of nodes of `e` as a tree, counting repeated subtrees every time.
+pub fn tree_size(e: &Expr) -> usize {
+    match e {
+        Expr::Add(lhs, rhs)
+        | Expr::Sub(lhs, rhs)
+        | Expr::Mul(lhs, rhs)
+        | Expr::Div(lhs, rhs)
+        | Expr::Pow(lhs, rhs) => 1 + tree_size(lhs) + tree_size(rhs),
+        Expr::Neg(inner) | Expr::Func(_, inner) => 1 + tree_size(inner),
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::syntax::*;
+
+    #[test]
+    fn equal_subtrees_are_shared() {
+        let mut dag = Dag::new();
+        let square = X.plus(num(1)).pow(num(2));
+        let root = dag.add(&square.clone().times(square.clone()));
+        // x, 1, x + 1, 2, (x + 1)^2 and the product
+        assert_eq!(dag.len(), 6);
+        assert_eq!(dag.add(&square), dag.add(&X.plus(num(1)).pow(num(2))));
+        assert_eq!(dag.to_expr(root), square.clone().times(square));
+    }
+
+    #[test]
+    fn repeated_subexpressions_are_bound() {
+        let y = var('y');
+        let sum = X.plus(y.clone());
+        let e = sin(sum.clone()).times(sin(sum.clone())).plus(sum.clone());
+        let form = e.cse();
+        assert_eq!(
+            form.bindings,
+            vec![('u', sum.clone()), ('v', sin(var('u')))]
+        );
+        assert_eq!(form.body, var('v').times(var('v')).plus(var('u')));
+        assert_eq!(form.inlined(), e);
+        // bindings avoid the variables of the expression
+        assert_eq!(
+            var('u')
+                .plus(num(1))
+                .pow(var('u').plus(num(1)))
+                .cse()
+                .bindings[0]
+                .0,
+            'v'
+        );
+    }
+
+    #[test]
+    fn high_derivatives_stay_small_as_dags() {
+        let mut der = X.times(X.plus(num(2)).pow(num(3))).div(X.plus(num(1)));
+        for _ in 0..4 {
+            der = der.derivative().with_respect_to('x').unwrap();
+        }
+        let mut dag = Dag::new();
+        dag.add(&der);
+        assert!(dag.len() * 20 < tree_size(&der));
+    }
+
+    #[test]
+    fn high_derivatives_of_functions_stay_small_as_dags() {
+        let mut der = X.times(sin(X)).div(X.plus(num(1)));
+        for _ in 0..4 {
+            der = der.derivative().with_respect_to('x').unwrap();
+        }
+        let mut dag = Dag::new();
+        dag.add(&der);
+        assert!(dag.len() * 20 < tree_size(&der));
+    }
+
+    #[test]
+    fn bindings_never_run_out_of_names() {
+        let mut der = X.times(sin(X)).div(X.plus(num(1)));
+        for _ in 0..5 {
+            der = der.derivative().with_respect_to('x').unwrap();
+        }
+        let form = der.cse();
+        assert!(form.bindings.len() > FRESH_NAMES.chars().count());
+        assert_eq!(form.inlined(), der);
+    }
+}
@@ -1,4 +1,7 @@
+use crate::expr::assumptions::{Assumptions, Condition, Conditional, Predicate};
+use crate::expr::simplify::simplify_assuming;
 use crate::expr::Expr;
+use std::collections::BTreeSet;
 
 pub struct Derivative<'a> {
     pub expr: &'a Expr,
@@ -6,87 +9,146 @@ pub struct Derivative<'a> {
 
 impl<'a> Derivative<'a> {
     pub fn with_respect_to(&self, var: char) -> Result<Expr, String> {
-        if self.expr.is_effectively_constant() {
-            Ok(Expr::Const(0))
-        } else {
-            match self.expr {
-                Expr::Var(v) if *v == var => Ok(Expr::Const(1)),
-                Expr::Var(_) => Err("derivative of other variables with respect to another variable has not been implemented yet".into()),
-                Expr::Neg(e) => {
-                    let e_der = Self { expr: e.as_ref() };
-                    Ok(Expr::Neg(Box::new(e_der.with_respect_to(var)?)))
-                }
-                Expr::Add(lhs, rhs) => {
-                    let lhs_der = Self { expr: lhs.as_ref() };
-                    let rhs_der = Self { expr: rhs.as_ref() };
-                    Ok(Expr::Add(
-                        Box::new(lhs_der.with_respect_to(var)?),
-                        Box::new(rhs_der.with_respect_to(var)?),
-                    ))
-                }
-                Expr::Sub(lhs, rhs) => {
-                    let lhs_der = Self { expr: lhs.as_ref() };
-                    let rhs_der = Self { expr: rhs.as_ref() };
-                    Ok(Expr::Sub(
-                        Box::new(lhs_der.with_respect_to(var)?),
-                        Box::new(rhs_der.with_respect_to(var)?),
+        differentiate(self.expr, var, &Assumptions::new(), &mut BTreeSet::new())
+    }
+
+    /// The derivative simplified under `assumptions`, along with the conditions that both
+    /// differentiating and simplifying relied on. Rules that need more than `assumptions`
+    /// guarantee, e.g. differentiating `x^x` without knowing `x > 0`, are not applied.
+    pub fn with_respect_to_assuming(
+        &self,
+        var: char,
+        assumptions: &Assumptions,
+    ) -> Result<Conditional, String> {
+        let mut conditions = BTreeSet::new();
+        let der = differentiate(self.expr, var, assumptions, &mut conditions)?;
+        let mut simplified = simplify_assuming(der, assumptions);
+        simplified.conditions.extend(conditions);
+        Ok(simplified)
+    }
+}
+
+fn boxed(e: Expr) -> Box<Expr> {
+    Box::new(e)
+}
+
+/// `f'` for `f(arg)` where `arg' = arg_der`, by the chain rule.
+fn func_derivative(name: &str, arg: &Expr, arg_der: Expr) -> Result<Expr, String> {
+    let call = |name: &str| Expr::Func(name.into(), boxed(arg.clone()));
+    let squared = |e: Expr| Expr::Pow(boxed(e), boxed(Expr::Const(2)));
+    let outer = match name {
+        "sin" => call("cos"),
+        "cos" => Expr::Neg(boxed(call("sin"))),
+        "tan" => Expr::Div(boxed(Expr::Const(1)), boxed(squared(call("cos")))),
+        "cot" => Expr::Neg(boxed(Expr::Div(
+            boxed(Expr::Const(1)),
+            boxed(squared(call("sin"))),
+        ))),
+        "exp" => call("exp"),
+        "ln" => Expr::Div(boxed(Expr::Const(1)), boxed(arg.clone())),
+        "sqrt" => Expr::Div(
+            boxed(Expr::Const(1)),
+            boxed(Expr::Mul(boxed(Expr::Const(2)), boxed(call("sqrt")))),
+        ),
+        other => return Err(format!("derivative of function [{}] is not known", other)),
+    };
+    Ok(Expr::Mul(boxed(outer), boxed(arg_der)))
+}
+
+fn depends_on(e: &Expr, var: char) -> bool {
+    match e {
+        Expr::Var(v) => *v == var,
+        Expr::Add(lhs, rhs)
+        | Expr::Sub(lhs, rhs)
+        | Expr::Mul(lhs, rhs)
+        | Expr::Div(lhs, rhs)
+        | Expr::Pow(lhs, rhs) => depends_on(lhs, var) || depends_on(rhs, var),
+        Expr::Neg(inner) | Expr::Func(_, inner) => depends_on(inner, var),
+        _ => false,
+    }
+}
+
+fn differentiate(
+    expr: &Expr,
+    var: char,
+    assumptions: &Assumptions,
+    conditions: &mut BTreeSet<Condition>,
+) -> Result<Expr, String> {
+    let mut der = |e: &Expr| differentiate(e, var, assumptions, conditions);
+    // other variables are held constant, giving partial derivatives
+    if !depends_on(expr, var) {
+        Ok(Expr::Const(0))
+    } else {
+        match expr {
+            Expr::Var(v) if *v == var => Ok(Expr::Const(1)),
+            Expr::Neg(e) => Ok(Expr::Neg(Box::new(der(e)?))),
+            Expr::Add(lhs, rhs) => Ok(Expr::Add(Box::new(der(lhs)?), Box::new(der(rhs)?))),
+            Expr::Sub(lhs, rhs) => Ok(Expr::Sub(Box::new(der(lhs)?), Box::new(der(rhs)?))),
+            Expr::Mul(lhs, rhs) => {
+                // lhs'rhs + rhs'lhs
+                let result = Expr::Add(
+                    Box::new(Expr::Mul(Box::new(der(lhs)?), rhs.clone())),
+                    Box::new(Expr::Mul(Box::new(der(rhs)?), lhs.clone())),
+                );
+                Ok(result)
+            }
+            Expr::Div(lhs, rhs) => {
+                // (lhs'rhs - rhs'lhs)/(rhs^2)
+                let dividend = Expr::Sub(
+                    Box::new(Expr::Mul(Box::new(der(lhs)?), rhs.clone())),
+                    Box::new(Expr::Mul(Box::new(der(rhs)?), lhs.clone())),
+                );
+                let divisor = Expr::Pow(rhs.clone(), Box::new(Expr::Const(2)));
+                Ok(Expr::Div(Box::new(dividend), Box::new(divisor)))
+            }
+            Expr::Pow(lhs, rhs) => match (lhs.as_ref(), rhs.as_ref()) {
+                (&Expr::Var(v), &Expr::Const(c)) if v == var && c > 0 => Ok(Expr::Mul(
+                    rhs.clone(),
+                    Box::new(Expr::Pow(lhs.clone(), Box::new(Expr::Const(c - 1)))),
+                )),
+                // c f^(c - 1) f'
+                (base, exponent) if !depends_on(exponent, var) => {
+                    let lowered = match exponent {
+                        Expr::Const(c) if *c > 0 => Expr::Const(c - 1),
+                        _ => Expr::Sub(rhs.clone(), boxed(Expr::Const(1))),
+                    };
+                    let power = Expr::Pow(lhs.clone(), boxed(lowered));
+                    Ok(Expr::Mul(
+                        boxed(Expr::Mul(rhs.clone(), boxed(power))),
+                        boxed(der(base)?),
                     ))
                 }
-                Expr::Mul(lhs, rhs) => {
-                    let lhs_der = Self { expr: lhs.as_ref() };
-                    let rhs_der = Self { expr: rhs.as_ref() };
-                    // lhs'rhs + rhs'lhs
-                    let result = Expr::Add(
-                        Box::new(Expr::Mul(
-                            Box::new(lhs_der.with_respect_to(var)?),
-                            rhs.clone(),
-                        )),
-                        Box::new(Expr::Mul(
-                            Box::new(rhs_der.with_respect_to(var)?),
-                            lhs.clone(),
-                        )),
-                    );
-                    Ok(result)
-                }
-                Expr::Div(lhs, rhs) => {
-                    let lhs_der = Self { expr: lhs.as_ref() };
-                    let rhs_der = Self { expr: rhs.as_ref() };
-                    // (lhs'rhs - rhs'lhs)/(rhs^2)
-                    let dividend = Expr::Sub(
-                        Box::new(Expr::Mul(
-                            Box::new(lhs_der.with_respect_to(var)?),
-                            rhs.clone(),
-                        )),
-                        Box::new(Expr::Mul(
-                            Box::new(rhs_der.with_respect_to(var)?),
-                            lhs.clone(),
-                        )),
-                    );
-                    let divisor = Expr::Pow(rhs.clone(), Box::new(Expr::Const(2)));
-                    Ok(Expr::Div(Box::new(dividend), Box::new(divisor)))
-                }
-                Expr::Pow(lhs, rhs) if matches!(lhs.as_ref(), &Expr::Var(_)) => {
-                    if let (&Expr::Var(v), &Expr::Const(c)) = (lhs.as_ref(), rhs.as_ref()) {
-                        if v == var && c > 0 {
-                            Ok(Expr::Mul(
-                                rhs.clone(),
-                                Box::new(Expr::Pow(lhs.clone(), Box::new(Expr::Const(c - 1)))),
-                            ))
-                        } else {
-                            Err("Other forms of pow not implemented yet".into())
-                        }
+                // f^g (g' ln f + g f'/f), where ln f needs f > 0
+                (base, exponent) => {
+                    let positive = Condition::new(base.clone(), Predicate::Positive);
+                    if !assumptions.implies(&positive) {
+                        return Err(format!(
+                            "differentiating a power with a variable exponent needs [{}]",
+                            positive
+                        ));
+                    }
+                    let ln = Expr::Func("ln".into(), lhs.clone());
+                    let exponent_part = Expr::Mul(boxed(der(exponent)?), boxed(ln));
+                    let base_part = if !depends_on(base, var) {
+                        Expr::Const(0)
                     } else {
-                        Err("Other forms of pow not implemented yet".into())
+                        Expr::Div(
+                            boxed(Expr::Mul(rhs.clone(), boxed(der(base)?))),
+                            lhs.clone(),
+                        )
+                    };
+                    // constant bases like 2 or e are known to be positive
+                    if !Assumptions::new().implies(&positive) {
+                        conditions.insert(positive);
                     }
+                    Ok(Expr::Mul(
+                        boxed(expr.clone()),
+                        boxed(Expr::Add(boxed(exponent_part), boxed(base_part))),
+                    ))
                 }
-                Expr::Pow(_, _) => {
-                    todo!()
-                }
-                Expr::Func(_, _) => {
-                    todo!()
-                }
-                _ => Err("not implemented yet!".into()),
-            }
+            },
+            Expr::Func(name, arg) => func_derivative(name, arg, der(arg)?),
+            _ => Err("not implemented yet!".into()),
         }
     }
 }
@@ -94,8 +156,9 @@ impl<'a> Derivative<'a> {
 #[cfg(test)]
 mod tests {
 
-    use crate::expr::simplify::simplify;
+    use crate::expr::assumptions::{Assumptions, Condition, Predicate};
     use crate::expr::{syntax::*, Expr};
+    use std::collections::HashMap;
 
     #[test]
     fn derivative_of_constant_is_0() {
@@ -139,4 +202,80 @@ mod tests {
 
         assert_eq!(der_result, Ok(expected));
     }
+
+    #[test]
+    fn chain_rule_for_functions() {
+        // d/dx sin(x^2) => 2x cos(x^2)
+        let der = sin(X.pow(num(2))).derivative().with_respect_to('x');
+        let expected = num(2).times(X).times(cos(X.pow(num(2))));
+        assert_eq!(der.map(Expr::simplified), Ok(expected.simplified()));
+        // d/dx ln(3x) => 1/x
+        let der = func("ln", num(3).times(X))
+            .derivative()
+            .with_respect_to('x');
+        assert_eq!(der.map(Expr::simplified), Ok(num(1).div(X)));
+    }
+
+    #[test]
+    fn derivatives_of_each_function() {
+        let u = num(2).times(X);
+        let call = |name: &str| func(name, u.clone());
+        let outer = [
+            ("sin", call("cos")),
+            ("cos", call("sin").neg()),
+            ("tan", num(1).div(call("cos").pow(num(2)))),
+            ("cot", num(1).div(call("sin").pow(num(2))).neg()),
+            ("exp", call("exp")),
+            ("ln", num(1).div(u.clone())),
+            ("sqrt", num(1).div(num(2).times(call("sqrt")))),
+        ];
+        for (name, outer) in outer {
+            let der = call(name).derivative().with_respect_to('x');
+            let expected = outer.times(num(2)).simplified();
+            assert_eq!(der.map(Expr::simplified), Ok(expected), "{}", name);
+        }
+        assert!(func("erf", X).derivative().with_respect_to('x').is_err());
+    }
+
+    #[test]
+    fn variable_exponents_need_a_positive_base() {
+        let expr = X.pow(X);
+        assert!(expr.derivative().with_respect_to('x').is_err());
+
+        let assumptions = Assumptions::new().assume('x', Predicate::Positive);
+        let der = expr
+            .derivative()
+            .with_respect_to_assuming('x', &assumptions)
+            .unwrap();
+        // x^x (ln x + 1)
+        let expected = X.pow(X).times(func("ln", X).plus(num(1))).simplified();
+        assert_eq!(der.expr, expected);
+        let positive = Condition::new(X, Predicate::Positive);
+        assert!(der.conditions.contains(&positive));
+
+        // constant bases are positive without assuming anything
+        for base in [num(2), E] {
+            let der = base
+                .pow(X)
+                .derivative()
+                .with_respect_to_assuming('x', &Assumptions::new())
+                .unwrap();
+            assert!(der.conditions.is_empty(), "{:?}", der.conditions);
+        }
+    }
+
+    #[test]
+    fn other_variables_are_held_constant() {
+        let y = var('y');
+        // d/dx x^2 y => 2xy, d/dy x^2 y => x^2
+        let expr = X.pow(num(2)).times(y.clone());
+        let der = expr.derivative().with_respect_to('x').map(Expr::simplified);
+        assert_eq!(der, Ok(num(2).times(X).times(y.clone()).simplified()));
+        let der = expr.derivative().with_respect_to('y').map(Expr::simplified);
+        assert_eq!(der, Ok(X.pow(num(2))));
+        // d/dx x^y => y x^(y - 1)
+        let der = X.pow(y.clone()).derivative().with_respect_to('x');
+        let at = HashMap::from([('x', 2.0), ('y', 3.0)]);
+        assert_eq!(der.unwrap().solve_for(&at), Ok(12.0));
+    }
 }
@@ -0,0 +1,251 @@
+use crate::expr::assumptions::{Assumptions, Condition, Predicate};
+use crate::expr::rational::Rational;
+use crate::expr::syntax::{cos, sin};
+use crate::expr::Expr;
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+
+/// An operation applied outside of its domain, e.g. `ln` of a negative number.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DomainError {
+    /// The subexpression that has no value.
+    pub expr: Expr,
+    /// The condition its arguments failed.
+    pub condition: Condition,
+}
+
+impl Display for DomainError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let e = crate::out::describe(&self.expr);
+        write!(f, "[{}] is undefined, it requires {}", e, self.condition)
+    }
+}
+
+/// Why [`Expr::solve_for`] has no value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    Domain(DomainError),
+    /// A variable without a value.
+    UnboundVariable(char),
+    /// Anything else, like an unknown function.
+    Other(String),
+}
+
+impl EvalError {
+    pub(crate) fn domain(expr: &Expr, condition: Condition) -> Self {
+        EvalError::Domain(DomainError {
+            expr: expr.clone(),
+            condition,
+        })
+    }
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalError::Domain(error) => write!(f, "{}", error),
+            EvalError::UnboundVariable(name) => write!(f, "could not find variable [{}]", name),
+            EvalError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<String> for EvalError {
+    fn from(message: String) -> Self {
+        EvalError::Other(message)
+    }
+}
+
+impl From<EvalError> for String {
+    fn from(error: EvalError) -> Self {
+        error.to_string()
+    }
+}
+
+/// The conditions under which every operation in `e` is defined over the reals:
+/// denominators are nonzero, logarithms take positive arguments, even roots nonnegative
+/// radicands and `tan` and `cot` stay away from their poles. Conditions that hold without
+/// any assumptions, like `2 ≠ 0`, are left out.
+pub fn domain(e: &Expr) -> BTreeSet<Condition> {
+    let mut conditions = BTreeSet::new();
+    collect(e, &mut conditions);
+    let trivial = Assumptions::new();
+    conditions.retain(|c| !trivial.implies(c));
+    conditions
+}
+
+fn collect(e: &Expr, conditions: &mut BTreeSet<Condition>) {
+    let mut require = |expr: &Expr, predicate| {
+        conditions.insert(Condition::new(expr.clone(), predicate));
+    };
+    match e {
+        Expr::Div(_, divisor) => require(divisor, Predicate::Nonzero),
+        Expr::Pow(base, exponent) => {
+            if let Some(condition) = power_condition(base, exponent) {
+                conditions.insert(condition);
+            }
+        }
+        Expr::Func(name, arg) => match name.as_str() {
+            "ln" => require(arg, Predicate::Positive),
+            "sqrt" => require(arg, Predicate::Nonnegative),
+            "tan" => require(&cos(*arg.clone()), Predicate::Nonzero),
+            "cot" => require(&sin(*arg.clone()), Predicate::Nonzero),
+            _ => {}
+        },
+        _ => {}
+    }
+    match e {
+        Expr::Add(lhs, rhs)
+        | Expr::Sub(lhs, rhs)
+        | Expr::Mul(lhs, rhs)
+        | Expr::Div(lhs, rhs)
+        | Expr::Pow(lhs, rhs) => {
+            collect(lhs, conditions);
+            collect(rhs, conditions);
+        }
+        Expr::Neg(inner) | Expr::Func(_, inner) => collect(inner, conditions),
+        _ => {}
+    }
+}
+
+/// The condition on `base` for `base^exponent` to be real. Rational exponents with an odd
+/// denominator are odd roots, defined for negative bases too.
+fn power_condition(base: &Expr, exponent: &Expr) -> Option<Condition> {
+    let odd = |r: Rational| r.is_integer() || r.denom() % 2 == 1;
+    let predicate = match Rational::from_expr(exponent) {
+        Some(r) if odd(r) && !r.is_negative() => return None,
+        Some(r) if odd(r) => Predicate::Nonzero,
+        Some(r) if r.is_negative() => Predicate::Positive,
+        Some(_) => Predicate::Nonnegative,
+        // the exponent may take any value, which only positive bases allow
+        None => Predicate::Positive,
+    };
+    Some(Condition::new(base.clone(), predicate))
+}
+
+/// Checks a value computed by [`Expr::solve_for`] for `e` from the values of its
+/// arguments, returning the condition of [`domain`] they fail.
+pub(crate) fn check(e: &Expr, args: &[f64]) -> Result<(), EvalError> {
+    let fail = |expr: &Expr, predicate| {
+        Err(EvalError::domain(
+            e,
+            Condition::new(expr.clone(), predicate),
+        ))
+    };
+    match (e, args) {
+        (Expr::Div(_, divisor), [_, value]) if *value == 0.0 => fail(divisor, Predicate::Nonzero),
+        (Expr::Pow(base, exponent), [b, x]) => {
+            if *b == 0.0 && *x < 0.0 {
+                fail(base, Predicate::Nonzero)
+            } else if *b < 0.0 && x.fract() != 0.0 && odd_root(exponent).is_none() {
+                let predicate = match Rational::from_expr(exponent) {
+                    Some(_) => Predicate::Nonnegative,
+                    None => Predicate::Positive,
+                };
+                fail(base, predicate)
+            } else {
+                Ok(())
+            }
+        }
+        (Expr::Func(name, arg), [x]) => match name.as_str() {
+            "ln" if *x <= 0.0 => fail(arg, Predicate::Positive),
+            "sqrt" if *x < 0.0 => fail(arg, Predicate::Nonnegative),
+            // within rounding of a pole
+            "tan" if x.cos().abs() <= f64::EPSILON * x.abs().max(1.0) => {
+                fail(&cos(*arg.clone()), Predicate::Nonzero)
+            }
+            "cot" if x.sin().abs() <= f64::EPSILON * x.abs().max(1.0) => {
+                fail(&sin(*arg.clone()), Predicate::Nonzero)
+            }
+            _ => Ok(()),
+        },
+        _ => Ok(()),
+    }
+}
+
+/// `p/q` if `exponent` is a fraction with an odd denominator, so that `b^(p/q)` is real
+/// for negative `b`.
+pub(crate) fn odd_root(exponent: &Expr) -> Option<Rational> {
+    Rational::from_expr(exponent).filter(|r| !r.is_integer() && r.denom() % 2 == 1)
+}
+
+/// `base^exponent` over the reals, where `odd_root` is the exponent as returned by
+/// [`odd_root`]: the odd root `(-1)^p |base|^(p/q)` of a negative `base`, `powf` otherwise.
+pub(crate) fn real_pow(base: f64, exponent: f64, odd_root: Option<Rational>) -> f64 {
+    match odd_root {
+        Some(r) if base < 0.0 => {
+            let root = (-base).powf(exponent);
+            if r.numer() % 2 == 0 {
+                root
+            } else {
+                -root
+            }
+        }
+        _ => base.powf(exponent),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::syntax::*;
+    use std::collections::HashMap;
+
+    fn conditions(e: Expr) -> Vec<String> {
+        e.domain().iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn conditions_of_each_operation() {
+        assert_eq!(conditions(num(1).div(X.minus(num(1)))), ["x - 1 ≠ 0"]);
+        assert_eq!(conditions(func("ln", X.minus(num(2)))), ["x - 2 > 0"]);
+        assert_eq!(conditions(func("sqrt", X)), ["x ≥ 0"]);
+        assert_eq!(conditions(X.pow(num(1).div(num(2)))), ["x ≥ 0"]);
+        assert_eq!(conditions(X.pow(num(1).div(num(3)))), Vec::<String>::new());
+        assert_eq!(conditions(X.pow(num(2).neg())), ["x ≠ 0"]);
+        assert_eq!(conditions(num(2).pow(X)), Vec::<String>::new());
+        assert_eq!(conditions(tan(X)), ["cos(x) ≠ 0"]);
+        // conditions of nested operations are all collected
+        let nested = func("sqrt", func("ln", X)).div(X);
+        assert_eq!(conditions(nested), ["x > 0", "x ≠ 0", "ln(x) ≥ 0"]);
+    }
+
+    #[test]
+    fn assumptions_discharge_conditions() {
+        let positive = Assumptions::new().assume('x', Predicate::Positive);
+        let e = func("ln", X).plus(num(1).div(X.pow(num(2)).plus(num(1))));
+        let remaining: Vec<_> = e
+            .domain()
+            .into_iter()
+            .filter(|c| !positive.implies(c))
+            .collect();
+        assert!(remaining.is_empty());
+    }
+
+    #[test]
+    fn evaluation_names_the_undefined_subexpression() {
+        let at = |x: f64| HashMap::from([('x', x)]);
+        let e = num(1).div(X.minus(num(1))).plus(func("ln", X));
+        let error = e.solve_for(&at(1.0)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "[1 / (x - 1)] is undefined, it requires x - 1 ≠ 0"
+        );
+        match e.solve_for(&at(-1.0)) {
+            Err(EvalError::Domain(DomainError { expr, condition })) => {
+                assert_eq!(expr, func("ln", X));
+                assert_eq!(condition, Condition::new(X, Predicate::Positive));
+            }
+            other => panic!("expected a domain error, got {:?}", other),
+        }
+        assert!(tan(PI.div(num(2))).solve_for(&at(0.0)).is_err());
+        assert!(func("sqrt", num(2).neg()).solve_for(&at(0.0)).is_err());
+        // odd roots of negative numbers are real
+        let cube_root = X.pow(num(1).div(num(3))).solve_for(&at(-8.0)).unwrap();
+        assert!((cube_root + 2.0).abs() < 1e-12);
+        assert_eq!(
+            X.plus(var('y')).solve_for(&at(0.0)),
+            Err(EvalError::UnboundVariable('y'))
+        );
+    }
+}
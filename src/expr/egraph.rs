@@ -0,0 +1,689 @@
+use crate::expr::rational::Rational;
+use crate::expr::rewrite::{Bindings, Rule};
+use crate::expr::simplify::simplify;
+use crate::expr::syntax::num;
+use crate::expr::Expr;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+const DEFAULT_NODE_LIMIT: usize = 2_000;
+
+const DEFAULT_ITERATION_LIMIT: usize = 30;
+
+const DEFAULT_TIME_LIMIT: Duration = Duration::from_secs(1);
+
+/// Identifier of an equivalence class of an [`EGraph`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Id(usize);
+
+/// An `Expr` node whose operands are equivalence classes rather than expressions.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ENode {
+    /// A number, named constant or variable.
+    Leaf(Expr),
+    Add(Id, Id),
+    Sub(Id, Id),
+    Mul(Id, Id),
+    Div(Id, Id),
+    Pow(Id, Id),
+    Neg(Id),
+    Func(String, Id),
+}
+
+impl ENode {
+    pub fn children(&self) -> Vec<Id> {
+        match self {
+            ENode::Leaf(_) => vec![],
+            ENode::Add(lhs, rhs)
+            | ENode::Sub(lhs, rhs)
+            | ENode::Mul(lhs, rhs)
+            | ENode::Div(lhs, rhs)
+            | ENode::Pow(lhs, rhs) => vec![*lhs, *rhs],
+            ENode::Neg(inner) | ENode::Func(_, inner) => vec![*inner],
+        }
+    }
+
+    fn map_children(&self, mut f: impl FnMut(Id) -> Id) -> ENode {
+        match self {
+            ENode::Leaf(e) => ENode::Leaf(e.clone()),
+            ENode::Add(lhs, rhs) => ENode::Add(f(*lhs), f(*rhs)),
+            ENode::Sub(lhs, rhs) => ENode::Sub(f(*lhs), f(*rhs)),
+            ENode::Mul(lhs, rhs) => ENode::Mul(f(*lhs), f(*rhs)),
+            ENode::Div(lhs, rhs) => ENode::Div(f(*lhs), f(*rhs)),
+            ENode::Pow(lhs, rhs) => ENode::Pow(f(*lhs), f(*rhs)),
+            ENode::Neg(inner) => ENode::Neg(f(*inner)),
+            ENode::Func(name, arg) => ENode::Func(name.clone(), f(*arg)),
+        }
+    }
+
+    /// The expression of this node, given expressions for its operands in order.
+    fn to_expr(&self, mut children: impl Iterator<Item = Expr>) -> Expr {
+        let mut next = || Box::new(children.next().expect("an expression per operand"));
+        match self {
+            ENode::Leaf(e) => e.clone(),
+            ENode::Add(_, _) => Expr::Add(next(), next()),
+            ENode::Sub(_, _) => Expr::Sub(next(), next()),
+            ENode::Mul(_, _) => Expr::Mul(next(), next()),
+            ENode::Div(_, _) => Expr::Div(next(), next()),
+            ENode::Pow(_, _) => Expr::Pow(next(), next()),
+            ENode::Neg(_) => Expr::Neg(next()),
+            ENode::Func(name, _) => Expr::Func(name.clone(), next()),
+        }
+    }
+}
+
+/// Decides which of the equivalent expressions in an [`EGraph`] is the cheapest.
+pub trait CostModel {
+    /// Cost of `node` given the costs of its operands, in order.
+    fn cost(&self, node: &ENode, children: &[u64]) -> u64;
+}
+
+/// Counts every node of the expression tree.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NodeCount;
+
+impl CostModel for NodeCount {
+    fn cost(&self, _: &ENode, children: &[u64]) -> u64 {
+        1 + children.iter().sum::<u64>()
+    }
+}
+
+/// Counts the operations, ignoring numbers, constants and variables.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OperationCount;
+
+impl CostModel for OperationCount {
+    fn cost(&self, node: &ENode, children: &[u64]) -> u64 {
+        let own = match node {
+            ENode::Leaf(_) => 0,
+            _ => 1,
+        };
+        own + children.iter().sum::<u64>()
+    }
+}
+
+/// Rough relative cost of evaluating the expression in floating point: additions are cheap,
+/// divisions and powers are not, and functions are the most expensive.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EvaluationCost;
+
+impl CostModel for EvaluationCost {
+    fn cost(&self, node: &ENode, children: &[u64]) -> u64 {
+        let own = match node {
+            ENode::Leaf(_) => 0,
+            ENode::Add(_, _) | ENode::Sub(_, _) | ENode::Neg(_) => 1,
+            ENode::Mul(_, _) => 2,
+            ENode::Div(_, _) => 8,
+            ENode::Pow(_, _) => 20,
+            ENode::Func(_, _) => 30,
+        };
+        own + children.iter().sum::<u64>()
+    }
+}
+
+/// A set of expressions grouped into classes of equal value, sharing common subexpressions.
+#[derive(Debug, Clone, Default)]
+pub struct EGraph {
+    parents: Vec<Id>,
+    classes: BTreeMap<Id, Vec<ENode>>,
+    memo: HashMap<ENode, Id>,
+}
+
+impl EGraph {
+    pub fn new() -> Self {
+        EGraph::default()
+    }
+
+    /// The representative of the class of `id`.
+    pub fn find(&self, mut id: Id) -> Id {
+        while self.parents[id.0] != id {
+            id = self.parents[id.0];
+        }
+        id
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.memo.len()
+    }
+
+    pub fn class_count(&self) -> usize {
+        self.classes.len()
+    }
+
+    fn canonical(&self, node: &ENode) -> ENode {
+        node.map_children(|id| self.find(id))
+    }
+
+    pub fn add_node(&mut self, node: ENode) -> Id {
+        let node = self.canonical(&node);
+        if let Some(&id) = self.memo.get(&node) {
+            return self.find(id);
+        }
+        let id = Id(self.parents.len());
+        self.parents.push(id);
+        self.classes.insert(id, vec![node.clone()]);
+        self.memo.insert(node, id);
+        id
+    }
+
+    pub fn add(&mut self, e: &Expr) -> Id {
+        let node = match e {
+            Expr::Add(lhs, rhs) => ENode::Add(self.add(lhs), self.add(rhs)),
+            Expr::Sub(lhs, rhs) => ENode::Sub(self.add(lhs), self.add(rhs)),
+            Expr::Mul(lhs, rhs) => ENode::Mul(self.add(lhs), self.add(rhs)),
+            Expr::Div(lhs, rhs) => ENode::Div(self.add(lhs), self.add(rhs)),
+            Expr::Pow(lhs, rhs) => ENode::Pow(self.add(lhs), self.add(rhs)),
+            Expr::Neg(inner) => ENode::Neg(self.add(inner)),
+            Expr::Func(name, arg) => ENode::Func(name.clone(), self.add(arg)),
+            leaf => ENode::Leaf(leaf.clone()),
+        };
+        self.add_node(node)
+    }
+
+    /// Records that both classes are equal, returning `false` if they already were. Call
+    /// [`EGraph::rebuild`] before matching again.
+    pub fn union(&mut self, a: Id, b: Id) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+        let (keep, merged) = (a.min(b), a.max(b));
+        self.parents[merged.0] = keep;
+        let nodes = self.classes.remove(&merged).unwrap_or_default();
+        self.classes.entry(keep).or_default().extend(nodes);
+        true
+    }
+
+    /// Restores the invariants after unions: nodes refer to class representatives, and
+    /// nodes that became identical, e.g. `f(a)` and `f(b)` after `a = b`, share a class.
+    pub fn rebuild(&mut self) {
+        loop {
+            let mut memo: HashMap<ENode, Id> = HashMap::new();
+            let mut equal = Vec::new();
+            for (&id, nodes) in &self.classes {
+                for node in nodes {
+                    if let Some(other) = memo.insert(self.canonical(node), id) {
+                        if other != id {
+                            equal.push((other, id));
+                        }
+                    }
+                }
+            }
+            if equal.is_empty() {
+                self.memo = memo;
+                break;
+            }
+            for (a, b) in equal {
+                self.union(a, b);
+            }
+        }
+        let canonical: Vec<(Id, Vec<ENode>)> = self
+            .classes
+            .iter()
+            .map(|(&id, nodes)| {
+                let mut nodes: Vec<ENode> = nodes.iter().map(|n| self.canonical(n)).collect();
+                nodes.sort();
+                nodes.dedup();
+                (id, nodes)
+            })
+            .collect();
+        self.classes = canonical.into_iter().collect();
+    }
+
+    /// All ways `pattern` matches an expression of `class`, as pattern variable bindings.
+    fn ematch(
+        &self,
+        pattern: &Expr,
+        class: Id,
+        bindings: &BTreeMap<char, Id>,
+    ) -> Vec<BTreeMap<char, Id>> {
+        let class = self.find(class);
+        if let Expr::Var(name) = pattern {
+            return match bindings.get(name) {
+                Some(&bound) if self.find(bound) != class => vec![],
+                Some(_) => vec![bindings.clone()],
+                None => {
+                    let mut extended = bindings.clone();
+                    extended.insert(*name, class);
+                    vec![extended]
+                }
+            };
+        }
+        let mut matches = Vec::new();
+        for node in &self.classes[&class] {
+            let operands: Vec<(&Expr, Id)> = match (pattern, node) {
+                (Expr::Add(p, q), ENode::Add(l, r))
+                | (Expr::Sub(p, q), ENode::Sub(l, r))
+                | (Expr::Mul(p, q), ENode::Mul(l, r))
+                | (Expr::Div(p, q), ENode::Div(l, r))
+                | (Expr::Pow(p, q), ENode::Pow(l, r)) => vec![(p, *l), (q, *r)],
+                (Expr::Neg(p), ENode::Neg(inner)) => vec![(p, *inner)],
+                (Expr::Func(p_name, p), ENode::Func(name, arg)) if p_name == name => {
+                    vec![(p, *arg)]
+                }
+                (leaf, ENode::Leaf(e)) if leaf == e => vec![],
+                _ => continue,
+            };
+            let mut partial = vec![bindings.clone()];
+            for (p, id) in operands {
+                partial = partial.iter().flat_map(|b| self.ematch(p, id, b)).collect();
+            }
+            matches.extend(partial);
+        }
+        matches
+    }
+
+    fn instantiate(&mut self, template: &Expr, bindings: &BTreeMap<char, Id>) -> Id {
+        let node = match template {
+            Expr::Var(name) => match bindings.get(name) {
+                Some(&id) => return id,
+                None => ENode::Leaf(template.clone()),
+            },
+            Expr::Add(lhs, rhs) => ENode::Add(
+                self.instantiate(lhs, bindings),
+                self.instantiate(rhs, bindings),
+            ),
+            Expr::Sub(lhs, rhs) => ENode::Sub(
+                self.instantiate(lhs, bindings),
+                self.instantiate(rhs, bindings),
+            ),
+            Expr::Mul(lhs, rhs) => ENode::Mul(
+                self.instantiate(lhs, bindings),
+                self.instantiate(rhs, bindings),
+            ),
+            Expr::Div(lhs, rhs) => ENode::Div(
+                self.instantiate(lhs, bindings),
+                self.instantiate(rhs, bindings),
+            ),
+            Expr::Pow(lhs, rhs) => ENode::Pow(
+                self.instantiate(lhs, bindings),
+                self.instantiate(rhs, bindings),
+            ),
+            Expr::Neg(inner) => ENode::Neg(self.instantiate(inner, bindings)),
+            Expr::Func(name, arg) => ENode::Func(name.clone(), self.instantiate(arg, bindings)),
+            leaf => ENode::Leaf(leaf.clone()),
+        };
+        self.add_node(node)
+    }
+
+    /// The cheapest node of every class under `cost`, with its total cost.
+    fn best_nodes(&self, cost: &dyn CostModel) -> HashMap<Id, (u64, ENode)> {
+        let mut best: HashMap<Id, (u64, ENode)> = HashMap::new();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (&id, nodes) in &self.classes {
+                for node in nodes {
+                    let children: Option<Vec<u64>> = node
+                        .children()
+                        .iter()
+                        .map(|c| best.get(&self.find(*c)).map(|(total, _)| *total))
+                        .collect();
+                    let Some(children) = children else {
+                        continue;
+                    };
+                    let total = cost.cost(node, &children);
+                    if best.get(&id).is_none_or(|(current, _)| total < *current) {
+                        best.insert(id, (total, node.clone()));
+                        changed = true;
+                    }
+                }
+            }
+        }
+        best
+    }
+
+    /// The expression of the cheapest nodes from `id` down, or `None` where they loop back
+    /// to a class on `path`, which a stale `best` or a cost model cheaper than its operands
+    /// can do.
+    fn build(&self, id: Id, best: &HashMap<Id, (u64, ENode)>, path: &mut Vec<Id>) -> Option<Expr> {
+        let id = self.find(id);
+        if path.contains(&id) {
+            return None;
+        }
+        let (_, node) = best.get(&id)?;
+        path.push(id);
+        let children: Option<Vec<Expr>> = node
+            .children()
+            .into_iter()
+            .map(|c| self.build(c, best, path))
+            .collect();
+        path.pop();
+        Some(node.to_expr(children?.into_iter()))
+    }
+
+    /// The cheapest expression of the class of `id` under `cost`, or `None` if the cheapest
+    /// nodes form a cycle, which only a cost model cheaper than some operand allows.
+    pub fn extract(&self, id: Id, cost: &dyn CostModel) -> Option<Expr> {
+        self.build(id, &self.best_nodes(cost), &mut Vec::new())
+    }
+}
+
+fn a() -> Expr {
+    Expr::Var('a')
+}
+
+fn b() -> Expr {
+    Expr::Var('b')
+}
+
+fn c() -> Expr {
+    Expr::Var('c')
+}
+
+fn neg(e: Expr) -> Expr {
+    Expr::Neg(Box::new(e))
+}
+
+fn is_number(b: &Bindings, name: char) -> bool {
+    Rational::from_expr(&b[name]).is_some()
+}
+
+/// Folds an operation on numbers, e.g. `2 * 3` into `6`.
+fn fold(name: &str, pattern: Expr) -> Rule {
+    let node = pattern.clone();
+    Rule::computed(name, pattern, move |b| {
+        let e = b.instantiate(&node);
+        Rational::from_expr(&e)?.to_expr()
+    })
+    .when(|b| is_number(b, 'a') && b.get('b').is_none_or(|_| is_number(b, 'b')))
+}
+
+/// The default rules for [`Saturation`]: algebraic identities in both directions, so that
+/// rewriting can move away from and back towards a smaller form, plus numeric folding.
+pub fn rules() -> Vec<Rule> {
+    vec![
+        Rule::new("commute add", a().plus(b()), b().plus(a())),
+        Rule::new("commute mul", a().times(b()), b().times(a())),
+        Rule::new(
+            "associate add",
+            a().plus(b().plus(c())),
+            a().plus(b()).plus(c()),
+        ),
+        Rule::new(
+            "associate add back",
+            a().plus(b()).plus(c()),
+            a().plus(b().plus(c())),
+        ),
+        Rule::new(
+            "associate mul",
+            a().times(b().times(c())),
+            a().times(b()).times(c()),
+        ),
+        Rule::new(
+            "associate mul back",
+            a().times(b()).times(c()),
+            a().times(b().times(c())),
+        ),
+        Rule::new("subtract", a().minus(b()), a().plus(neg(b()))),
+        Rule::new("add negation", a().plus(neg(b())), a().minus(b())),
+        Rule::new(
+            "pull out negation",
+            neg(a()).times(b()),
+            neg(a().times(b())),
+        ),
+        Rule::new("push in negation", neg(a().times(b())), neg(a()).times(b())),
+        Rule::new(
+            "distribute",
+            a().times(b().plus(c())),
+            a().times(b()).plus(a().times(c())),
+        ),
+        Rule::new(
+            "factor out",
+            a().times(b()).plus(a().times(c())),
+            a().times(b().plus(c())),
+        ),
+        Rule::new(
+            "distribute difference",
+            a().times(b().minus(c())),
+            a().times(b()).minus(a().times(c())),
+        ),
+        Rule::new(
+            "factor out of difference",
+            a().times(b()).minus(a().times(c())),
+            a().times(b().minus(c())),
+        ),
+        Rule::new("cancel added term", a().plus(b()).minus(a()), b()),
+        Rule::new("add zero", a().plus(num(0)), a()),
+        Rule::new("multiply by one", a().times(num(1)), a()),
+        Rule::new("multiply by zero", a().times(num(0)), num(0)),
+        Rule::new("subtract self", a().minus(a()), num(0)),
+        Rule::new("double negation", neg(neg(a())), a()),
+        Rule::new("square", a().times(a()), a().pow(num(2))),
+        Rule::new("unsquare", a().pow(num(2)), a().times(a())),
+        Rule::new(
+            "add exponents",
+            a().pow(b()).times(a().pow(c())),
+            a().pow(b().plus(c())),
+        ),
+        Rule::new(
+            "increment exponent",
+            a().times(a().pow(b())),
+            a().pow(b().plus(num(1))),
+        ),
+        Rule::new("unit exponent", a().pow(num(1)), a()),
+        Rule::new("zero exponent", a().pow(num(0)), num(1)),
+        Rule::new("self division", a().div(a()), num(1)).when(|b| !is_number(b, 'a')),
+        Rule::new("cancel factor", a().times(b()).div(b()), a()).when(|b| !is_number(b, 'b')),
+        Rule::new(
+            "divide product",
+            a().times(b()).div(c()),
+            a().times(b().div(c())),
+        ),
+        fold("fold add", a().plus(b())),
+        fold("fold subtract", a().minus(b())),
+        fold("fold multiply", a().times(b())),
+        fold("fold divide", a().div(b())),
+        fold("fold negate", neg(a())),
+        fold("fold power", a().pow(b())),
+    ]
+}
+
+/// Simplification by equality saturation: the expression is added to an [`EGraph`],
+/// rules are applied everywhere at once, keeping every form they produce, until no rule adds
+/// anything new or a limit is reached, and the cheapest equivalent form is extracted. Unlike
+/// the greedy [`Expr::simplified`], intermediate forms may be larger than the input.
+pub struct Saturation {
+    rules: Vec<Rule>,
+    cost: Box<dyn CostModel>,
+    node_limit: usize,
+    iteration_limit: usize,
+    time_limit: Duration,
+}
+
+impl Default for Saturation {
+    fn default() -> Self {
+        Saturation::new(rules())
+    }
+}
+
+impl Saturation {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Saturation {
+            rules,
+            cost: Box::new(NodeCount),
+            node_limit: DEFAULT_NODE_LIMIT,
+            iteration_limit: DEFAULT_ITERATION_LIMIT,
+            time_limit: DEFAULT_TIME_LIMIT,
+        }
+    }
+
+    pub fn cost<C: CostModel + 'static>(mut self, cost: C) -> Self {
+        self.cost = Box::new(cost);
+        self
+    }
+
+    /// Stops applying rules once the e-graph holds this many nodes.
+    pub fn node_limit(mut self, node_limit: usize) -> Self {
+        self.node_limit = node_limit;
+        self
+    }
+
+    pub fn iteration_limit(mut self, iteration_limit: usize) -> Self {
+        self.iteration_limit = iteration_limit;
+        self
+    }
+
+    pub fn time_limit(mut self, time_limit: Duration) -> Self {
+        self.time_limit = time_limit;
+        self
+    }
+
+    /// The cheapest expression found equal to `expr`, never costlier than `expr` itself or
+    /// its [`simplify`]d form, both of which seed the search.
+    pub fn simplify(&self, expr: &Expr) -> Expr {
+        let start = Instant::now();
+        let mut graph = EGraph::new();
+        let root = graph.add(expr);
+        let seed = simplify(expr.clone());
+        let simplified = graph.add(&seed);
+        graph.union(root, simplified);
+        graph.rebuild();
+
+        for _ in 0..self.iteration_limit {
+            if graph.node_count() >= self.node_limit || start.elapsed() >= self.time_limit {
+                break;
+            }
+            let classes: Vec<Id> = graph.classes.keys().copied().collect();
+            let matches: Vec<(&Rule, Id, BTreeMap<char, Id>)> = self
+                .rules
+                .iter()
+                .flat_map(|rule| {
+                    let graph = &graph;
+                    classes.iter().flat_map(move |&class| {
+                        graph
+                            .ematch(rule.pattern(), class, &BTreeMap::new())
+                            .into_iter()
+                            .map(move |bindings| (rule, class, bindings))
+                    })
+                })
+                .collect();
+
+            let (nodes, classes) = (graph.node_count(), graph.class_count());
+            // every replacement is built before any union, while `best` still describes the graph
+            let best = graph.best_nodes(self.cost.as_ref());
+            let mut unions = Vec::new();
+            for (rule, class, bindings) in matches {
+                if graph.node_count() >= self.node_limit || start.elapsed() >= self.time_limit {
+                    break;
+                }
+                let replacement = match rule.template() {
+                    Some(template) => graph.instantiate(template, &bindings),
+                    None => {
+                        let bound: Option<Bindings> = bindings
+                            .iter()
+                            .map(|(&name, &id)| {
+                                Some((name, graph.build(id, &best, &mut Vec::new())?))
+                            })
+                            .collect();
+                        match bound.and_then(|bound| rule.replace(&bound)) {
+                            Some(e) => graph.add(&e),
+                            None => continue,
+                        }
+                    }
+                };
+                unions.push((class, replacement));
+            }
+            for (class, replacement) in unions {
+                graph.union(class, replacement);
+            }
+            graph.rebuild();
+            if graph.node_count() == nodes && graph.class_count() == classes {
+                // saturated, nothing new was added or merged
+                break;
+            }
+        }
+        graph.extract(root, self.cost.as_ref()).unwrap_or(seed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::syntax::*;
+
+    fn size(e: &Expr) -> u64 {
+        let mut graph = EGraph::new();
+        let id = graph.add(e);
+        graph.best_nodes(&NodeCount)[&id].0
+    }
+
+    #[test]
+    fn equal_subexpressions_share_a_class() {
+        let mut graph = EGraph::new();
+        let lhs = graph.add(&X.plus(num(1)).times(X.plus(num(1))));
+        let rhs = graph.add(&X.plus(num(1)));
+        assert_eq!(graph.class_count(), 4);
+        assert_ne!(lhs, rhs);
+    }
+
+    #[test]
+    fn rebuild_merges_congruent_nodes() {
+        let mut graph = EGraph::new();
+        let (x, y) = (graph.add(&X), graph.add(&var('y')));
+        let (sin_x, sin_y) = (graph.add(&sin(X)), graph.add(&sin(var('y'))));
+        graph.union(x, y);
+        graph.rebuild();
+        assert_eq!(graph.find(sin_x), graph.find(sin_y));
+    }
+
+    #[test]
+    fn escapes_local_minima_of_simplify() {
+        // (x + y)z - xz => yz, which simplify can't see without expanding
+        let (y, z) = (var('y'), var('z'));
+        let expr = X.plus(y.clone()).times(z.clone()).minus(X.times(z.clone()));
+        assert_eq!(size(&simplify(expr.clone())), size(&expr));
+        let result = Saturation::default().simplify(&expr);
+        assert!(
+            result == y.clone().times(z.clone()) || result == z.times(y),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn factors_out_common_terms() {
+        // xy + xz => x(y + z)
+        let (y, z) = (var('y'), var('z'));
+        let expr = X.times(y).plus(X.times(z));
+        let result = Saturation::default().simplify(&expr);
+        assert_eq!(size(&result), 5);
+    }
+
+    #[test]
+    fn cost_model_decides_the_extracted_form() {
+        let expr = X.pow(num(2)).plus(num(0));
+        let by_evaluation = Saturation::default().cost(EvaluationCost).simplify(&expr);
+        assert_eq!(by_evaluation, X.times(X));
+        let by_operations = Saturation::default().cost(OperationCount).simplify(&expr);
+        assert_eq!(OperationCount.cost(&ENode::Leaf(X), &[]), 0);
+        assert_eq!(size(&by_operations), 3);
+    }
+
+    #[test]
+    fn folding_after_a_merge_terminates() {
+        // x + (3 - x) merges with 3 before the outer sum is folded
+        let y = var('y');
+        let expr = X.plus(num(3).plus(num(3).minus(X))).div(y.clone());
+        assert_eq!(Saturation::default().simplify(&expr), num(6).div(y));
+        let divisor = sin(func("sqrt", func("ln", num(1))));
+        let expr = X
+            .plus(num(3).pow(num(1)).plus(num(3).minus(X)))
+            .div(divisor);
+        let vars = [('x', 0.7)].into_iter().collect();
+        assert!(Saturation::default()
+            .simplify(&expr)
+            .solve_for(&vars)
+            .is_err());
+    }
+
+    #[test]
+    fn limits_still_return_an_equivalent_form() {
+        let expr = X.plus(num(1)).pow(num(2)).times(var('y').plus(num(2)));
+        let result = Saturation::default().node_limit(50).simplify(&expr);
+        assert!(size(&result) <= size(&expr));
+        let vars = [('x', 0.7), ('y', -1.3)].into_iter().collect();
+        let expected = expr.solve_for(&vars).unwrap();
+        assert!((result.solve_for(&vars).unwrap() - expected).abs() < 1e-9);
+    }
+}
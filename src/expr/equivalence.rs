@@ -0,0 +1,216 @@
+use crate::expr::domain::EvalError;
+use crate::expr::rng::SplitMix64;
+pub use crate::expr::simplify::check_rewrites;
+use crate::expr::simplify::simplify;
+use crate::expr::Expr;
+use std::collections::{BTreeSet, HashMap};
+
+const DEFAULT_SAMPLES: usize = 32;
+
+const DEFAULT_TOLERANCE: f64 = 1e-9;
+
+const DEFAULT_RANGE: f64 = 10.0;
+
+const DEFAULT_SEED: u64 = 0x5eed_cafe_f00d_d00d;
+
+fn collect_vars(e: &Expr, out: &mut BTreeSet<char>) {
+    match e {
+        Expr::Var(name) => {
+            out.insert(*name);
+        }
+        Expr::Add(lhs, rhs)
+        | Expr::Sub(lhs, rhs)
+        | Expr::Mul(lhs, rhs)
+        | Expr::Div(lhs, rhs)
+        | Expr::Pow(lhs, rhs) => {
+            collect_vars(lhs, out);
+            collect_vars(rhs, out);
+        }
+        Expr::Neg(inner) | Expr::Func(_, inner) => collect_vars(inner, out),
+        _ => {}
+    }
+}
+
+/// `Some(value)` where `e` is defined at `point`, `None` where it isn't, e.g. dividing by
+/// zero or taking the logarithm of a negative number.
+fn value_at(e: &Expr, point: &HashMap<char, f64>) -> Result<Option<f64>, String> {
+    match e.solve_for(point) {
+        Ok(value) if value.is_finite() => Ok(Some(value)),
+        Ok(_) => Ok(None),
+        Err(error @ EvalError::UnboundVariable(_)) => Err(error.to_string()),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Decides whether two expressions are equal by comparing them at random points. Points
+/// where either side is undefined are skipped, so expressions that only differ by a
+/// removable singularity, like `x/x` and `1`, count as equivalent. More generally, the
+/// expressions are only compared where both are defined: `ln(x^2)` and `2ln(x)` are
+/// equivalent although they differ for negative `x`, where only the first one is defined.
+#[derive(Debug, Clone)]
+pub struct Equivalence {
+    samples: usize,
+    tolerance: f64,
+    range: f64,
+    seed: u64,
+}
+
+impl Default for Equivalence {
+    fn default() -> Self {
+        Equivalence {
+            samples: DEFAULT_SAMPLES,
+            tolerance: DEFAULT_TOLERANCE,
+            range: DEFAULT_RANGE,
+            seed: DEFAULT_SEED,
+        }
+    }
+}
+
+impl Equivalence {
+    pub fn new() -> Self {
+        Equivalence::default()
+    }
+
+    /// Number of random points to evaluate both sides at.
+    pub fn samples(mut self, samples: usize) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    /// Largest difference allowed between both sides, relative to their magnitude once
+    /// that exceeds 1.
+    pub fn tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Variables are sampled uniformly from `[-range, range)`.
+    pub fn range(mut self, range: f64) -> Self {
+        self.range = range;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    fn close(&self, a: f64, b: f64) -> bool {
+        (a - b).abs() <= self.tolerance * a.abs().max(b.abs()).max(1.0)
+    }
+
+    /// Whether `a` and `b` are equal: they are if they simplify to the same expression, and
+    /// otherwise their values are compared at random points. Fails if fewer than a quarter
+    /// of the points could be compared, as then there is too little evidence either way.
+    pub fn check(&self, a: &Expr, b: &Expr) -> Result<bool, String> {
+        if a == b || simplify(a.clone()) == simplify(b.clone()) {
+            return Ok(true);
+        }
+        self.numerically(a, b)
+    }
+
+    /// [`Equivalence::check`] without trying to simplify either side first.
+    pub fn numerically(&self, a: &Expr, b: &Expr) -> Result<bool, String> {
+        let mut vars = BTreeSet::new();
+        collect_vars(a, &mut vars);
+        collect_vars(b, &mut vars);
+
+        let mut rng = SplitMix64::new(self.seed);
+        let mut compared = 0;
+        for _ in 0..self.samples {
+            let point: HashMap<char, f64> = vars
+                .iter()
+                .map(|&name| (name, rng.next_f64(self.range)))
+                .collect();
+            if let (Some(lhs), Some(rhs)) = (value_at(a, &point)?, value_at(b, &point)?) {
+                if !self.close(lhs, rhs) {
+                    return Ok(false);
+                }
+                compared += 1;
+            }
+        }
+        if self.samples > 0 && compared * 4 < self.samples {
+            return Err(format!(
+                "both expressions were defined at only {} of {} sample points",
+                compared, self.samples
+            ));
+        }
+        Ok(true)
+    }
+}
+
+/// Whether `a` and `b` are mathematically equal, as far as [`Equivalence::check`] with the
+/// default settings can tell. Expressions that can't be compared count as different.
+pub fn equivalent(a: &Expr, b: &Expr) -> bool {
+    Equivalence::default().check(a, b).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::syntax::*;
+
+    #[test]
+    fn equal_after_canonicalisation() {
+        let y = var('y');
+        assert!(equivalent(
+            &X.plus(y.clone()).times(num(2)),
+            &num(2).times(y).plus(num(2).times(X))
+        ));
+    }
+
+    #[test]
+    fn equal_only_numerically() {
+        // sin(x)^2 + cos(x)^2 and 1, (x + 1)^2 and x^2 + 2x + 1
+        let pythagoras = sin(X).pow(num(2)).plus(cos(X).pow(num(2)));
+        assert!(equivalent(&pythagoras, &num(1)));
+        let square = X.plus(num(1)).pow(num(2));
+        let expanded = X.pow(num(2)).plus(num(2).times(X)).plus(num(1));
+        assert_eq!(Equivalence::new().numerically(&square, &expanded), Ok(true));
+    }
+
+    #[test]
+    fn different_expressions() {
+        assert!(!equivalent(&X.pow(num(2)), &num(2).times(X)));
+        assert!(!equivalent(&sin(X), &cos(X)));
+        // equal at integer points only
+        assert!(!equivalent(&sin(PI.times(X)), &num(0)));
+    }
+
+    #[test]
+    fn undefined_points_are_skipped() {
+        assert!(equivalent(&X.div(X), &num(1)));
+        // ln(x) is undefined for half of the sampled points, and only the other half, where
+        // x > 0, is compared
+        let product = func("ln", X.pow(num(2)));
+        assert!(equivalent(&product, &num(2).times(func("ln", X))));
+        assert!(Equivalence::new()
+            .check(&func("ln", X.neg().pow(num(2)).neg()), &X)
+            .is_err());
+    }
+
+    #[test]
+    fn derivatives_match_hand_derived_ones() {
+        // d/dx x^2/(x + 1) = (x^2 + 2x)/(x + 1)^2
+        let e = X.pow(num(2)).div(X.plus(num(1)));
+        let der = e.derivative().with_respect_to('x').unwrap();
+        let expected = X
+            .pow(num(2))
+            .plus(num(2).times(X))
+            .div(X.plus(num(1)).pow(num(2)));
+        assert!(equivalent(&der, &expected));
+    }
+
+    #[test]
+    fn chain_rule_derivatives_match_hand_derived_ones() {
+        // d/dx x sin(x) = sin(x) + x cos(x)
+        let der = X.times(sin(X)).derivative().with_respect_to('x').unwrap();
+        assert!(equivalent(&der, &sin(X).plus(X.times(cos(X)))));
+        // d/dx 2^x sin(y) = ln(2) 2^x sin(y), where sin(y) is a constant factor
+        let y = var('y');
+        let e = num(2).pow(X).times(sin(y.clone()));
+        let der = e.derivative().with_respect_to('x').unwrap();
+        let expected = func("ln", num(2)).times(num(2).pow(X)).times(sin(y));
+        assert!(equivalent(&der, &expected));
+    }
+}
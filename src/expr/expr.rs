@@ -1,9 +1,17 @@
+use crate::expr::domain::{self, EvalError};
 use std::collections::HashMap;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Expr {
     Const(u32),
     E,
+    Pi,
+    Tau,
+    Phi,
+    I,
+    Inf,
+    /// A variable. The parser reads `e`, `i`, `π`, `τ` and `φ` as constants, so variables
+    /// with those names can't be parsed or printed.
     Var(char),
     Add(Box<Expr>, Box<Expr>),
     Sub(Box<Expr>, Box<Expr>),
@@ -18,7 +26,7 @@ impl Expr {
     pub fn is_effectively_constant(&self) -> bool {
         match self {
             Expr::Const(_) => true,
-            Expr::E => true,
+            Expr::E | Expr::Pi | Expr::Tau | Expr::Phi | Expr::I | Expr::Inf => true,
             Expr::Add(lhs, rhs) => lhs.is_effectively_constant() && rhs.is_effectively_constant(),
             Expr::Sub(lhs, rhs) => lhs.is_effectively_constant() && rhs.is_effectively_constant(),
             Expr::Mul(lhs, rhs) => lhs.is_effectively_constant() && rhs.is_effectively_constant(),
@@ -34,27 +42,49 @@ impl Expr {
         }
     }
 
-    pub fn solve_for(&self, vars: &HashMap<char, f64>) -> Result<f64, String> {
+    /// The value of the expression at `vars`. Operations outside of their domain, like
+    /// dividing by zero, fail with an [`EvalError::Domain`] naming the operation.
+    pub fn solve_for(&self, vars: &HashMap<char, f64>) -> Result<f64, EvalError> {
         match self {
             Expr::Const(n) => Ok(f64::from(*n)),
             Expr::Var(name) => vars
                 .get(name)
-                .map(|&x| x)
-                .ok_or(format!("could not find variable [{}]", name)),
+                .copied()
+                .ok_or(EvalError::UnboundVariable(*name)),
             Expr::E => Ok(std::f64::consts::E),
-            Expr::Add(lhs, rhs) => Ok(lhs.solve_for(&vars)? + rhs.solve_for(&vars)?),
-            Expr::Sub(lhs, rhs) => Ok(lhs.solve_for(&vars)? - rhs.solve_for(&vars)?),
-            Expr::Mul(lhs, rhs) => Ok(lhs.solve_for(&vars)? * rhs.solve_for(&vars)?),
-            Expr::Div(lhs, rhs) => Ok(lhs.solve_for(&vars)? / rhs.solve_for(&vars)?),
-            Expr::Pow(lhs, rhs) => Ok(lhs.solve_for(&vars)?.powf(rhs.solve_for(&vars)?)),
-            Expr::Neg(e) => Ok(-(e.solve_for(&vars)?)),
-            Expr::Func(name, arg) => match name.as_str() {
-                "sin" => Ok(arg.solve_for(&vars)?.sin()),
-                "cos" => Ok(arg.solve_for(&vars)?.cos()),
-                "tan" => Ok(arg.solve_for(&vars)?.tan()),
-                "cot" => Ok(1.0 / arg.solve_for(&vars)?.tan()),
-                _ => Err(format!("Unrecognized function [{}({:?})]", name, arg)),
-            },
+            Expr::Pi => Ok(std::f64::consts::PI),
+            Expr::Tau => Ok(std::f64::consts::TAU),
+            Expr::Phi => Ok((1.0 + 5f64.sqrt()) / 2.0),
+            Expr::I => Err("imaginary unit [i] has no real value".to_string().into()),
+            Expr::Inf => Ok(f64::INFINITY),
+            Expr::Add(lhs, rhs) => Ok(lhs.solve_for(vars)? + rhs.solve_for(vars)?),
+            Expr::Sub(lhs, rhs) => Ok(lhs.solve_for(vars)? - rhs.solve_for(vars)?),
+            Expr::Mul(lhs, rhs) => Ok(lhs.solve_for(vars)? * rhs.solve_for(vars)?),
+            Expr::Div(lhs, rhs) => {
+                let (dividend, divisor) = (lhs.solve_for(vars)?, rhs.solve_for(vars)?);
+                domain::check(self, &[dividend, divisor])?;
+                Ok(dividend / divisor)
+            }
+            Expr::Pow(lhs, rhs) => {
+                let (base, exponent) = (lhs.solve_for(vars)?, rhs.solve_for(vars)?);
+                domain::check(self, &[base, exponent])?;
+                Ok(domain::real_pow(base, exponent, domain::odd_root(rhs)))
+            }
+            Expr::Neg(e) => Ok(-(e.solve_for(vars)?)),
+            Expr::Func(name, arg) => {
+                let x = arg.solve_for(vars)?;
+                domain::check(self, &[x])?;
+                match name.as_str() {
+                    "sin" => Ok(x.sin()),
+                    "cos" => Ok(x.cos()),
+                    "tan" => Ok(x.tan()),
+                    "cot" => Ok(1.0 / x.tan()),
+                    "ln" => Ok(x.ln()),
+                    "exp" => Ok(x.exp()),
+                    "sqrt" => Ok(x.sqrt()),
+                    _ => Err(format!("Unrecognized function [{}({:?})]", name, arg).into()),
+                }
+            }
         }
     }
 }
@@ -0,0 +1,540 @@
+use crate::expr::domain;
+use crate::expr::Expr;
+use std::collections::HashMap;
+use std::f64::consts::{FRAC_PI_2, PI, TAU};
+use std::fmt::{Display, Formatter};
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// A closed set of reals `[lo, hi]`, possibly unbounded, used to enclose every value an
+/// expression takes over a box of inputs. Results are rounded outwards, so they contain the
+/// exact result and not just the one floating point arithmetic gives.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    lo: f64,
+    hi: f64,
+}
+
+impl Interval {
+    pub const ENTIRE: Interval = Interval {
+        lo: f64::NEG_INFINITY,
+        hi: f64::INFINITY,
+    };
+
+    /// # Panics
+    /// If `lo > hi` or either is NaN.
+    pub fn new(lo: f64, hi: f64) -> Self {
+        assert!(lo <= hi, "not an interval: [{}, {}]", lo, hi);
+        Interval { lo, hi }
+    }
+
+    pub fn point(x: f64) -> Self {
+        Interval::new(x, x)
+    }
+
+    pub fn lo(&self) -> f64 {
+        self.lo
+    }
+
+    pub fn hi(&self) -> f64 {
+        self.hi
+    }
+
+    pub fn width(&self) -> f64 {
+        self.hi - self.lo
+    }
+
+    pub fn contains(&self, x: f64) -> bool {
+        self.lo <= x && x <= self.hi
+    }
+
+    /// Whether every value in the interval is positive.
+    pub fn is_positive(&self) -> bool {
+        self.lo > 0.0
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.hi < 0.0
+    }
+
+    /// The smallest interval containing both.
+    pub fn hull(self, that: Interval) -> Self {
+        Interval::new(self.lo.min(that.lo), self.hi.max(that.hi))
+    }
+
+    /// An interval from computed bounds, where a NaN bound comes from an indeterminate
+    /// form like `inf - inf` and could be anything, so is unbounded on its side.
+    fn enclosing(lo: f64, hi: f64) -> Self {
+        let lo = if lo.is_nan() { f64::NEG_INFINITY } else { lo };
+        let hi = if hi.is_nan() { f64::INFINITY } else { hi };
+        Interval::new(lo, hi)
+    }
+
+    /// Widened by one unit in the last place on either side, to account for the rounding
+    /// of the operation that produced the bounds.
+    fn rounded_out(lo: f64, hi: f64) -> Self {
+        Interval::enclosing(lo.next_down(), hi.next_up())
+    }
+
+    /// [`Interval::rounded_out`] with an extra unit in the last place for functions like
+    /// `sin` whose results the standard library doesn't round correctly.
+    fn rounded_out_twice(lo: f64, hi: f64) -> Self {
+        Interval::enclosing(lo.next_down().next_down(), hi.next_up().next_up())
+    }
+
+    fn recip(self) -> Result<Self, String> {
+        Interval::point(1.0).checked_div(self)
+    }
+
+    /// Division, which is unbounded where the divisor contains zero: `[1, 2] / [0, 1]` is
+    /// `[1, inf]` and `[1, 2] / [-1, 1]` the entire line, the hull of its two pieces.
+    pub fn checked_div(self, divisor: Interval) -> Result<Self, String> {
+        if divisor.lo == 0.0 && divisor.hi == 0.0 {
+            return Err("division by an interval only containing zero".into());
+        }
+        if !divisor.contains(0.0) {
+            let quotients = [
+                self.lo / divisor.lo,
+                self.lo / divisor.hi,
+                self.hi / divisor.lo,
+                self.hi / divisor.hi,
+            ];
+            return Ok(Self::hull_of(&quotients));
+        }
+        let inf = f64::INFINITY;
+        Ok(
+            if self.contains(0.0) || (divisor.lo < 0.0 && divisor.hi > 0.0) {
+                Interval::ENTIRE
+            } else if self.is_positive() && divisor.lo == 0.0 {
+                Interval::enclosing((self.lo / divisor.hi).next_down(), inf)
+            } else if self.is_positive() {
+                Interval::enclosing(-inf, (self.lo / divisor.lo).next_up())
+            } else if divisor.lo == 0.0 {
+                Interval::enclosing(-inf, (self.hi / divisor.hi).next_up())
+            } else {
+                Interval::enclosing((self.hi / divisor.lo).next_down(), inf)
+            },
+        )
+    }
+
+    /// Bounds of the results of one operation on different operands, rounded outwards.
+    /// NaN results come from indeterminate forms like `0 * inf` or `inf / inf`, which could
+    /// be anything, so make the bounds unbounded like in [`Interval::enclosing`].
+    fn hull_of(values: &[f64]) -> Self {
+        if values.iter().any(|v| v.is_nan()) {
+            return Interval::ENTIRE;
+        }
+        let lo = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let hi = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        Interval::rounded_out(lo, hi)
+    }
+
+    fn powi(self, n: i32) -> Result<Self, String> {
+        if n < 0 {
+            return self.powi(-n)?.recip();
+        }
+        let n = n as u32;
+        if n == 0 {
+            return Ok(Interval::point(1.0));
+        }
+        let pow_lo = |x: f64| power_bounds(x, n).0;
+        let pow_hi = |x: f64| power_bounds(x, n).1;
+        Ok(if self.lo >= 0.0 {
+            Interval::new(pow_lo(self.lo), pow_hi(self.hi))
+        } else if n % 2 == 1 {
+            // odd powers are increasing, and negative bases mirror positive ones
+            let lo = -pow_hi(-self.lo);
+            let hi = if self.hi >= 0.0 {
+                pow_hi(self.hi)
+            } else {
+                -pow_lo(-self.hi)
+            };
+            Interval::new(lo, hi)
+        } else if self.hi <= 0.0 {
+            Interval::new(pow_lo(-self.hi), pow_hi(-self.lo))
+        } else {
+            // even powers have their minimum at zero
+            Interval::new(0.0, pow_hi(self.lo.abs().max(self.hi)))
+        })
+    }
+
+    pub fn pow(self, exponent: Interval) -> Result<Self, String> {
+        if exponent.lo == exponent.hi && exponent.lo.fract() == 0.0 && exponent.lo.abs() < 1e9 {
+            return self.powi(exponent.lo as i32);
+        }
+        // non-integer powers are only real for nonnegative bases
+        if self.hi < 0.0 {
+            return Err(format!(
+                "power with a non-integer exponent of negative base {}",
+                self
+            ));
+        }
+        let base = Interval::new(self.lo.max(0.0), self.hi);
+        // monotonic in both base and exponent, so the bounds are at the corners
+        let corners = [
+            base.lo.powf(exponent.lo),
+            base.lo.powf(exponent.hi),
+            base.hi.powf(exponent.lo),
+            base.hi.powf(exponent.hi),
+        ];
+        let hull = Self::hull_of(&corners);
+        Ok(Interval::rounded_out_twice(hull.lo, hull.hi))
+    }
+
+    /// [`Interval::pow`] for an exponent `p/q` with an odd `q`, which is real for negative
+    /// bases too as `(-1)^p |x|^(p/q)`: the hull of the powers of the nonnegative part and
+    /// of the mirrored negative part.
+    fn odd_root(self, exponent: Interval, odd_numerator: bool) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        if self.hi >= 0.0 {
+            parts.push(Interval::new(self.lo.max(0.0), self.hi).pow(exponent)?);
+        }
+        if self.lo < 0.0 {
+            let mirrored = Interval::new((-self.hi).max(0.0), -self.lo).pow(exponent)?;
+            parts.push(if odd_numerator { -mirrored } else { mirrored });
+        }
+        Ok(parts
+            .into_iter()
+            .reduce(Interval::hull)
+            .expect("lo <= hi, so one of the parts is there"))
+    }
+
+    /// Whether `lo <= offset + k period <= hi` for some integer `k`, erring on the side of
+    /// yes when the bounds are too close to tell.
+    fn contains_periodic(&self, offset: f64, period: f64) -> bool {
+        let slack = 1e-12 * (1.0 + self.lo.abs().max(self.hi.abs()));
+        let first = ((self.lo - slack - offset) / period).ceil();
+        let last = ((self.hi + slack - offset) / period).floor();
+        first <= last
+    }
+
+    /// `f` on an interval of width less than a period, extended to the maximum and minimum
+    /// of 1 and -1 where the interval contains the points `f` takes them at.
+    fn periodic(self, f: fn(f64) -> f64, max_at: f64) -> Self {
+        if self.width() >= TAU || !self.width().is_finite() {
+            return Interval::new(-1.0, 1.0);
+        }
+        let (a, b) = (f(self.lo), f(self.hi));
+        let hi = if self.contains_periodic(max_at, TAU) {
+            1.0
+        } else {
+            a.max(b).next_up().next_up().min(1.0)
+        };
+        let lo = if self.contains_periodic(max_at + PI, TAU) {
+            -1.0
+        } else {
+            a.min(b).next_down().next_down().max(-1.0)
+        };
+        Interval::new(lo, hi)
+    }
+
+    pub fn sin(self) -> Self {
+        self.periodic(f64::sin, FRAC_PI_2)
+    }
+
+    pub fn cos(self) -> Self {
+        self.periodic(f64::cos, 0.0)
+    }
+
+    /// Increasing between its poles, so the entire line unless the interval lies between
+    /// two of them.
+    pub fn tan(self) -> Self {
+        if self.width() >= PI || self.contains_periodic(FRAC_PI_2, PI) {
+            return Interval::ENTIRE;
+        }
+        Interval::rounded_out_twice(self.lo.tan(), self.hi.tan())
+    }
+
+    /// Decreasing between its poles at multiples of pi.
+    pub fn cot(self) -> Self {
+        if self.width() >= PI || self.contains_periodic(0.0, PI) {
+            return Interval::ENTIRE;
+        }
+        let cot = |x: f64| 1.0 / x.tan();
+        Interval::rounded_out_twice(cot(self.hi), cot(self.lo))
+    }
+
+    pub fn ln(self) -> Result<Self, String> {
+        if self.hi <= 0.0 {
+            return Err(format!("logarithm of nonpositive interval {}", self));
+        }
+        let lo = if self.lo <= 0.0 {
+            f64::NEG_INFINITY
+        } else {
+            self.lo.ln()
+        };
+        Ok(Interval::rounded_out_twice(lo, self.hi.ln()))
+    }
+
+    pub fn exp(self) -> Self {
+        let rounded = Interval::rounded_out_twice(self.lo.exp(), self.hi.exp());
+        Interval::new(rounded.lo.max(0.0), rounded.hi)
+    }
+
+    pub fn sqrt(self) -> Result<Self, String> {
+        if self.hi < 0.0 {
+            return Err(format!("square root of negative interval {}", self));
+        }
+        let lo = self.lo.max(0.0).sqrt().next_down().max(0.0);
+        Ok(Interval::new(lo, self.hi.sqrt().next_up()))
+    }
+}
+
+/// Lower and upper bounds of `x^n` for `x >= 0`, by squaring and multiplying with each
+/// product rounded outwards, so that the bounds hold however large `n` is.
+fn power_bounds(x: f64, n: u32) -> (f64, f64) {
+    let (mut lo, mut hi) = (1.0, 1.0);
+    let (mut base_lo, mut base_hi) = (x, x);
+    let mut n = n;
+    loop {
+        if n % 2 == 1 {
+            lo = (lo * base_lo).next_down().max(0.0);
+            hi = (hi * base_hi).next_up();
+        }
+        n /= 2;
+        if n == 0 {
+            return (lo, hi);
+        }
+        base_lo = (base_lo * base_lo).next_down().max(0.0);
+        base_hi = (base_hi * base_hi).next_up();
+    }
+}
+
+impl Add for Interval {
+    type Output = Interval;
+
+    fn add(self, rhs: Interval) -> Interval {
+        Interval::rounded_out(self.lo + rhs.lo, self.hi + rhs.hi)
+    }
+}
+
+impl Sub for Interval {
+    type Output = Interval;
+
+    fn sub(self, rhs: Interval) -> Interval {
+        Interval::rounded_out(self.lo - rhs.hi, self.hi - rhs.lo)
+    }
+}
+
+impl Mul for Interval {
+    type Output = Interval;
+
+    fn mul(self, rhs: Interval) -> Interval {
+        Interval::hull_of(&[
+            self.lo * rhs.lo,
+            self.lo * rhs.hi,
+            self.hi * rhs.lo,
+            self.hi * rhs.hi,
+        ])
+    }
+}
+
+impl Neg for Interval {
+    type Output = Interval;
+
+    fn neg(self) -> Interval {
+        Interval::new(-self.hi, -self.lo)
+    }
+}
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}, {}]", self.lo, self.hi)
+    }
+}
+
+/// An interval containing every value `e` takes where its variables range over `vars`,
+/// mirroring [`Expr::solve_for`]. Fails where `e` is undefined on all of the box, e.g. the
+/// logarithm of a negative interval; where it's only undefined on part of it, the enclosure
+/// is of the part where it is defined.
+pub fn enclose(e: &Expr, vars: &HashMap<char, Interval>) -> Result<Interval, String> {
+    let eval = |e: &Expr| enclose(e, vars);
+    match e {
+        Expr::Const(n) => Ok(Interval::point(f64::from(*n))),
+        Expr::Var(name) => vars
+            .get(name)
+            .copied()
+            .ok_or(format!("could not find variable [{}]", name)),
+        Expr::Inf => Ok(Interval::point(f64::INFINITY)),
+        Expr::Add(lhs, rhs) => Ok(eval(lhs)? + eval(rhs)?),
+        Expr::Sub(lhs, rhs) => Ok(eval(lhs)? - eval(rhs)?),
+        Expr::Mul(lhs, rhs) => Ok(eval(lhs)? * eval(rhs)?),
+        Expr::Div(lhs, rhs) => eval(lhs)?.checked_div(eval(rhs)?),
+        Expr::Pow(lhs, rhs) => match domain::odd_root(rhs) {
+            Some(r) => eval(lhs)?.odd_root(eval(rhs)?, r.numer() % 2 != 0),
+            None => eval(lhs)?.pow(eval(rhs)?),
+        },
+        Expr::Neg(inner) => Ok(-eval(inner)?),
+        Expr::Func(name, arg) => match name.as_str() {
+            "sin" => Ok(eval(arg)?.sin()),
+            "cos" => Ok(eval(arg)?.cos()),
+            "tan" => Ok(eval(arg)?.tan()),
+            "cot" => Ok(eval(arg)?.cot()),
+            "ln" => eval(arg)?.ln(),
+            "exp" => Ok(eval(arg)?.exp()),
+            "sqrt" => eval(arg)?.sqrt(),
+            _ => Err(format!("Unrecognized function [{}({:?})]", name, arg)),
+        },
+        // irrational constants aren't representable, their floating point value is within
+        // an ulp
+        constant => {
+            let value = constant.solve_for(&HashMap::new())?;
+            Ok(Interval::rounded_out(value, value))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::bigfloat::BigFloat;
+    use crate::expr::syntax::*;
+    use crate::test_util::SplitMix64;
+
+    fn on(lo: f64, hi: f64) -> HashMap<char, Interval> {
+        HashMap::from([('x', Interval::new(lo, hi))])
+    }
+
+    #[test]
+    fn enclosures_contain_every_sampled_value() {
+        let exprs = [
+            X.pow(num(2)).minus(num(2).times(X)),
+            sin(X).times(cos(X.times(num(3)))).plus(Expr::Pi),
+            func("exp", X.neg()).div(X.pow(num(2)).plus(num(1))),
+            func("sqrt", X.plus(num(3))).minus(tan(X.div(num(4)))),
+            X.pow(num(3)).neg().plus(func("cot", X.plus(num(2)))),
+        ];
+        for e in exprs {
+            let range = e.range_over(&on(-1.5, 0.7)).unwrap();
+            for i in 0..=1000 {
+                let x = -1.5 + 2.2 * f64::from(i) / 1000.0;
+                let value = e.solve_for(&[('x', x)].into()).unwrap();
+                assert!(range.contains(value), "{:?} at {} not in {}", e, x, range);
+            }
+        }
+    }
+
+    #[test]
+    fn derivative_is_provably_positive() {
+        // d/dx (x^3 + x) = 3x^2 + 1 > 0 on [-2, 2]
+        let der = X
+            .pow(num(3))
+            .plus(X)
+            .derivative()
+            .with_respect_to('x')
+            .unwrap();
+        assert!(der.range_over(&on(-2.0, 2.0)).unwrap().is_positive());
+    }
+
+    #[test]
+    fn powers() {
+        let square = X.pow(num(2)).range_over(&on(-2.0, 1.0)).unwrap();
+        assert_eq!(square.lo(), 0.0);
+        assert!(square.contains(4.0) && square.hi() < 4.0 + 1e-12);
+        let cube = X.pow(num(3)).range_over(&on(-2.0, 1.0)).unwrap();
+        assert!(cube.contains(-8.0) && cube.contains(1.0));
+        let root = X
+            .pow(num(1).div(num(2)))
+            .range_over(&on(-1.0, 4.0))
+            .unwrap();
+        assert!(root.contains(0.0) && root.contains(2.0) && root.hi() < 2.0 + 1e-12);
+        assert!(X
+            .pow(num(1).div(num(2)))
+            .range_over(&on(-2.0, -1.0))
+            .is_err());
+        // odd roots are real for negative bases, like with solve_for
+        let cube_root = X
+            .pow(num(1).div(num(3)))
+            .range_over(&on(-8.0, 1.0))
+            .unwrap();
+        assert!(cube_root.contains(-2.0) && cube_root.contains(1.0) && cube_root.lo() > -2.001);
+        let even = X
+            .pow(num(2).div(num(3)))
+            .range_over(&on(-8.0, -1.0))
+            .unwrap();
+        assert!(even.contains(1.0) && even.contains(4.0) && even.lo() > 0.99);
+    }
+
+    #[test]
+    fn high_powers_enclose_the_exact_value() {
+        let precision = 256;
+        let mut rng = SplitMix64::new(41);
+        let mut cases = vec![(1.0001095786059855, 438)];
+        for _ in 0..300 {
+            let x = 1.0 + (rng.next_u64() % 1_000_000) as f64 * 1e-9;
+            let n = 1 + (rng.next_u64() % 1000) as i32;
+            cases.push((if rng.next_u64() & 1 == 0 { x } else { -x }, n));
+        }
+        for (x, n) in cases {
+            let power = Interval::point(x).powi(n).unwrap();
+            let exact = BigFloat::from_f64(x, precision)
+                .unwrap()
+                .pow(&BigFloat::from_integer(n.into(), precision))
+                .unwrap();
+            let bound = |b: f64| BigFloat::from_f64(b, precision).unwrap();
+            assert!(
+                !exact.sub(&bound(power.lo())).is_negative()
+                    && !bound(power.hi()).sub(&exact).is_negative(),
+                "{}^{} = {} is not in {}",
+                x,
+                n,
+                exact,
+                power
+            );
+            assert!(power.width() <= 1e-12 * power.hi().abs());
+        }
+    }
+
+    #[test]
+    fn division_by_intervals_containing_zero() {
+        let recip = num(1).div(X);
+        let upper = recip.range_over(&on(0.0, 2.0)).unwrap();
+        assert!(upper.lo() <= 0.5 && upper.lo() > 0.49 && upper.hi() == f64::INFINITY);
+        let lower = recip.range_over(&on(-2.0, 0.0)).unwrap();
+        assert!(lower.lo() == f64::NEG_INFINITY && lower.hi() >= -0.5);
+        assert_eq!(recip.range_over(&on(-1.0, 1.0)), Ok(Interval::ENTIRE));
+        assert!(recip.range_over(&on(0.0, 0.0)).is_err());
+    }
+
+    #[test]
+    fn indeterminate_bounds_are_unbounded() {
+        let shifted = num(1).div(X).plus(Expr::Inf);
+        assert_eq!(shifted.range_over(&on(-2.0, 0.0)), Ok(Interval::ENTIRE));
+        let log = func("ln", X).plus(Expr::Inf);
+        assert_eq!(log.range_over(&on(0.0, 1.0)), Ok(Interval::ENTIRE));
+        assert_eq!(
+            Expr::Inf.minus(Expr::Inf).range_over(&on(0.0, 1.0)),
+            Ok(Interval::ENTIRE)
+        );
+        assert_eq!(
+            tan(Expr::Inf).range_over(&on(0.0, 1.0)),
+            Ok(Interval::ENTIRE)
+        );
+        let indeterminate = [
+            Expr::Inf.div(Expr::Inf),
+            num(0).times(Expr::Inf),
+            X.times(Expr::Inf),
+        ];
+        for e in indeterminate {
+            assert_eq!(e.range_over(&on(0.0, 1.0)), Ok(Interval::ENTIRE), "{:?}", e);
+        }
+    }
+
+    #[test]
+    fn trigonometric_functions_are_split_into_monotonic_pieces() {
+        // sin rises to 1 at pi/2 and falls again
+        let rising_and_falling = sin(X).range_over(&on(1.0, 2.0)).unwrap();
+        assert_eq!(rising_and_falling.hi(), 1.0);
+        assert!(rising_and_falling.lo() <= 1f64.sin() && rising_and_falling.lo() > 0.84);
+        let monotonic = cos(X).range_over(&on(0.5, 1.0)).unwrap();
+        assert!(monotonic.width() < 0.4);
+        assert_eq!(
+            cos(X).range_over(&on(-10.0, 10.0)),
+            Ok(Interval::new(-1.0, 1.0))
+        );
+        assert_eq!(tan(X).range_over(&on(1.0, 2.0)), Ok(Interval::ENTIRE));
+        assert!(tan(X)
+            .range_over(&on(-1.0, 1.0))
+            .unwrap()
+            .contains(1f64.tan()));
+    }
+}
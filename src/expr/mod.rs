@@ -1,11 +1,33 @@
+pub mod assumptions;
+pub mod autodiff;
+pub mod bigfloat;
+pub mod compile;
+pub mod complex;
+pub mod dag;
 mod derivative;
+pub mod domain;
+pub mod egraph;
+pub mod equivalence;
+#[allow(clippy::module_inception)]
 pub mod expr;
+pub mod interval;
+pub(crate) mod natural;
+pub mod rational;
+pub mod rewrite;
+pub(crate) mod rng;
 mod simplify;
+mod subs;
+pub(crate) mod terms;
+mod trig;
+pub mod uncertainty;
 
 pub mod syntax {
+    use crate::expr::assumptions::{Assumptions, Conditional};
     use crate::expr::derivative::Derivative;
-    use crate::expr::simplify::simplify;
+    use crate::expr::rewrite::{Rewriter, Rule};
+    use crate::expr::simplify::{simplify, simplify_assuming, simplify_with};
     use crate::expr::Expr;
+    use std::collections::{BTreeMap, HashMap};
 
     pub fn num(n: u32) -> Expr {
         Expr::Const(n)
@@ -13,6 +35,16 @@ pub mod syntax {
 
     pub const E: Expr = Expr::E;
 
+    pub const PI: Expr = Expr::Pi;
+
+    pub const TAU: Expr = Expr::Tau;
+
+    pub const PHI: Expr = Expr::Phi;
+
+    pub const I: Expr = Expr::I;
+
+    pub const INF: Expr = Expr::Inf;
+
     pub fn var(name: char) -> Expr {
         Expr::Var(name)
     }
@@ -35,6 +67,7 @@ pub mod syntax {
 
     pub const X: Expr = Expr::Var('x');
 
+    #[allow(clippy::should_implement_trait)]
     impl Expr {
         pub fn plus(self, that: Expr) -> Self {
             Expr::Add(Box::new(self), Box::new(that))
@@ -67,7 +100,192 @@ pub mod syntax {
             simplify(self)
         }
 
-        pub fn derivative(&self) -> Derivative {
+        /// [`Expr::simplified`] with domain specific `rules` tried before the built-in ones.
+        pub fn simplified_with(self, rules: &[Rule]) -> Self {
+            simplify_with(self, rules)
+        }
+
+        /// The smallest equivalent expression found by equality saturation with the default
+        /// rules, see [`Saturation`] to choose the rules, cost model and limits.
+        ///
+        /// [`Saturation`]: crate::expr::egraph::Saturation
+        pub fn saturated(self) -> Self {
+            crate::expr::egraph::Saturation::default().simplify(&self)
+        }
+
+        /// [`Expr::simplified`] using only what follows from `assumptions`, along with the
+        /// conditions the result relies on.
+        pub fn simplified_assuming(self, assumptions: &Assumptions) -> Conditional {
+            simplify_assuming(self, assumptions)
+        }
+
+        /// Whether both expressions are mathematically equal, see [`equivalent`].
+        ///
+        /// [`equivalent`]: crate::expr::equivalence::equivalent
+        pub fn equivalent_to(&self, that: &Expr) -> bool {
+            crate::expr::equivalence::equivalent(self, that)
+        }
+
+        pub fn rewritten(self, rewriter: &Rewriter) -> Self {
+            rewriter.rewrite(self)
+        }
+
+        /// [`Expr::simplified`] followed by the Pythagorean, double-angle, sum/difference and
+        /// parity identities and exact values at multiples of pi/6 and pi/4.
+        pub fn trig_simplified(self) -> Self {
+            crate::expr::trig::simplify_trig(self)
+        }
+
+        /// [`Expr::trig_simplified`] using only what follows from `assumptions`, along with
+        /// the conditions the result relies on.
+        pub fn trig_simplified_assuming(self, assumptions: &Assumptions) -> Conditional {
+            crate::expr::trig::simplify_trig_assuming(self, assumptions)
+        }
+
+        /// Splits sines and cosines of sums and doubled arguments into products of their parts.
+        pub fn trig_expanded(self) -> Self {
+            crate::expr::trig::expand_trig(self)
+        }
+
+        pub fn expanded(self) -> Result<Self, String> {
+            crate::poly::expand(&self)
+        }
+
+        pub fn collected(self, var: char) -> Result<Self, String> {
+            crate::poly::collect(&self, var)
+        }
+
+        pub fn factored(self) -> Result<Self, String> {
+            crate::poly::factor(&self)
+        }
+
+        pub fn together(self) -> Result<Self, String> {
+            crate::poly::together(&self)
+        }
+
+        pub fn apart(self, var: char) -> Result<Self, String> {
+            crate::poly::apart(&self, var)
+        }
+
+        /// Replaces every occurrence of `var` with `with`.
+        pub fn subs(self, var: char, with: Expr) -> Self {
+            crate::expr::subs::substitute(&self, &BTreeMap::from([(var, with)]))
+        }
+
+        /// Replaces the variables in `substitutions` simultaneously, e.g. swapping two of them.
+        pub fn subs_all(self, substitutions: &BTreeMap<char, Expr>) -> Self {
+            crate::expr::subs::substitute(&self, substitutions)
+        }
+
+        pub fn renamed(self, from: char, to: char) -> Self {
+            self.subs(from, Expr::Var(to))
+        }
+
+        /// Replaces every occurrence of the subexpression `target` with `with`.
+        pub fn replaced(self, target: &Expr, with: Expr) -> Self {
+            crate::expr::subs::replace(&self, target, &with)
+        }
+
+        /// Binds only the variables in `values` and folds the constants that leaves, unlike
+        /// [`Expr::solve_for`] which needs a value for every variable.
+        pub fn evaluated_partially(self, values: &BTreeMap<char, Expr>) -> Self {
+            crate::expr::subs::evaluate_partially(&self, values)
+        }
+
+        /// Compiles to bytecode reading the value of `vars[i]` from slot `i`, for evaluating
+        /// the expression many times, see [`Compiled`].
+        ///
+        /// [`Compiled`]: crate::expr::compile::Compiled
+        pub fn compile(&self, vars: &[char]) -> Result<crate::expr::compile::Compiled, String> {
+            crate::expr::compile::Compiled::new(self, vars)
+        }
+
+        /// The expression with every repeated compound subexpression bound to a variable,
+        /// see [`Dag`].
+        ///
+        /// [`Dag`]: crate::expr::dag::Dag
+        pub fn cse(&self) -> crate::expr::dag::LetForm {
+            let mut dag = crate::expr::dag::Dag::new();
+            let root = dag.add(self);
+            dag.eliminate_common_subexpressions(root)
+        }
+
+        /// The value at `vars` together with the derivative with respect to `var`, computed
+        /// with dual numbers instead of a derivative expression.
+        pub fn value_and_derivative(
+            &self,
+            vars: &HashMap<char, f64>,
+            var: char,
+        ) -> Result<crate::expr::autodiff::Dual, String> {
+            crate::expr::autodiff::forward(self, vars, var)
+        }
+
+        /// The partial derivatives with respect to every variable in `vars`, evaluated there.
+        pub fn gradient_at(
+            &self,
+            vars: &HashMap<char, f64>,
+        ) -> Result<BTreeMap<char, f64>, String> {
+            crate::expr::autodiff::gradient(self, vars)
+        }
+
+        /// The value at `vars` together with the partial derivatives with respect to all of
+        /// them, by reverse-mode differentiation, which unlike [`Expr::gradient_at`] takes
+        /// the same time however many variables there are.
+        pub fn value_and_gradient(
+            &self,
+            vars: &HashMap<char, f64>,
+        ) -> Result<(f64, BTreeMap<char, f64>), String> {
+            crate::expr::autodiff::reverse(self, vars)
+        }
+
+        /// An interval guaranteed to contain every value the expression takes where its
+        /// variables range over the intervals in `vars`.
+        pub fn range_over(
+            &self,
+            vars: &HashMap<char, crate::expr::interval::Interval>,
+        ) -> Result<crate::expr::interval::Interval, String> {
+            crate::expr::interval::enclose(self, vars)
+        }
+
+        /// [`Expr::solve_for`] over the complex numbers, taking principal branches.
+        pub fn solve_complex(
+            &self,
+            vars: &HashMap<char, crate::expr::complex::Complex>,
+        ) -> Result<crate::expr::complex::Complex, String> {
+            crate::expr::complex::solve_complex(self, vars)
+        }
+
+        /// [`Expr::solve_for`] to `digits` significant decimal digits, see [`BigFloat`] and
+        /// [`evaluate_to_digits`].
+        ///
+        /// [`BigFloat`]: crate::expr::bigfloat::BigFloat
+        /// [`evaluate_to_digits`]: crate::expr::bigfloat::evaluate_to_digits
+        pub fn solve_precise(
+            &self,
+            vars: &HashMap<char, crate::expr::bigfloat::BigFloat>,
+            digits: u32,
+        ) -> Result<crate::expr::bigfloat::BigFloat, crate::expr::domain::EvalError> {
+            crate::expr::bigfloat::evaluate_to_digits(self, vars, digits)
+        }
+
+        /// The conditions under which the expression has a real value, see
+        /// [`domain`](crate::expr::domain::domain).
+        pub fn domain(&self) -> std::collections::BTreeSet<crate::expr::assumptions::Condition> {
+            crate::expr::domain::domain(self)
+        }
+
+        /// The value and first-order propagated standard deviation for independent `inputs`,
+        /// see [`Propagation`](crate::expr::uncertainty::Propagation) for correlated ones.
+        pub fn propagate_uncertainty(
+            &self,
+            inputs: &BTreeMap<char, crate::expr::uncertainty::Measurement>,
+        ) -> Result<crate::expr::uncertainty::Measurement, String> {
+            crate::expr::uncertainty::Propagation::new()
+                .inputs(inputs)
+                .propagate(self)
+        }
+
+        pub fn derivative(&self) -> Derivative<'_> {
             Derivative { expr: self }
         }
     }
@@ -0,0 +1,361 @@
+use std::cmp::Ordering;
+
+/// An arbitrarily large unsigned integer, the mantissa of a [`BigFloat`]. Stored as base
+/// 2^32 limbs, least significant first, without leading zero limbs.
+///
+/// [`BigFloat`]: crate::expr::bigfloat::BigFloat
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Natural {
+    limbs: Vec<u32>,
+}
+
+impl Natural {
+    pub fn zero() -> Self {
+        Natural::default()
+    }
+
+    fn from_limbs(mut limbs: Vec<u32>) -> Self {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        Natural { limbs }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    /// Number of bits up to and including the highest set one.
+    pub fn bits(&self) -> u64 {
+        match self.limbs.last() {
+            None => 0,
+            Some(top) => 32 * self.limbs.len() as u64 - u64::from(top.leading_zeros()),
+        }
+    }
+
+    /// Number of zero bits below the lowest set one, 0 for zero.
+    pub fn trailing_zeros(&self) -> u64 {
+        match self.limbs.iter().position(|&limb| limb != 0) {
+            None => 0,
+            Some(i) => 32 * i as u64 + u64::from(self.limbs[i].trailing_zeros()),
+        }
+    }
+
+    pub fn bit(&self, i: u64) -> bool {
+        self.limbs
+            .get((i / 32) as usize)
+            .is_some_and(|limb| limb >> (i % 32) & 1 == 1)
+    }
+
+    /// Whether any of the lowest `n` bits is set.
+    pub fn low_bits_nonzero(&self, n: u64) -> bool {
+        let whole = ((n / 32) as usize).min(self.limbs.len());
+        if self.limbs[..whole].iter().any(|&limb| limb != 0) {
+            return true;
+        }
+        let rest = n % 32;
+        rest > 0
+            && self
+                .limbs
+                .get(whole)
+                .is_some_and(|&limb| limb & ((1 << rest) - 1) != 0)
+    }
+
+    /// The lowest 64 bits.
+    pub fn low_u64(&self) -> u64 {
+        let limb = |i: usize| u64::from(self.limbs.get(i).copied().unwrap_or(0));
+        limb(0) | limb(1) << 32
+    }
+
+    pub fn pow10(n: u32) -> Self {
+        let mut result = Natural::from(1);
+        for _ in 0..n {
+            result = result.mul_small(10);
+        }
+        result
+    }
+
+    pub fn add(&self, that: &Natural) -> Natural {
+        let (long, short) = if self.limbs.len() >= that.limbs.len() {
+            (self, that)
+        } else {
+            (that, self)
+        };
+        let mut limbs = Vec::with_capacity(long.limbs.len() + 1);
+        let mut carry = 0u64;
+        for (i, &limb) in long.limbs.iter().enumerate() {
+            let sum = u64::from(limb) + u64::from(short.limbs.get(i).copied().unwrap_or(0)) + carry;
+            limbs.push(sum as u32);
+            carry = sum >> 32;
+        }
+        limbs.push(carry as u32);
+        Natural::from_limbs(limbs)
+    }
+
+    /// `self - that`.
+    ///
+    /// # Panics
+    /// If `that` is larger.
+    pub fn sub(&self, that: &Natural) -> Natural {
+        assert!(*self >= *that, "natural subtraction would be negative");
+        let mut limbs = Vec::with_capacity(self.limbs.len());
+        let mut borrow = 0i64;
+        for (i, &limb) in self.limbs.iter().enumerate() {
+            let diff =
+                i64::from(limb) - i64::from(that.limbs.get(i).copied().unwrap_or(0)) - borrow;
+            limbs.push(diff as u32);
+            borrow = i64::from(diff < 0);
+        }
+        Natural::from_limbs(limbs)
+    }
+
+    pub fn mul(&self, that: &Natural) -> Natural {
+        if self.is_zero() || that.is_zero() {
+            return Natural::zero();
+        }
+        let mut limbs = vec![0u32; self.limbs.len() + that.limbs.len()];
+        for (i, &a) in self.limbs.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in that.limbs.iter().enumerate() {
+                let product = u64::from(a) * u64::from(b) + u64::from(limbs[i + j]) + carry;
+                limbs[i + j] = product as u32;
+                carry = product >> 32;
+            }
+            limbs[i + that.limbs.len()] = carry as u32;
+        }
+        Natural::from_limbs(limbs)
+    }
+
+    pub fn mul_small(&self, factor: u32) -> Natural {
+        let mut limbs = Vec::with_capacity(self.limbs.len() + 1);
+        let mut carry = 0u64;
+        for &limb in &self.limbs {
+            let product = u64::from(limb) * u64::from(factor) + carry;
+            limbs.push(product as u32);
+            carry = product >> 32;
+        }
+        limbs.push(carry as u32);
+        Natural::from_limbs(limbs)
+    }
+
+    pub fn shl(&self, n: u64) -> Natural {
+        if self.is_zero() {
+            return Natural::zero();
+        }
+        let (whole, rest) = ((n / 32) as usize, n % 32);
+        let mut limbs = vec![0u32; whole];
+        let mut carry = 0u32;
+        for &limb in &self.limbs {
+            if rest == 0 {
+                limbs.push(limb);
+            } else {
+                limbs.push(limb << rest | carry);
+                carry = limb >> (32 - rest);
+            }
+        }
+        limbs.push(carry);
+        Natural::from_limbs(limbs)
+    }
+
+    pub fn shr(&self, n: u64) -> Natural {
+        let whole = (n / 32) as usize;
+        if whole >= self.limbs.len() {
+            return Natural::zero();
+        }
+        let rest = n % 32;
+        let high = &self.limbs[whole..];
+        let limbs = (0..high.len())
+            .map(|i| {
+                let next = high.get(i + 1).copied().unwrap_or(0);
+                if rest == 0 {
+                    high[i]
+                } else {
+                    high[i] >> rest | next << (32 - rest)
+                }
+            })
+            .collect();
+        Natural::from_limbs(limbs)
+    }
+
+    pub fn div_rem_small(&self, divisor: u32) -> (Natural, u32) {
+        assert!(divisor != 0, "division by zero");
+        let mut limbs = vec![0u32; self.limbs.len()];
+        let mut remainder = 0u64;
+        for i in (0..self.limbs.len()).rev() {
+            let current = remainder << 32 | u64::from(self.limbs[i]);
+            limbs[i] = (current / u64::from(divisor)) as u32;
+            remainder = current % u64::from(divisor);
+        }
+        (Natural::from_limbs(limbs), remainder as u32)
+    }
+
+    /// Quotient and remainder, by Knuth's algorithm D.
+    ///
+    /// # Panics
+    /// If `divisor` is zero.
+    pub fn div_rem(&self, divisor: &Natural) -> (Natural, Natural) {
+        assert!(!divisor.is_zero(), "division by zero");
+        if *self < *divisor {
+            return (Natural::zero(), self.clone());
+        }
+        if divisor.limbs.len() == 1 {
+            let (quotient, remainder) = self.div_rem_small(divisor.limbs[0]);
+            return (quotient, Natural::from(u64::from(remainder)));
+        }
+        // normalise so the top limb of the divisor has its highest bit set, which keeps the
+        // estimated quotient limbs at most two too large
+        let shift = u64::from(divisor.limbs.last().unwrap().leading_zeros());
+        let v = divisor.shl(shift).limbs;
+        let mut u = self.shl(shift).limbs;
+        u.push(0);
+        let n = v.len();
+        let m = u.len() - n - 1;
+        let base = 1u64 << 32;
+        let mut quotient = vec![0u32; m + 1];
+        for j in (0..=m).rev() {
+            let top = u64::from(u[j + n]) << 32 | u64::from(u[j + n - 1]);
+            let mut qhat = top / u64::from(v[n - 1]);
+            let mut rhat = top % u64::from(v[n - 1]);
+            while qhat >= base
+                || qhat * u64::from(v[n - 2]) > (rhat << 32 | u64::from(u[j + n - 2]))
+            {
+                qhat -= 1;
+                rhat += u64::from(v[n - 1]);
+                if rhat >= base {
+                    break;
+                }
+            }
+            let mut borrow = 0i64;
+            let mut carry = 0u64;
+            for i in 0..n {
+                let product = qhat * u64::from(v[i]) + carry;
+                carry = product >> 32;
+                let diff = i64::from(u[i + j]) - borrow - (product & 0xffff_ffff) as i64;
+                u[i + j] = diff as u32;
+                borrow = i64::from(diff < 0);
+            }
+            let diff = i64::from(u[j + n]) - borrow - carry as i64;
+            u[j + n] = diff as u32;
+            if diff < 0 {
+                // the estimate was one too large, add the divisor back
+                qhat -= 1;
+                let mut carry = 0u64;
+                for i in 0..n {
+                    let sum = u64::from(u[i + j]) + u64::from(v[i]) + carry;
+                    u[i + j] = sum as u32;
+                    carry = sum >> 32;
+                }
+                u[j + n] = u[j + n].wrapping_add(carry as u32);
+            }
+            quotient[j] = qhat as u32;
+        }
+        u.truncate(n);
+        (
+            Natural::from_limbs(quotient),
+            Natural::from_limbs(u).shr(shift),
+        )
+    }
+
+    /// The largest integer whose square is at most `self`.
+    pub fn isqrt(&self) -> Natural {
+        if self.is_zero() {
+            return Natural::zero();
+        }
+        // Newton's iteration decreases monotonically from any starting point above the root
+        let mut x = Natural::from(1).shl(self.bits().div_ceil(2));
+        loop {
+            let y = x.add(&self.div_rem(&x).0).shr(1);
+            if y >= x {
+                return x;
+            }
+            x = y;
+        }
+    }
+
+    pub fn to_decimal(&self) -> String {
+        if self.is_zero() {
+            return "0".into();
+        }
+        let mut chunks = Vec::new();
+        let mut rest = self.clone();
+        while !rest.is_zero() {
+            let (quotient, chunk) = rest.div_rem_small(1_000_000_000);
+            chunks.push(chunk);
+            rest = quotient;
+        }
+        let mut digits = chunks.pop().unwrap().to_string();
+        for chunk in chunks.iter().rev() {
+            digits.push_str(&format!("{:09}", chunk));
+        }
+        digits
+    }
+}
+
+impl From<u64> for Natural {
+    fn from(n: u64) -> Self {
+        Natural::from_limbs(vec![n as u32, (n >> 32) as u32])
+    }
+}
+
+impl Ord for Natural {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.limbs
+            .len()
+            .cmp(&other.limbs.len())
+            .then_with(|| self.limbs.iter().rev().cmp(other.limbs.iter().rev()))
+    }
+}
+
+impl PartialOrd for Natural {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(n: u128) -> Natural {
+        Natural::from((n >> 64) as u64)
+            .shl(64)
+            .add(&Natural::from(n as u64))
+    }
+
+    #[test]
+    fn arithmetic_matches_u128() {
+        let pairs = [
+            (u128::MAX / 3, 0xffff_ffff_0000_0001_u128),
+            (
+                0x1234_5678_9abc_def0_1234_5678_9abc,
+                0x1_0000_0000_0000_0001,
+            ),
+            (987_654_321_987_654_321_987_654_321, 123_456_789_123),
+            (1 << 100, (1 << 64) - 1),
+        ];
+        for (a, b) in pairs {
+            let (q, r) = big(a).div_rem(&big(b));
+            assert_eq!((q, r), (big(a / b), big(a % b)), "{} / {}", a, b);
+            assert_eq!(big(a).sub(&big(b)), big(a - b));
+            assert_eq!(big(a >> 1).add(&big(b)), big((a >> 1) + b));
+            assert_eq!(big(a >> 70).mul(&big(b >> 10)), big((a >> 70) * (b >> 10)));
+            assert_eq!(big(a).shr(37).shl(5), big(a >> 37 << 5));
+        }
+    }
+
+    #[test]
+    fn large_division_and_square_roots() {
+        // (10^60 + 7)^2 = 10^120 + 14 * 10^60 + 49
+        let n = Natural::pow10(60).add(&Natural::from(7));
+        let square = n.mul(&n);
+        assert_eq!(square.isqrt(), n);
+        assert_eq!(square.add(&Natural::from(1)).isqrt(), n);
+        assert_eq!(
+            square.sub(&Natural::from(1)).isqrt(),
+            n.sub(&Natural::from(1))
+        );
+        let (q, r) = square.add(&Natural::from(5)).div_rem(&n);
+        assert_eq!((q, r), (n.clone(), Natural::from(5)));
+        assert_eq!(n.to_decimal(), format!("1{}7", "0".repeat(59)));
+    }
+}
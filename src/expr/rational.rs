@@ -0,0 +1,354 @@
+use crate::expr::Expr;
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::ops::Neg;
+
+pub(crate) fn gcd(a: i128, b: i128) -> i128 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+pub(crate) fn checked_lcm(a: i128, b: i128) -> Option<i128> {
+    (a / gcd(a, b)).checked_mul(b)
+}
+
+/// An exact fraction, always kept in lowest terms with a positive denominator. Arithmetic is
+/// checked, since `i128` overflows quickly in powers and products of polynomials; neither
+/// part is ever `i128::MIN`, so negation can't overflow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rational {
+    num: i128,
+    den: i128,
+}
+
+impl Rational {
+    pub const ZERO: Rational = Rational { num: 0, den: 1 };
+    pub const ONE: Rational = Rational { num: 1, den: 1 };
+
+    /// `num / den` in lowest terms; callers make sure `den` isn't zero.
+    pub(crate) fn new(num: i128, den: i128) -> Self {
+        assert!(den != 0, "rational with a zero denominator");
+        let divisor = gcd(num, den) * den.signum();
+        Rational {
+            num: num / divisor,
+            den: den / divisor,
+        }
+    }
+
+    /// `num / den` in lowest terms, or `None` for a zero denominator or parts out of range.
+    fn checked_new(num: i128, den: i128) -> Option<Self> {
+        if den == 0 || num == i128::MIN || den == i128::MIN {
+            return None;
+        }
+        Some(Rational::new(num, den))
+    }
+
+    /// `n` as a fraction; panics on `i128::MIN`, which has no negation.
+    pub fn integer(n: i128) -> Self {
+        Rational::checked_integer(n).expect("rational out of range")
+    }
+
+    /// `n` as a fraction, or `None` for `i128::MIN`.
+    pub fn checked_integer(n: i128) -> Option<Self> {
+        Rational::checked_new(n, 1)
+    }
+
+    pub fn numer(&self) -> i128 {
+        self.num
+    }
+
+    pub fn denom(&self) -> i128 {
+        self.den
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.num == 0
+    }
+
+    pub fn is_one(&self) -> bool {
+        self.num == 1 && self.den == 1
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.den == 1
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.num < 0
+    }
+
+    pub fn abs(self) -> Self {
+        Rational {
+            num: self.num.abs(),
+            den: self.den,
+        }
+    }
+
+    /// The reciprocal of a rational known not to be zero.
+    pub(crate) fn recip(self) -> Self {
+        Rational::new(self.den, self.num)
+    }
+
+    /// The reciprocal, or `None` for zero.
+    pub fn checked_recip(self) -> Option<Self> {
+        (!self.is_zero()).then(|| self.recip())
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+
+    pub fn checked_add(self, that: Rational) -> Option<Rational> {
+        let num = self
+            .num
+            .checked_mul(that.den)?
+            .checked_add(that.num.checked_mul(self.den)?)?;
+        Rational::checked_new(num, self.den.checked_mul(that.den)?)
+    }
+
+    pub fn checked_sub(self, that: Rational) -> Option<Rational> {
+        self.checked_add(-that)
+    }
+
+    pub fn checked_mul(self, that: Rational) -> Option<Rational> {
+        let (a, b) = (
+            gcd(self.num, that.den).max(1),
+            gcd(that.num, self.den).max(1),
+        );
+        let num = (self.num / a).checked_mul(that.num / b)?;
+        let den = (self.den / b).checked_mul(that.den / a)?;
+        Rational::checked_new(num, den)
+    }
+
+    /// `None` on overflow or division by zero.
+    pub fn checked_div(self, that: Rational) -> Option<Rational> {
+        if that.is_zero() {
+            return None;
+        }
+        self.checked_mul(that.recip())
+    }
+
+    pub fn checked_pow(self, exponent: i32) -> Option<Rational> {
+        if exponent < 0 {
+            if self.is_zero() {
+                return None;
+            }
+            return self.recip().checked_pow(exponent.checked_neg()?);
+        }
+        let exponent = exponent as u32;
+        Rational::checked_new(
+            self.num.checked_pow(exponent)?,
+            self.den.checked_pow(exponent)?,
+        )
+    }
+
+    /// Evaluates a purely numeric expression built from integer constants exactly,
+    /// returning `None` as soon as anything else (variables, `e`, functions, ...) is found.
+    pub fn from_expr(e: &Expr) -> Option<Rational> {
+        match e {
+            Expr::Const(n) => Some(Rational::integer(i128::from(*n))),
+            Expr::Neg(inner) => Rational::from_expr(inner).map(|r| -r),
+            Expr::Add(lhs, rhs) => Rational::from_expr(lhs)?.checked_add(Rational::from_expr(rhs)?),
+            Expr::Sub(lhs, rhs) => Rational::from_expr(lhs)?.checked_sub(Rational::from_expr(rhs)?),
+            Expr::Mul(lhs, rhs) => Rational::from_expr(lhs)?.checked_mul(Rational::from_expr(rhs)?),
+            Expr::Div(lhs, rhs) => Rational::from_expr(lhs)?.checked_div(Rational::from_expr(rhs)?),
+            Expr::Pow(base, exponent) => {
+                let exponent = Rational::from_expr(exponent)?;
+                if !exponent.is_integer() || exponent.num.abs() > 128 {
+                    return None;
+                }
+                Rational::from_expr(base)?.checked_pow(exponent.num as i32)
+            }
+            _ => None,
+        }
+    }
+
+    /// `None` if the numerator or denominator is beyond `u32` and not a power of a smaller
+    /// integer, which no `Expr` can write.
+    pub fn to_expr(self) -> Option<Expr> {
+        let magnitude = int_expr(self.num.unsigned_abs())?;
+        let unsigned = if self.den == 1 {
+            magnitude
+        } else {
+            Expr::Div(
+                Box::new(magnitude),
+                Box::new(int_expr(self.den.unsigned_abs())?),
+            )
+        };
+        Some(if self.num < 0 {
+            Expr::Neg(Box::new(unsigned))
+        } else {
+            unsigned
+        })
+    }
+}
+
+/// Integers beyond `u32` don't fit in an `Expr::Const`, so only perfect powers can be
+/// written, like `2^40`.
+fn int_expr(n: u128) -> Option<Expr> {
+    if let Ok(small) = u32::try_from(n) {
+        return Some(Expr::Const(small));
+    }
+    let (base, exponent) = perfect_power(n)?;
+    Some(Expr::Pow(
+        Box::new(Expr::Const(base)),
+        Box::new(Expr::Const(exponent)),
+    ))
+}
+
+/// `n` as `base^exponent` with the smallest base, if it is a power of a `u32`.
+fn perfect_power(n: u128) -> Option<(u32, u32)> {
+    (2..u128::BITS).rev().find_map(|exponent| {
+        let estimate = (n as f64).powf(1.0 / f64::from(exponent)).round() as u32;
+        (estimate.saturating_sub(1)..=estimate.saturating_add(1))
+            .filter(|&base| base >= 2)
+            .find(|&base| u128::from(base).checked_pow(exponent) == Some(n))
+            .map(|base| (base, exponent))
+    })
+}
+
+impl TryFrom<i128> for Rational {
+    type Error = String;
+
+    fn try_from(n: i128) -> Result<Self, String> {
+        Rational::checked_integer(n).ok_or_else(|| format!("{} is out of range", n))
+    }
+}
+
+impl Neg for Rational {
+    type Output = Rational;
+
+    fn neg(self) -> Rational {
+        Rational {
+            num: -self.num,
+            den: self.den,
+        }
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Rational {
+    /// Compares integer parts, then the reciprocals of the fractional parts the other way
+    /// round, as in a continued fraction, so no products can overflow.
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (mut a, mut b, mut c, mut d) = (self.num, self.den, other.num, other.den);
+        loop {
+            let ordering = a.div_euclid(b).cmp(&c.div_euclid(d));
+            let (r, s) = (a.rem_euclid(b), c.rem_euclid(d));
+            let ordering = match (ordering, r == 0, s == 0) {
+                (Ordering::Equal, true, true) => Ordering::Equal,
+                (Ordering::Equal, true, false) => Ordering::Less,
+                (Ordering::Equal, false, true) => Ordering::Greater,
+                // r/b < s/d exactly when d/s < b/r
+                (Ordering::Equal, false, false) => {
+                    (a, b, c, d) = (d, s, b, r);
+                    continue;
+                }
+                (ordering, _, _) => ordering,
+            };
+            return ordering;
+        }
+    }
+}
+
+impl Display for Rational {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fractions_are_kept_in_lowest_terms() {
+        let r = Rational::new(6, -4);
+        assert_eq!((r.numer(), r.denom()), (-3, 2));
+        assert_eq!(r.to_string(), "-3/2");
+        assert_eq!(Rational::new(0, -7), Rational::ZERO);
+    }
+
+    #[test]
+    fn arithmetic_is_exact() {
+        let (a, b) = (Rational::new(1, 2), Rational::new(1, 3));
+        assert_eq!(a.checked_add(b), Some(Rational::new(5, 6)));
+        assert_eq!(a.checked_sub(b), Some(Rational::new(1, 6)));
+        assert_eq!(a.checked_mul(b), Some(Rational::new(1, 6)));
+        assert_eq!(a.checked_div(b), Some(Rational::new(3, 2)));
+        assert_eq!(a.checked_pow(-3), Some(Rational::integer(8)));
+        assert_eq!(a.checked_div(Rational::ZERO), None);
+        assert_eq!(Rational::ZERO.checked_pow(-1), None);
+        assert_eq!(Rational::ZERO.checked_recip(), None);
+    }
+
+    #[test]
+    fn overflow_is_none() {
+        let max = Rational::integer(i128::MAX);
+        assert_eq!(max.checked_add(Rational::ONE), None);
+        assert_eq!(max.checked_mul(Rational::integer(2)), None);
+        assert_eq!(Rational::integer(2).checked_pow(127), None);
+        // -i128::MAX - 1 is i128::MIN, which a rational never holds
+        assert_eq!((-max).checked_sub(Rational::ONE), None);
+        assert_eq!(
+            Rational::new(1, i128::MAX).checked_mul(Rational::new(1, 2)),
+            None
+        );
+    }
+
+    #[test]
+    fn i128_min_is_rejected() {
+        assert_eq!(Rational::checked_integer(i128::MIN), None);
+        assert!(Rational::try_from(i128::MIN).is_err());
+        assert_eq!(Rational::try_from(-5), Ok(Rational::integer(-5)));
+        let min = Rational::checked_integer(i128::MIN + 1).unwrap();
+        assert_eq!(-(-min), min);
+    }
+
+    #[test]
+    fn ordering_does_not_overflow() {
+        let (a, b) = (Rational::new(i128::MAX, 3), Rational::new(i128::MAX - 1, 3));
+        assert!(b < a);
+        assert!(Rational::new(-1, 3) < Rational::new(-1, 4));
+        assert!(Rational::new(1, i128::MAX) < Rational::new(1, i128::MAX - 1));
+        assert_eq!(
+            Rational::new(2, 4).cmp(&Rational::new(1, 2)),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn from_expr_evaluates_numbers_only() {
+        use crate::expr::syntax::*;
+        let expr = num(3).div(num(4)).minus(num(2).pow(num(2).neg()));
+        assert_eq!(Rational::from_expr(&expr), Some(Rational::new(1, 2)));
+        assert_eq!(Rational::from_expr(&num(1).div(num(0))), None);
+        assert_eq!(Rational::from_expr(&X.plus(num(1))), None);
+        assert_eq!(
+            Rational::new(-7, 2).to_expr(),
+            Some(num(7).div(num(2)).neg())
+        );
+    }
+
+    #[test]
+    fn only_perfect_powers_beyond_u32_are_written() {
+        use crate::expr::syntax::*;
+        let power = Rational::integer(1 << 40).to_expr();
+        assert_eq!(power, Some(num(2).pow(num(40))));
+        let cube = Rational::new(1, i128::from(u32::MAX).pow(3)).to_expr();
+        assert_eq!(cube, Some(num(1).div(num(u32::MAX).pow(num(3)))));
+        assert_eq!(Rational::integer((1 << 40) + 1).to_expr(), None);
+    }
+}
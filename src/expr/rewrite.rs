@@ -0,0 +1,451 @@
+use crate::expr::equivalence::Equivalence;
+use crate::expr::Expr;
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Formatter};
+use std::ops::Index;
+use std::sync::Arc;
+
+/// Upper bound on rule applications in a single [`Rewriter::rewrite`], so that a rule set
+/// that cycles, e.g. `a + b => b + a`, still terminates.
+const DEFAULT_MAX_STEPS: usize = 10_000;
+
+/// Points compared for every rule application of a [`Rewriter::checked`] rewriter.
+const CHECK_SAMPLES: usize = 8;
+
+/// Subexpressions captured by the pattern variables of a [`Rule`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Bindings(BTreeMap<char, Expr>);
+
+impl Bindings {
+    pub fn get(&self, name: char) -> Option<&Expr> {
+        self.0.get(&name)
+    }
+
+    /// Binds `name` to `e`, failing if it is already bound to something else.
+    fn bind(&mut self, name: char, e: &Expr) -> bool {
+        match self.0.get(&name) {
+            Some(bound) => bound == e,
+            None => {
+                self.0.insert(name, e.clone());
+                true
+            }
+        }
+    }
+
+    /// The template with each pattern variable replaced by what it is bound to.
+    pub(crate) fn instantiate(&self, template: &Expr) -> Expr {
+        let both = |lhs: &Expr, rhs: &Expr| {
+            (
+                Box::new(self.instantiate(lhs)),
+                Box::new(self.instantiate(rhs)),
+            )
+        };
+        match template {
+            Expr::Var(name) => self.get(*name).cloned().unwrap_or(Expr::Var(*name)),
+            Expr::Add(lhs, rhs) => {
+                let (lhs, rhs) = both(lhs, rhs);
+                Expr::Add(lhs, rhs)
+            }
+            Expr::Sub(lhs, rhs) => {
+                let (lhs, rhs) = both(lhs, rhs);
+                Expr::Sub(lhs, rhs)
+            }
+            Expr::Mul(lhs, rhs) => {
+                let (lhs, rhs) = both(lhs, rhs);
+                Expr::Mul(lhs, rhs)
+            }
+            Expr::Div(lhs, rhs) => {
+                let (lhs, rhs) = both(lhs, rhs);
+                Expr::Div(lhs, rhs)
+            }
+            Expr::Pow(lhs, rhs) => {
+                let (lhs, rhs) = both(lhs, rhs);
+                Expr::Pow(lhs, rhs)
+            }
+            Expr::Neg(inner) => Expr::Neg(Box::new(self.instantiate(inner))),
+            Expr::Func(name, arg) => Expr::Func(name.clone(), Box::new(self.instantiate(arg))),
+            other => other.clone(),
+        }
+    }
+}
+
+impl FromIterator<(char, Expr)> for Bindings {
+    fn from_iter<T: IntoIterator<Item = (char, Expr)>>(iter: T) -> Self {
+        Bindings(iter.into_iter().collect())
+    }
+}
+
+impl Index<char> for Bindings {
+    type Output = Expr;
+
+    fn index(&self, name: char) -> &Expr {
+        self.get(name)
+            .unwrap_or_else(|| panic!("pattern variable [{}] is not bound", name))
+    }
+}
+
+/// Matches `e` against `pattern`, extending `bindings`. `Add` and `Mul` operands match in
+/// either order.
+fn match_pattern(pattern: &Expr, e: &Expr, bindings: &mut Bindings) -> bool {
+    let both = |p: (&Expr, &Expr), e: (&Expr, &Expr), bindings: &mut Bindings| {
+        let mut attempt = bindings.clone();
+        if match_pattern(p.0, e.0, &mut attempt) && match_pattern(p.1, e.1, &mut attempt) {
+            *bindings = attempt;
+            true
+        } else {
+            false
+        }
+    };
+    match (pattern, e) {
+        (Expr::Var(name), _) => bindings.bind(*name, e),
+        (Expr::Add(p_lhs, p_rhs), Expr::Add(lhs, rhs))
+        | (Expr::Mul(p_lhs, p_rhs), Expr::Mul(lhs, rhs)) => {
+            both((p_lhs, p_rhs), (lhs, rhs), bindings) || both((p_lhs, p_rhs), (rhs, lhs), bindings)
+        }
+        (Expr::Sub(p_lhs, p_rhs), Expr::Sub(lhs, rhs))
+        | (Expr::Div(p_lhs, p_rhs), Expr::Div(lhs, rhs))
+        | (Expr::Pow(p_lhs, p_rhs), Expr::Pow(lhs, rhs)) => {
+            both((p_lhs, p_rhs), (lhs, rhs), bindings)
+        }
+        (Expr::Neg(p_inner), Expr::Neg(inner)) => match_pattern(p_inner, inner, bindings),
+        (Expr::Func(p_name, p_arg), Expr::Func(name, arg)) => {
+            p_name == name && match_pattern(p_arg, arg, bindings)
+        }
+        _ => pattern == e,
+    }
+}
+
+type Condition = Arc<dyn Fn(&Bindings) -> bool + Send + Sync>;
+
+type Computation = Arc<dyn Fn(&Bindings) -> Option<Expr> + Send + Sync>;
+
+#[derive(Clone)]
+enum Replacement {
+    Template(Expr),
+    Computed(Computation),
+}
+
+/// A rewrite rule `pattern => replacement`. Every variable in the pattern is a pattern
+/// variable matching any subexpression, and a variable used more than once must match
+/// equal subexpressions, so `a / a => 1` cancels `(x + 1)/(x + 1)` but not `x/y`.
+/// Numbers, named constants and function names match only themselves.
+#[derive(Clone)]
+pub struct Rule {
+    name: String,
+    pattern: Expr,
+    replacement: Replacement,
+    condition: Option<Condition>,
+}
+
+impl Rule {
+    /// A rule replacing matches of `pattern` with `replacement`, in which pattern variables
+    /// stand for what they matched.
+    pub fn new<T: Into<String>>(name: T, pattern: Expr, replacement: Expr) -> Self {
+        Rule {
+            name: name.into(),
+            pattern,
+            replacement: Replacement::Template(replacement),
+            condition: None,
+        }
+    }
+
+    /// A rule whose replacement is computed from the bindings, for rewrites a template
+    /// can't express such as numeric folding. Returning `None` leaves the match as it is.
+    pub fn computed<T, F>(name: T, pattern: Expr, replacement: F) -> Self
+    where
+        T: Into<String>,
+        F: Fn(&Bindings) -> Option<Expr> + Send + Sync + 'static,
+    {
+        Rule {
+            name: name.into(),
+            pattern,
+            replacement: Replacement::Computed(Arc::new(replacement)),
+            condition: None,
+        }
+    }
+
+    /// Restricts the rule to matches whose bindings satisfy `condition`.
+    pub fn when<F>(mut self, condition: F) -> Self
+    where
+        F: Fn(&Bindings) -> bool + Send + Sync + 'static,
+    {
+        self.condition = Some(Arc::new(condition));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Rewrites `e` itself, not its subexpressions, if it matches.
+    pub fn apply(&self, e: &Expr) -> Option<Expr> {
+        let mut bindings = Bindings::default();
+        if !match_pattern(&self.pattern, e, &mut bindings) {
+            return None;
+        }
+        self.replace(&bindings)
+    }
+
+    pub(crate) fn pattern(&self) -> &Expr {
+        &self.pattern
+    }
+
+    /// The replacement of an unconditional template rule, which can be built without
+    /// looking at what the pattern variables matched.
+    pub(crate) fn template(&self) -> Option<&Expr> {
+        match (&self.replacement, &self.condition) {
+            (Replacement::Template(template), None) => Some(template),
+            _ => None,
+        }
+    }
+
+    /// The replacement for a match of the pattern, if the condition holds.
+    pub(crate) fn replace(&self, bindings: &Bindings) -> Option<Expr> {
+        if !self.condition.as_ref().is_none_or(|holds| holds(bindings)) {
+            return None;
+        }
+        match &self.replacement {
+            Replacement::Template(template) => Some(bindings.instantiate(template)),
+            Replacement::Computed(compute) => compute(bindings),
+        }
+    }
+}
+
+impl Debug for Rule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Rule({}: {:?})", self.name, self.pattern)
+    }
+}
+
+/// Applies a list of rules bottom-up until none of them matches anywhere: the operands of
+/// a node are rewritten first, then the first matching rule rewrites the node itself and
+/// its result is rewritten again.
+#[derive(Debug, Clone)]
+pub struct Rewriter {
+    rules: Vec<Rule>,
+    max_steps: usize,
+    checked: bool,
+}
+
+impl Rewriter {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Rewriter {
+            rules,
+            max_steps: DEFAULT_MAX_STEPS,
+            checked: false,
+        }
+    }
+
+    /// In debug builds, compares the expression before and after every rule application
+    /// at random points and panics, naming the rule, if a rewrite changed its value. Only
+    /// meant for rules that hold unconditionally.
+    pub fn checked(mut self) -> Self {
+        self.checked = true;
+        self
+    }
+
+    /// Stops rewriting after `max_steps` rule applications, returning the expression as far
+    /// as it got, see [`Rewriter::rewrite_to_fixpoint`] to tell when that happened.
+    pub fn max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    pub fn rewrite(&self, expr: Expr) -> Expr {
+        self.rewrite_to_fixpoint(expr)
+            .unwrap_or_else(|partial| partial)
+    }
+
+    /// [`Rewriter::rewrite`], failing with the expression as far as it got if rules still
+    /// matched when the step limit was reached.
+    pub fn rewrite_to_fixpoint(&self, expr: Expr) -> Result<Expr, Expr> {
+        let mut steps = 0;
+        let mut exhausted = false;
+        let expr = self.rewrite_counting(expr, &mut steps, &mut exhausted);
+        if exhausted {
+            Err(expr)
+        } else {
+            Ok(expr)
+        }
+    }
+
+    /// Rewrites the children, then applies the first rule that changes the node and starts
+    /// over on its result, in a loop rather than by recursion, so that long chains of
+    /// rewrites don't grow the stack.
+    fn rewrite_counting(&self, expr: Expr, steps: &mut usize, exhausted: &mut bool) -> Expr {
+        let mut expr = expr;
+        loop {
+            let mut rewrite = |e: Box<Expr>| Box::new(self.rewrite_counting(*e, steps, exhausted));
+            let node = match expr {
+                Expr::Add(lhs, rhs) => Expr::Add(rewrite(lhs), rewrite(rhs)),
+                Expr::Sub(lhs, rhs) => Expr::Sub(rewrite(lhs), rewrite(rhs)),
+                Expr::Mul(lhs, rhs) => Expr::Mul(rewrite(lhs), rewrite(rhs)),
+                Expr::Div(lhs, rhs) => Expr::Div(rewrite(lhs), rewrite(rhs)),
+                Expr::Pow(lhs, rhs) => Expr::Pow(rewrite(lhs), rewrite(rhs)),
+                Expr::Neg(inner) => Expr::Neg(rewrite(inner)),
+                Expr::Func(name, arg) => Expr::Func(name, rewrite(arg)),
+                leaf => leaf,
+            };
+            let rewritten = self.rules.iter().find_map(|rule| {
+                rule.apply(&node)
+                    .filter(|result| *result != node)
+                    .map(|result| (rule, result))
+            });
+            let Some((rule, result)) = rewritten else {
+                return node;
+            };
+            if *steps >= self.max_steps {
+                *exhausted = true;
+                return node;
+            }
+            if cfg!(debug_assertions) && self.checked {
+                check_rewrite(rule, &node, &result);
+            }
+            *steps += 1;
+            expr = result;
+        }
+    }
+}
+
+fn check_rewrite(rule: &Rule, before: &Expr, after: &Expr) {
+    let sound = Equivalence::new()
+        .samples(CHECK_SAMPLES)
+        .numerically(before, after);
+    // expressions that can't be evaluated give no evidence either way
+    if sound == Ok(false) {
+        panic!(
+            "rule [{}] rewrote {:?} into {:?}, which has a different value",
+            rule.name, before, after
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::syntax::*;
+
+    fn a() -> Expr {
+        var('a')
+    }
+
+    fn b() -> Expr {
+        var('b')
+    }
+
+    #[test]
+    fn template_rules_substitute_bindings() {
+        let rule = Rule::new(
+            "log of power",
+            func("ln", a().pow(b())),
+            b().times(func("ln", a())),
+        );
+        let expr = func("ln", X.pow(num(3)));
+        assert_eq!(rule.apply(&expr), Some(num(3).times(func("ln", X))));
+        assert_eq!(rule.apply(&func("ln", X)), None);
+    }
+
+    #[test]
+    fn repeated_pattern_variables_match_equal_subexpressions() {
+        let rule = Rule::new("self division", a().div(a()), num(1));
+        let y = var('y');
+        assert_eq!(
+            rule.apply(&X.plus(y.clone()).div(X.plus(y.clone()))),
+            Some(num(1))
+        );
+        assert_eq!(rule.apply(&X.div(y)), None);
+    }
+
+    #[test]
+    fn sums_and_products_match_in_either_order() {
+        let rule = Rule::new("add zero", a().plus(num(0)), a());
+        assert_eq!(rule.apply(&num(0).plus(X)), Some(X));
+        let rule = Rule::new(
+            "exp of log",
+            func("exp", func("ln", a()).times(b())),
+            a().pow(b()),
+        );
+        let expr = func("exp", num(2).times(func("ln", X)));
+        assert_eq!(rule.apply(&expr), Some(X.pow(num(2))));
+    }
+
+    #[test]
+    fn conditions_restrict_matches() {
+        let rule = Rule::new(
+            "sqrt of square",
+            a().pow(num(2)).pow(num(1).div(num(2))),
+            a(),
+        )
+        .when(|b| matches!(b['a'], Expr::Const(_)));
+        let sqrt_of_square = |e: Expr| e.pow(num(2)).pow(num(1).div(num(2)));
+        assert_eq!(rule.apply(&sqrt_of_square(num(3))), Some(num(3)));
+        assert_eq!(rule.apply(&sqrt_of_square(X)), None);
+    }
+
+    #[test]
+    fn rewriting_is_bottom_up_to_a_fixpoint() {
+        let rewriter = Rewriter::new(vec![
+            Rule::new("add zero", a().plus(num(0)), a()),
+            Rule::new("double negation", Expr::Neg(Box::new(a().neg())), a()),
+            Rule::computed("fold", a().times(b()), |b| match (&b['a'], &b['b']) {
+                (Expr::Const(x), Expr::Const(y)) => Some(num(x * y)),
+                _ => None,
+            }),
+        ]);
+        // -(-(2 * 3)) * x + 0 => 6x, which needs the fold below the negations first
+        let expr = Expr::Neg(Box::new(Expr::Neg(Box::new(num(2).times(num(3))))))
+            .times(X)
+            .plus(num(0));
+        assert_eq!(rewriter.rewrite(expr), num(6).times(X));
+    }
+
+    #[test]
+    fn cycling_rules_stop_after_max_steps() {
+        let rewriter =
+            Rewriter::new(vec![Rule::new("swap", a().minus(b()), b().minus(a()))]).max_steps(5);
+        let expr = rewriter.rewrite(X.minus(var('y')));
+        assert_eq!(expr, var('y').minus(X));
+        assert_eq!(
+            rewriter.rewrite_to_fixpoint(X.minus(var('y'))),
+            Err(var('y').minus(X))
+        );
+        let rewriter = Rewriter::new(vec![Rule::new("add zero", a().plus(num(0)), a())]);
+        let expr = X.plus(num(0)).plus(num(0));
+        assert_eq!(rewriter.max_steps(2).rewrite_to_fixpoint(expr), Ok(X));
+    }
+
+    #[test]
+    #[should_panic(expected = "rule [wrong sign]")]
+    fn checked_rewriters_catch_unsound_rules() {
+        let rewriter = Rewriter::new(vec![Rule::new(
+            "wrong sign",
+            a().minus(b()),
+            b().minus(a()),
+        )])
+        .max_steps(1)
+        .checked();
+        rewriter.rewrite(X.minus(num(1)));
+    }
+
+    #[test]
+    fn simplify_rules_are_sound() {
+        let rewriter = Rewriter::new(crate::expr::simplify::rules()).checked();
+        let y = var('y');
+        let exprs = [
+            X.plus(num(1)).pow(num(2)).minus(X.times(num(2))),
+            X.pow(num(2)).minus(num(1)).div(X.minus(num(1))),
+            num(3).times(X).div(num(6).times(y.clone())),
+            sin(PI.times(num(2))).plus(func("ln", E)).times(X.div(X)),
+            X.div(num(2)).plus(y.clone().div(num(3))).neg(),
+            I.pow(num(3)).times(X.pow(num(0))),
+            X.pow(num(2)).pow(num(3).neg()).times(X),
+        ];
+        for expr in exprs {
+            rewriter.rewrite(expr);
+        }
+    }
+}
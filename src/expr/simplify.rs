@@ -1,75 +1,311 @@
+use crate::expr::assumptions::{Assumptions, Condition, Conditional, Predicate};
+use crate::expr::rational::Rational;
+use crate::expr::rewrite::{Bindings, Rewriter, Rule};
+use crate::expr::syntax::{cos, func, num, sin, tan, var, E, I};
+use crate::expr::terms::{collect_product, collect_sum, split_coefficient, with_coefficient};
 use crate::expr::Expr;
+use crate::poly::{poly_gcd, Poly, RationalFunction, MAX_FACTORED_DEGREE};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
 
-pub fn simplify(expr: Expr) -> Expr {
-    match expr {
-        Expr::Const(_) | Expr::Var(_) | Expr::E => expr,
-        Expr::Neg(inner) if *inner == Expr::Const(0) => Expr::Const(0),
-        Expr::Neg(inner) if matches!(*inner, Expr::Neg(_)) => inner.neg(),
-        Expr::Add(lhs, rhs) if matches!(*rhs, Expr::Neg(_)) => Expr::Sub(lhs, Box::new(rhs.neg())),
-        Expr::Add(lhs, rhs) if matches!(*lhs, Expr::Neg(_)) => Expr::Sub(rhs, Box::new(lhs.neg())),
-        Expr::Sub(lhs, rhs) if *lhs == *rhs => Expr::Const(0),
-        Expr::Sub(lhs, rhs) if matches!(*rhs, Expr::Neg(_)) => Expr::Add(lhs, Box::new(rhs.neg())),
-        Expr::Add(lhs, rhs) => {
-            let lhs_simplified = simplify(*lhs);
-            let rhs_simplified = simplify(*rhs);
-            if lhs_simplified == Expr::Const(0) {
-                rhs_simplified
-            } else if rhs_simplified == Expr::Const(0) {
-                lhs_simplified
-            } else {
-                Expr::Add(Box::new(lhs_simplified), Box::new(rhs_simplified))
-            }
-        }
-        Expr::Mul(lhs, rhs) => {
-            let lhs_simplified = simplify(*lhs);
-            let rhs_simplified = simplify(*rhs);
-            if lhs_simplified == Expr::Const(0) || rhs_simplified == Expr::Const(0) {
-                Expr::Const(0)
-            } else if lhs_simplified == Expr::Const(1) {
-                rhs_simplified
-            } else if rhs_simplified == Expr::Const(1) {
-                lhs_simplified
-            } else {
-                Expr::Mul(Box::new(lhs_simplified), Box::new(rhs_simplified))
+/// Integer multiple of pi within a function argument, e.g. `pi`, `tau` or `3pi`.
+fn pi_multiple(arg: &Expr) -> Option<u32> {
+    match arg {
+        Expr::Const(0) => Some(0),
+        Expr::Pi => Some(1),
+        Expr::Tau => Some(2),
+        Expr::Mul(lhs, rhs) => match (lhs.as_ref(), rhs.as_ref()) {
+            (Expr::Const(n), other) | (other, Expr::Const(n)) => {
+                pi_multiple(other).and_then(|m| n.checked_mul(m))
             }
-        }
-        Expr::Pow(base, exponent) => {
-            let base_simplified = simplify(*base);
-            let exponent_simplified = simplify(*exponent);
-
-            if exponent_simplified == Expr::Const(0) {
-                Expr::Const(1)
-            } else if exponent_simplified == Expr::Const(1) {
-                base_simplified
-            } else if base_simplified == Expr::Const(0) {
-                Expr::Const(0)
-            } else if base_simplified == Expr::Const(1) {
-                Expr::Const(1)
-            } else {
-                Expr::Pow(Box::new(base_simplified), Box::new(exponent_simplified))
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Cancels a non-constant common factor of both sides of a quotient, found as the GCD of
+/// their polynomial forms, e.g. `(x^2 - 1)/(x - 1)` becomes `x + 1`, if `nonzero` allows
+/// dividing by it.
+fn cancel_common_factors(dividend: &Expr, divisor: &Expr) -> Option<Expr> {
+    let (numerator, denominator) = (
+        Poly::from_expr(dividend).ok()?,
+        Poly::from_expr(divisor).ok()?,
+    );
+    // the gcd takes a step per degree, too many for powers like x^20000
+    if numerator.is_zero()
+        || numerator.max_degree().max(denominator.max_degree()) > MAX_FACTORED_DEGREE
+    {
+        return None;
+    }
+    let common = poly_gcd(&numerator, &denominator)?;
+    if common.as_constant().is_some() {
+        return None;
+    }
+    let reduced = RationalFunction::new(numerator, denominator).ok()?;
+    if !cancellable(&common.to_expr()?) {
+        return None;
+    }
+    if reduced.is_polynomial() {
+        reduced.numerator().to_expr()
+    } else {
+        reduced.to_expr()
+    }
+}
+
+/// Whether an expression may be cancelled from both sides of a quotient.
+type NonzeroCheck = Arc<dyn Fn(&Expr) -> bool + Send + Sync>;
+
+thread_local! {
+    /// Decides which factors the [`simplify_assuming`] running on this thread may cancel,
+    /// including in the simplifications [`collect_product`] and [`collect_sum`] start.
+    static NONZERO: RefCell<Option<NonzeroCheck>> = const { RefCell::new(None) };
+}
+
+/// Whether `e` may be cancelled from both sides of a quotient: always in [`simplify`],
+/// otherwise if the assumptions it runs under imply that `e` is nonzero.
+pub(crate) fn cancellable(e: &Expr) -> bool {
+    match NONZERO.with(|check| check.borrow().clone()) {
+        Some(nonzero) => nonzero(e),
+        None => true,
+    }
+}
+
+/// Whether a condition holds, recording it if it does.
+type Justification = Arc<dyn Fn(Condition) -> bool + Send + Sync>;
+
+fn a() -> Expr {
+    Expr::Var('a')
+}
+
+fn b() -> Expr {
+    Expr::Var('b')
+}
+
+fn is_number(e: &Expr) -> bool {
+    Rational::from_expr(e).is_some()
+}
+
+/// The rules [`simplify`] applies, in order of priority. Sums and products are brought
+/// into the canonical form of [`collect_sum`] and [`collect_product`], which collects
+/// like terms and folds numbers; the remaining rules are local identities. Quotients are
+/// cancelled assuming the cancelled factor is nonzero, unless run by [`simplify_assuming`].
+pub fn rules() -> Vec<Rule> {
+    let sum = |e: Expr| Some(collect_sum(e));
+    vec![
+        Rule::computed("sum", a().plus(b()), move |b| {
+            sum(b['a'].clone().plus(b['b'].clone()))
+        }),
+        Rule::computed("difference", a().minus(b()), move |b| {
+            sum(b['a'].clone().minus(b['b'].clone()))
+        }),
+        Rule::computed("negation", a().neg(), move |b| {
+            sum(Expr::Neg(Box::new(b['a'].clone())))
+        }),
+        Rule::computed("product", a().times(b()), |b| {
+            Some(collect_product(b['a'].clone().times(b['b'].clone())))
+        }),
+        Rule::new("zero exponent", a().pow(num(0)), num(1)),
+        Rule::new("unit exponent", a().pow(num(1)), a()),
+        Rule::new("zero base", num(0).pow(a()), num(0)),
+        Rule::new("unit base", num(1).pow(a()), num(1)),
+        Rule::computed("powers of i", I.pow(a()), |b| match b['a'] {
+            Expr::Const(n) => Some(match n % 4 {
+                0 => num(1),
+                1 => I,
+                2 => num(1).neg(),
+                _ => I.neg(),
+            }),
+            _ => None,
+        }),
+        // numbers beyond u32 come back as powers like 2^40, which would match again
+        Rule::computed("numeric power", a().pow(b()), |b| {
+            let power = Rational::from_expr(&b['a'].clone().pow(b['b'].clone()))?.to_expr()?;
+            (!matches!(power, Expr::Pow(_, _))).then_some(power)
+        }),
+        // (a^m)^n = a^(mn) for integers m and n whatever a is, unlike other exponents
+        Rule::computed("integer power of power", a().pow(b()).pow(var('c')), |b| {
+            let (m, n) = (Rational::from_expr(&b['b'])?, Rational::from_expr(&b['c'])?);
+            if !m.is_integer() || !n.is_integer() {
+                return None;
             }
-        }
-        Expr::Div(dividend, divisor) => {
-            let dividend_simplified = simplify(*dividend);
-            let divisor_simplified = simplify(*divisor);
-            if divisor_simplified == Expr::Const(1) {
-                divisor_simplified
-            } else if divisor_simplified == dividend_simplified {
-                Expr::Const(1)
-            } else {
-                Expr::Div(Box::new(dividend_simplified), Box::new(divisor_simplified))
+            Some(b['a'].clone().pow(m.checked_mul(n)?.to_expr()?))
+        }),
+        Rule::computed("numeric divisor", a().div(b()), |b| {
+            Some(collect_product(b['a'].clone().div(b['b'].clone())))
+        })
+        .when(|b| Rational::from_expr(&b['b']).is_some_and(|d| !d.is_zero())),
+        Rule::computed("numeric factor of divisor", a().div(b()), |b| {
+            let (dividend_coefficient, dividend) = split_coefficient(b['a'].clone());
+            let (divisor_coefficient, divisor) = split_coefficient(b['b'].clone());
+            let coefficient =
+                dividend_coefficient.checked_mul(divisor_coefficient.checked_recip()?)?;
+            if coefficient.is_zero() {
+                return Some(num(0));
             }
-        }
-        Expr::Func(name, arg) => Expr::Func(name, Box::new(simplify(*arg))),
-        other => other,
+            // the denominator of the coefficient stays in the divisor, as in `2 / (3 * x)`
+            let magnitude = coefficient.abs();
+            let quotient = Expr::Div(
+                Box::new(with_coefficient(
+                    Rational::integer(magnitude.numer()),
+                    dividend,
+                )?),
+                Box::new(with_coefficient(
+                    Rational::integer(magnitude.denom()),
+                    divisor,
+                )?),
+            );
+            let quotient = match coefficient.is_negative() {
+                true => Expr::Neg(Box::new(quotient)),
+                false => quotient,
+            };
+            (quotient != b['a'].clone().div(b['b'].clone())).then_some(quotient)
+        })
+        .when(|b| {
+            let (coefficient, divisor) = split_coefficient(b['b'].clone());
+            !coefficient.is_one() && divisor != num(1)
+        }),
+        Rule::new("self division", a().div(a()), num(1))
+            .when(|b| !is_number(&b['a']) && cancellable(&b['a'])),
+        Rule::computed("common factors", a().div(b()), |b| {
+            cancel_common_factors(&b['a'], &b['b'])
+        }),
+        // exact values of functions at named constants, so that `sin(pi)` stays `0` rather
+        // than a floating point approximation of it
+        Rule::computed("sin at multiples of pi", sin(a()), |b| {
+            pi_multiple(&b['a']).map(|_| num(0))
+        }),
+        Rule::computed("tan at multiples of pi", tan(a()), |b| {
+            pi_multiple(&b['a']).map(|_| num(0))
+        }),
+        Rule::computed("cos at multiples of pi", cos(a()), |b| {
+            pi_multiple(&b['a']).map(|n| if n % 2 == 0 { num(1) } else { num(1).neg() })
+        }),
+        Rule::new("ln of 1", func("ln", num(1)), num(0)),
+        Rule::new("ln of e", func("ln", E), num(1)),
+        Rule::new("exp of 0", func("exp", num(0)), num(1)),
+    ]
+}
+
+static CHECK_REWRITES: AtomicBool = AtomicBool::new(false);
+
+/// Makes [`simplify`] check every rule it applies, see [`Rewriter::checked`]. Has no effect
+/// in release builds. Setting the `RSDE_CHECK_REWRITES` environment variable turns the
+/// checks on as well, e.g. for a whole test run.
+pub fn check_rewrites(enabled: bool) {
+    CHECK_REWRITES.store(enabled, Ordering::Relaxed);
+}
+
+fn checking() -> bool {
+    static FROM_ENV: OnceLock<bool> = OnceLock::new();
+    cfg!(debug_assertions)
+        && (CHECK_REWRITES.load(Ordering::Relaxed)
+            || *FROM_ENV.get_or_init(|| std::env::var_os("RSDE_CHECK_REWRITES").is_some()))
+}
+
+fn rewriter() -> &'static Rewriter {
+    static REWRITER: OnceLock<Rewriter> = OnceLock::new();
+    static CHECKED: OnceLock<Rewriter> = OnceLock::new();
+    if checking() {
+        CHECKED.get_or_init(|| Rewriter::new(rules()).checked())
+    } else {
+        REWRITER.get_or_init(|| Rewriter::new(rules()))
     }
 }
 
+pub fn simplify(expr: Expr) -> Expr {
+    rewriter().rewrite(expr)
+}
+
+/// Identities that only hold for some values of their variables, each firing only if its
+/// condition is justified.
+fn conditional_rules(justify: Justification) -> Vec<Rule> {
+    let positive = |name: char, justify: &Justification| {
+        let justify = justify.clone();
+        move |b: &Bindings| justify(Condition::new(b[name].clone(), Predicate::Positive))
+    };
+    let real = |name: char, justify: &Justification| {
+        let justify = justify.clone();
+        move |b: &Bindings| justify(Condition::new(b[name].clone(), Predicate::Real))
+    };
+    let half = || num(1).div(num(2));
+    let power_of_power = justify.clone();
+    vec![
+        Rule::new("root of square", a().pow(num(2)).pow(half()), a()).when(positive('a', &justify)),
+        Rule::new("sqrt of square", func("sqrt", a().pow(num(2))), a())
+            .when(positive('a', &justify)),
+        Rule::new(
+            "power of power",
+            a().pow(b()).pow(var('c')),
+            a().pow(b().times(var('c'))),
+        )
+        .when(move |b| {
+            power_of_power(Condition::new(b['c'].clone(), Predicate::Integer))
+                || power_of_power(Condition::new(b['a'].clone(), Predicate::Positive))
+        }),
+        Rule::new("ln of power of e", func("ln", E.pow(a())), a()).when(real('a', &justify)),
+        Rule::new("ln of exp", func("ln", func("exp", a())), a()).when(real('a', &justify)),
+        Rule::new("e to the ln", E.pow(func("ln", a())), a()).when(positive('a', &justify)),
+        Rule::new("exp of ln", func("exp", func("ln", a())), a()).when(positive('a', &justify)),
+    ]
+}
+
+/// Runs `simplify`, handed a simplification using only what follows from `assumptions`,
+/// and returns its result along with the conditions it relied on.
+pub(crate) fn assuming(
+    assumptions: &Assumptions,
+    simplify: impl FnOnce(&dyn Fn(Expr) -> Expr) -> Expr,
+) -> Conditional {
+    let used: Arc<Mutex<BTreeSet<Condition>>> = Arc::default();
+    let justify: Justification = {
+        let (assumptions, used) = (assumptions.clone(), used.clone());
+        Arc::new(move |condition| {
+            let holds = assumptions.implies(&condition);
+            if holds {
+                used.lock().expect("no rule panics").insert(condition);
+            }
+            holds
+        })
+    };
+    let nonzero: NonzeroCheck = {
+        let justify = justify.clone();
+        Arc::new(move |e| justify(Condition::new(e.clone(), Predicate::Nonzero)))
+    };
+    let rules = conditional_rules(justify)
+        .into_iter()
+        .chain(rules())
+        .collect();
+    let rewriter = Rewriter::new(rules);
+    let outer = NONZERO.with(|check| check.replace(Some(nonzero)));
+    let expr = simplify(&|e| rewriter.rewrite(e));
+    NONZERO.with(|check| check.replace(outer));
+    let conditions = used.lock().expect("no rule panics").clone();
+    Conditional { expr, conditions }
+}
+
+/// Simplifies `expr` using only what follows from `assumptions`: quotients are cancelled
+/// only by factors known to be nonzero, and identities such as `sqrt(x^2) = x`,
+/// `ln(e^x) = x` or `(x^a)^b = x^(ab)` fire only when their conditions are known to hold.
+/// The conditions the result relies on are returned along with it. Under
+/// [`Assumptions::generic`] this is [`simplify`] reporting the factors it cancelled.
+pub fn simplify_assuming(expr: Expr, assumptions: &Assumptions) -> Conditional {
+    assuming(assumptions, |simplify| simplify(expr))
+}
+
+/// [`simplify`] with `extra` rules tried before the built-in ones.
+pub fn simplify_with(expr: Expr, extra: &[Rule]) -> Expr {
+    let rules = extra.iter().cloned().chain(rules()).collect();
+    Rewriter::new(rules).rewrite(expr)
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
     use crate::expr::{syntax::*, Expr};
+    use crate::out::{standard, Out};
 
     #[test]
     fn test_add_0_is_self() -> Result<(), String> {
@@ -122,11 +358,11 @@ mod tests {
         }
     }
 
-    // 3 + (-2) => 3 - 2
+    // x + (-y) => x - y
     #[test]
     fn add_of_negated_rhs_is_sub() -> Result<(), String> {
-        let expr = simplify(num(3).plus(num(2).neg()));
-        let expected = Expr::Sub(Box::new(num(3)), Box::new(num(2)));
+        let expr = simplify(X.plus(var('y').neg()));
+        let expected = Expr::Sub(Box::new(X), Box::new(var('y')));
         if expr == expected {
             Ok(())
         } else {
@@ -134,11 +370,11 @@ mod tests {
         }
     }
 
-    // -(3) + 2 => 2 - 3
+    // -(x) + y => y - x
     #[test]
     fn add_of_negated_lhs_is_sub() -> Result<(), String> {
-        let expr = simplify(num(3).neg().plus(num(2)));
-        let expected = Expr::Sub(Box::new(num(2)), Box::new(num(3)));
+        let expr = simplify(X.neg().plus(var('y')));
+        let expected = Expr::Sub(Box::new(var('y')), Box::new(X));
         if expr == expected {
             Ok(())
         } else {
@@ -146,11 +382,11 @@ mod tests {
         }
     }
 
-    // 2 - -(3) => 2 + 3
+    // x - -(y) => x + y
     #[test]
     fn sub_of_negated_rhs_is_add() -> Result<(), String> {
-        let expr = simplify(num(2).minus(num(3).neg()));
-        let expected = num(2).plus(num(3));
+        let expr = simplify(X.minus(var('y').neg()));
+        let expected = X.plus(var('y'));
         if expr == expected {
             Ok(())
         } else {
@@ -245,4 +481,281 @@ mod tests {
             Err("function arguments not simplified".into())
         }
     }
+
+    #[test]
+    fn sin_of_pi_is_0() {
+        assert_eq!(simplify(sin(PI)), num(0));
+        assert_eq!(simplify(sin(num(3).times(PI))), num(0));
+        assert_eq!(simplify(tan(TAU)), num(0));
+    }
+
+    #[test]
+    fn cos_of_pi_multiples_is_exact() {
+        assert_eq!(simplify(cos(PI)), num(1).neg());
+        assert_eq!(simplify(cos(TAU)), num(1));
+        assert_eq!(simplify(cos(PI.times(num(4)))), num(1));
+    }
+
+    #[test]
+    fn named_constants_are_kept_symbolic() {
+        let expr = PHI.plus(num(0)).times(INF);
+        assert_eq!(simplify(expr), PHI.times(INF));
+    }
+
+    #[test]
+    fn like_terms_are_collected() {
+        // x + x => 2x
+        assert_eq!(simplify(X.plus(X)), num(2).times(X));
+        // 2x + 3x => 5x
+        assert_eq!(
+            simplify(num(2).times(X).plus(num(3).times(X))),
+            num(5).times(X)
+        );
+        // x*y - y*x => 0
+        let y = var('y');
+        assert_eq!(simplify(X.times(y.clone()).minus(y.times(X))), num(0));
+    }
+
+    #[test]
+    fn equal_bases_add_exponents() {
+        // x*x => x^2
+        assert_eq!(simplify(X.times(X)), X.pow(num(2)));
+        // x^2 * 3 * x => 3x^3
+        assert_eq!(
+            simplify(X.pow(num(2)).times(num(3)).times(X)),
+            num(3).times(X.pow(num(3)))
+        );
+        // x^a * x^b => x^(a + b)
+        let (a, b) = (var('a'), var('b'));
+        assert_eq!(
+            simplify(X.pow(a.clone()).times(X.pow(b.clone()))),
+            X.pow(a.plus(b))
+        );
+    }
+
+    #[test]
+    fn operands_are_sorted_canonically() {
+        let (y, z) = (var('y'), var('z'));
+        let lhs = simplify(z.clone().plus(X).times(y.clone()).plus(num(1)));
+        let rhs = simplify(num(1).plus(y.times(X.plus(z))));
+        assert_eq!(lhs, rhs);
+    }
+
+    #[test]
+    fn numeric_subexpressions_are_folded() {
+        assert_eq!(simplify(num(2).pow(num(10)).div(num(4))), num(256));
+        assert_eq!(simplify(num(3).minus(num(5))), num(2).neg());
+        assert_eq!(simplify(X.div(num(1))), X);
+        assert_eq!(simplify(num(3).times(X).div(num(6))), X.div(num(2)));
+    }
+
+    #[test]
+    fn infinities_are_not_cancelled() {
+        let expr = simplify(INF.minus(INF));
+        assert_ne!(expr, num(0));
+    }
+
+    #[test]
+    fn quotients_cancel_common_polynomial_factors() {
+        // (x^2 - 1)/(x - 1) => x + 1
+        let expr = simplify(X.pow(num(2)).minus(num(1)).div(X.minus(num(1))));
+        assert_eq!(expr, X.plus(num(1)));
+        // (x^2 + x)/(x^2 - 1) => x/(x - 1)
+        let expr = simplify(X.pow(num(2)).plus(X).div(X.pow(num(2)).minus(num(1))));
+        assert_eq!(expr, X.div(X.minus(num(1))));
+    }
+
+    #[test]
+    fn sums_of_fractions_share_a_denominator() {
+        // 1/(x - 1) - 1/(x + 1) => 2/(x^2 - 1)
+        let expr = num(1)
+            .div(X.minus(num(1)))
+            .minus(num(1).div(X.plus(num(1))));
+        let expected = num(2).div(X.pow(num(2)).minus(num(1)));
+        assert_eq!(simplify(expr), expected);
+    }
+
+    #[test]
+    fn numeric_factors_of_divisors_stay_in_the_divisor() {
+        // 2/(3x) is as short as it gets, and so is 2x/(3y) for 4x/(6y)
+        let expr = num(2).div(num(3).times(X));
+        let simplified = simplify(expr.clone());
+        assert_eq!(simplified, expr);
+        let written = |e: &Expr| standard().output(e).unwrap().len();
+        assert!(written(&simplified) <= written(&expr));
+        let expr = num(4).times(X).div(num(6).times(var('y')));
+        assert_eq!(simplify(expr), num(2).times(X).div(num(3).times(var('y'))));
+        assert_eq!(
+            simplify(num(3).times(num(1).div(num(3).times(X)))),
+            num(1).div(X)
+        );
+    }
+
+    #[test]
+    fn integer_powers_of_powers_multiply_exponents() {
+        let p = var('p');
+        let expr = simplify(p.clone().pow(num(2)).pow(num(2)).pow(num(2)));
+        assert_eq!(expr, p.clone().pow(num(8)));
+        let expr = simplify(p.clone().pow(num(3)).pow(num(2).neg()));
+        assert_eq!(expr, simplify(p.clone().pow(num(6).neg())));
+        // only for integers, (p^2)^(1/2) is |p|
+        let root = p.clone().pow(num(2)).pow(num(1).div(num(2)));
+        assert_eq!(simplify(root.clone()), root);
+    }
+
+    #[test]
+    fn quotient_rule_output_is_reduced() {
+        // d/dx (x^2/x) = (2x * x - 1 * x^2)/x^2 => 1
+        let expr = X.pow(num(2)).div(X);
+        let der = expr.derivative().with_respect_to('x').map(simplify);
+        assert_eq!(der, Ok(num(1)));
+    }
+
+    #[test]
+    fn nested_operands_are_simplified() {
+        // -((x^1)/(x^1)) - sin(2pi) => -1
+        let expr =
+            Expr::Neg(Box::new(X.pow(num(1)).div(X.pow(num(1))))).minus(sin(num(2).times(PI)));
+        assert_eq!(simplify(expr), num(1).neg());
+    }
+
+    #[test]
+    fn extra_rules_run_before_the_built_in_ones() {
+        let rules = [Rule::new(
+            "exp of ln",
+            func("exp", func("ln", var('a'))),
+            var('a'),
+        )];
+        let expr = func("exp", func("ln", X.plus(X)));
+        assert_eq!(simplify_with(expr, &rules), num(2).times(X));
+    }
+
+    #[test]
+    fn division_cancels_only_known_nonzero_factors() {
+        let y = var('y');
+        let expr = X.times(y.clone()).div(X);
+        let unknown = simplify_assuming(expr.clone(), &Assumptions::new());
+        assert_eq!(unknown.expr, expr);
+        assert!(unknown.conditions.is_empty());
+
+        let nonzero = Assumptions::new().assume('x', Predicate::Nonzero);
+        let known = simplify_assuming(expr, &nonzero);
+        assert_eq!(known.expr, y);
+        let expected: BTreeSet<_> = [Condition::new(X, Predicate::Nonzero)].into();
+        assert_eq!(known.conditions, expected);
+    }
+
+    #[test]
+    fn identities_fire_only_under_their_assumptions() {
+        let root_of_square = func("sqrt", X.pow(num(2)));
+        let ln_of_exp = func("ln", E.pow(X));
+        let power_of_power = X.pow(var('y')).pow(num(1).div(num(2)));
+
+        let none = Assumptions::new();
+        assert_eq!(
+            simplify_assuming(root_of_square.clone(), &none).expr,
+            root_of_square
+        );
+        assert_eq!(simplify_assuming(ln_of_exp.clone(), &none).expr, ln_of_exp);
+
+        let positive = Assumptions::new().assume('x', Predicate::Positive);
+        assert_eq!(simplify_assuming(root_of_square, &positive).expr, X);
+        assert_eq!(simplify_assuming(ln_of_exp, &positive).expr, X);
+        let halved = simplify_assuming(power_of_power, &positive);
+        assert_eq!(halved.expr, X.pow(var('y').div(num(2))));
+        assert!(halved
+            .conditions
+            .contains(&Condition::new(X, Predicate::Positive)));
+    }
+
+    #[test]
+    fn zero_times_infinity_is_not_folded() {
+        let expr = simplify(num(0).times(INF));
+        assert_ne!(expr, num(0));
+    }
+
+    #[test]
+    fn constants_beyond_u32_simplify() {
+        let expr = simplify(num(2).pow(num(33)));
+        assert_eq!(Rational::from_expr(&expr), Some(Rational::integer(1 << 33)));
+        let expr = simplify(
+            num(2)
+                .pow(num(120))
+                .times(X)
+                .plus(num(2).pow(num(120)).times(X)),
+        );
+        let (coefficient, monomial) = split_coefficient(expr);
+        assert_eq!(coefficient, Rational::integer(1 << 121));
+        assert_eq!(monomial, X);
+    }
+
+    #[test]
+    fn constants_beyond_u32_stay_readable() {
+        assert_eq!(simplify(num(2).pow(num(40))), num(2).pow(num(40)));
+        let expr = simplify(num(2).pow(num(64)).times(X));
+        assert_eq!(expr, num(2).pow(num(64)).times(X));
+        // not a power of a smaller integer, so there is nothing shorter to write
+        let odd = num(2).pow(num(40)).plus(num(1));
+        assert_eq!(simplify(odd.clone()), odd);
+        let big = num(4_294_967_295);
+        let factors = big
+            .clone()
+            .times(big.clone())
+            .times(big.clone())
+            .times(big.clone());
+        assert_eq!(simplify(factors), big.pow(num(4)));
+        let powers = num(2)
+            .pow(num(64))
+            .times(num(2).pow(num(64)))
+            .times(num(2).pow(num(64)));
+        assert_eq!(simplify(powers), num(2).pow(num(192)));
+        let product = simplify(num(3).times(num(2).pow(num(64))).times(num(2).pow(num(64))));
+        assert_eq!(product, num(3).times(num(2).pow(num(128))));
+    }
+
+    #[test]
+    fn coefficients_multiply_the_leftmost_factor() {
+        let y = var('y');
+        let expr = simplify(X.times(y.clone()).plus(X.times(y.clone())));
+        assert_eq!(expr, num(2).times(X).times(y));
+        assert_eq!(standard().output(&expr), Ok("2x * y".to_string()));
+        let expr = simplify(num(2).times(sin(X)).times(cos(X)));
+        assert_eq!(standard().output(&expr), Ok("2cos(x) * sin(x)".to_string()));
+    }
+
+    #[test]
+    fn generic_cancellation_is_reported() {
+        let generic = simplify_assuming(X.div(X), &Assumptions::generic());
+        assert_eq!(generic.expr, num(1));
+        let expected: BTreeSet<_> = [Condition::new(X, Predicate::Nonzero)].into();
+        assert_eq!(generic.conditions, expected);
+        // factors cancelled while collecting a product need the same justification
+        let y = var('y');
+        let expr = X.times(y.clone().div(X));
+        assert_ne!(simplify_assuming(expr.clone(), &Assumptions::new()).expr, y);
+        assert_eq!(simplify_assuming(expr, &Assumptions::generic()).expr, y);
+    }
+
+    #[test]
+    fn powers_cancel_only_nonzero_bases() {
+        let y = var('y');
+        let x_minus_1 = X.minus(num(1));
+        let products = [
+            (X.pow(num(1).neg()).times(X), X),
+            (X.pow(y.clone()).times(X.pow(y.neg())), X),
+            (
+                x_minus_1.clone().times(x_minus_1.clone().pow(num(1).neg())),
+                x_minus_1,
+            ),
+        ];
+        for (expr, base) in products {
+            let kept = simplify_assuming(expr.clone(), &Assumptions::new());
+            assert_ne!(kept.expr, num(1));
+            assert!(kept.conditions.is_empty());
+            let generic = simplify_assuming(expr, &Assumptions::generic());
+            assert_eq!(generic.expr, num(1));
+            let expected: BTreeSet<_> = [Condition::new(base, Predicate::Nonzero)].into();
+            assert_eq!(generic.conditions, expected);
+        }
+    }
 }
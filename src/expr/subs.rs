@@ -0,0 +1,93 @@
+use crate::expr::simplify::simplify;
+use crate::expr::Expr;
+use std::collections::BTreeMap;
+
+/// Replaces every occurrence of the variables in `substitutions` at once, so swapping `x`
+/// and `y` works, and a replacement is never substituted into again.
+pub fn substitute(e: &Expr, substitutions: &BTreeMap<char, Expr>) -> Expr {
+    map_subexpressions(e, &|node| match node {
+        Expr::Var(name) => substitutions.get(name).cloned(),
+        _ => None,
+    })
+}
+
+/// Replaces every occurrence of `target` as a subexpression of `e` with `with`.
+pub fn replace(e: &Expr, target: &Expr, with: &Expr) -> Expr {
+    map_subexpressions(e, &|node| (node == target).then(|| with.clone()))
+}
+
+/// Binds the variables in `values` and folds what became constant, leaving an expression
+/// in the remaining variables.
+pub fn evaluate_partially(e: &Expr, values: &BTreeMap<char, Expr>) -> Expr {
+    simplify(substitute(e, values))
+}
+
+/// Rebuilds `e` top-down, replacing the outermost subexpressions `f` returns a replacement
+/// for and leaving replacements alone.
+fn map_subexpressions(e: &Expr, f: &dyn Fn(&Expr) -> Option<Expr>) -> Expr {
+    if let Some(replacement) = f(e) {
+        return replacement;
+    }
+    let map = |inner: &Expr| Box::new(map_subexpressions(inner, f));
+    match e {
+        Expr::Add(lhs, rhs) => Expr::Add(map(lhs), map(rhs)),
+        Expr::Sub(lhs, rhs) => Expr::Sub(map(lhs), map(rhs)),
+        Expr::Mul(lhs, rhs) => Expr::Mul(map(lhs), map(rhs)),
+        Expr::Div(lhs, rhs) => Expr::Div(map(lhs), map(rhs)),
+        Expr::Pow(lhs, rhs) => Expr::Pow(map(lhs), map(rhs)),
+        Expr::Neg(inner) => Expr::Neg(map(inner)),
+        Expr::Func(name, arg) => Expr::Func(name.clone(), map(arg)),
+        leaf => leaf.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::expr::syntax::*;
+
+    #[test]
+    fn substitution_composes_expressions() {
+        // x^2 + x with x := 2y + 1
+        let y = var('y');
+        let e = X.pow(num(2)).plus(X);
+        let with = num(2).times(y.clone()).plus(num(1));
+        assert_eq!(
+            e.subs('x', with.clone()),
+            with.clone().pow(num(2)).plus(with)
+        );
+        assert_eq!(sin(X).subs('y', num(2)), sin(X));
+    }
+
+    #[test]
+    fn substitutions_are_simultaneous() {
+        let y = var('y');
+        let swapped = X
+            .minus(y.clone())
+            .subs_all(&[('x', y.clone()), ('y', X)].into());
+        assert_eq!(swapped, y.clone().minus(X));
+        assert_eq!(X.times(X).renamed('x', 't'), var('t').times(var('t')));
+    }
+
+    #[test]
+    fn subexpressions_are_replaced() {
+        let angle = num(2).times(X);
+        let e = sin(angle.clone()).plus(cos(angle.clone()));
+        assert_eq!(
+            e.replaced(&angle, var('u')),
+            sin(var('u')).plus(cos(var('u')))
+        );
+    }
+
+    #[test]
+    fn partial_evaluation_folds_constants() {
+        // x y + x^2 at x = 3
+        let y = var('y');
+        let e = X.times(y.clone()).plus(X.pow(num(2)));
+        let folded = e.evaluated_partially(&[('x', num(3))].into());
+        assert_eq!(folded, num(3).times(y).plus(num(9)).simplified());
+        assert_eq!(
+            cos(X.times(PI)).evaluated_partially(&[('x', num(2))].into()),
+            num(1)
+        );
+    }
+}
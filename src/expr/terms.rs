@@ -0,0 +1,367 @@
+use crate::expr::rational::Rational;
+use crate::expr::simplify::{cancellable, simplify};
+use crate::expr::Expr;
+use crate::poly::{poly_gcd, Poly, RationalFunction};
+use std::collections::BTreeMap;
+
+fn contains_inf(e: &Expr) -> bool {
+    match e {
+        Expr::Inf => true,
+        Expr::Add(lhs, rhs)
+        | Expr::Sub(lhs, rhs)
+        | Expr::Mul(lhs, rhs)
+        | Expr::Div(lhs, rhs)
+        | Expr::Pow(lhs, rhs) => contains_inf(lhs) || contains_inf(rhs),
+        Expr::Neg(inner) | Expr::Func(_, inner) => contains_inf(inner),
+        _ => false,
+    }
+}
+
+/// `c * monomial` with `c` as the innermost left factor, so that `2 * (x * y)` is built as
+/// `(2 * x) * y` like the rest of the product.
+fn times_on_left(c: Expr, monomial: Expr) -> Expr {
+    match monomial {
+        Expr::Mul(lhs, rhs) => Expr::Mul(Box::new(times_on_left(c, *lhs)), rhs),
+        factor => Expr::Mul(Box::new(c), Box::new(factor)),
+    }
+}
+
+/// Puts the numeric coefficient in front of an already canonical product, e.g. `-3/2 * xy`
+/// becomes `-((3 * xy) / 2)`. Returns `None` if the coefficient can't be written, see
+/// [`Rational::to_expr`].
+pub fn with_coefficient(coefficient: Rational, monomial: Expr) -> Option<Expr> {
+    if coefficient.is_zero() {
+        return Some(Expr::Const(0));
+    }
+    if monomial == Expr::Const(1) {
+        return coefficient.to_expr();
+    }
+    let magnitude = coefficient.abs();
+    let scaled = if magnitude.numer() == 1 {
+        monomial
+    } else {
+        times_on_left(Rational::integer(magnitude.numer()).to_expr()?, monomial)
+    };
+    let unsigned = if magnitude.denom() == 1 {
+        scaled
+    } else {
+        Expr::Div(
+            Box::new(scaled),
+            Box::new(Rational::integer(magnitude.denom()).to_expr()?),
+        )
+    };
+    Some(if coefficient.is_negative() {
+        Expr::Neg(Box::new(unsigned))
+    } else {
+        unsigned
+    })
+}
+
+/// Splits a simplified term into its numeric coefficient and the remaining monomial,
+/// which is `Const(1)` for purely numeric terms.
+pub fn split_coefficient(term: Expr) -> (Rational, Expr) {
+    if let Some(r) = Rational::from_expr(&term) {
+        return (r, Expr::Const(1));
+    }
+    match term {
+        Expr::Neg(inner) => {
+            let (c, m) = split_coefficient(*inner);
+            (-c, m)
+        }
+        // a coefficient that overflows stays part of the monomial
+        Expr::Div(dividend, divisor) => {
+            let split = Rational::from_expr(&divisor).and_then(|d| {
+                let (c, m) = split_coefficient(dividend.as_ref().clone());
+                Some((c.checked_div(d)?, m))
+            });
+            split.unwrap_or((Rational::ONE, Expr::Div(dividend, divisor)))
+        }
+        Expr::Mul(lhs, rhs) => {
+            let split = Rational::from_expr(&lhs).and_then(|c| {
+                let (inner, m) = split_coefficient(rhs.as_ref().clone());
+                Some((c.checked_mul(inner)?, m))
+            });
+            match (split, *lhs) {
+                (Some(split), _) => split,
+                // the coefficient of a longer product is its innermost left factor
+                (None, Expr::Mul(l, r)) => match split_coefficient(Expr::Mul(l, r)) {
+                    (c, Expr::Const(1)) => (c, *rhs),
+                    (c, m) => (c, Expr::Mul(Box::new(m), rhs)),
+                },
+                (None, lhs) => (Rational::ONE, Expr::Mul(Box::new(lhs), rhs)),
+            }
+        }
+        other => (Rational::ONE, other),
+    }
+}
+
+fn push_sum_terms(e: Expr, negated: bool, out: &mut Vec<(Rational, Expr)>) {
+    match e {
+        Expr::Add(lhs, rhs) => {
+            push_sum_terms(*lhs, negated, out);
+            push_sum_terms(*rhs, negated, out);
+        }
+        Expr::Sub(lhs, rhs) => {
+            push_sum_terms(*lhs, negated, out);
+            push_sum_terms(*rhs, !negated, out);
+        }
+        Expr::Neg(inner) => push_sum_terms(*inner, !negated, out),
+        term => {
+            let (c, m) = split_coefficient(term);
+            out.push((if negated { -c } else { c }, m));
+        }
+    }
+}
+
+/// The `(coefficient, monomial)` terms of an already simplified sum.
+pub fn sum_terms(e: Expr) -> Vec<(Rational, Expr)> {
+    let mut terms = Vec::new();
+    push_sum_terms(e, false, &mut terms);
+    terms
+}
+
+fn is_fraction(e: &Expr) -> bool {
+    matches!(e, Expr::Div(_, divisor) if Rational::from_expr(divisor).is_none())
+}
+
+/// Adds up the terms as a single rational function in lowest terms, unless that cancels a
+/// factor of the common denominator which may not be cancelled.
+fn over_common_denominator(terms: &[(Rational, Expr)]) -> Option<Expr> {
+    let mut sum = RationalFunction::from_poly(Poly::zero());
+    let mut common_denominator = Poly::one();
+    for (c, m) in terms {
+        let term = RationalFunction::from_expr(m).ok()?;
+        let denominator = term.denominator();
+        let shared = poly_gcd(&common_denominator, denominator)?;
+        common_denominator = common_denominator.times(&denominator.exact_div(&shared)?)?;
+        let scaled = term.times(&RationalFunction::from_poly(Poly::constant(*c)));
+        sum = sum.plus(&scaled.ok()?).ok()?;
+    }
+    let cancelled = common_denominator.exact_div(sum.denominator())?;
+    if cancelled.as_constant().is_none() && !cancellable(&cancelled.to_expr()?) {
+        return None;
+    }
+    let numerator = simplify(sum.numerator().to_expr()?);
+    if sum.is_polynomial() {
+        Some(numerator)
+    } else {
+        let denominator = simplify(sum.denominator().to_expr()?);
+        Some(Expr::Div(Box::new(numerator), Box::new(denominator)))
+    }
+}
+
+/// Flattens nested `Add`, `Sub` and `Neg` nodes, whose operands are already simplified,
+/// into a single sum, adds up the coefficients of like terms and rebuilds the sum in
+/// canonical order, with the constant term last. Sums whose coefficients overflow or can't
+/// be written are returned as they are.
+pub fn collect_sum(e: Expr) -> Expr {
+    let flat = sum_terms(e.clone());
+
+    let fractions = flat.iter().filter(|(_, m)| is_fraction(m)).count();
+    if fractions > 1 {
+        if let Some(combined) = over_common_denominator(&flat) {
+            return combined;
+        }
+    }
+
+    let mut constant = Rational::ZERO;
+    let mut like_terms: BTreeMap<Expr, Rational> = BTreeMap::new();
+    // inf - inf is undefined, so terms involving infinity are never combined
+    let mut unmergeable: Vec<(Rational, Expr)> = Vec::new();
+    for (c, m) in flat {
+        if m == Expr::Const(1) {
+            let Some(sum) = constant.checked_add(c) else {
+                return e;
+            };
+            constant = sum;
+        } else if contains_inf(&m) {
+            unmergeable.push((c, m));
+        } else {
+            let entry = like_terms.entry(m).or_insert(Rational::ZERO);
+            let Some(sum) = entry.checked_add(c) else {
+                return e;
+            };
+            *entry = sum;
+        }
+    }
+
+    let terms: Vec<(Rational, Expr)> = like_terms
+        .into_iter()
+        .map(|(m, c)| (c, m))
+        .chain(unmergeable)
+        .chain([(constant, Expr::Const(1))])
+        .collect();
+    build_sum(terms).unwrap_or(e)
+}
+
+/// Joins `(coefficient, monomial)` pairs into a sum, in the given order, turning negative
+/// coefficients into subtractions. The first positive term is moved to the front, so that
+/// `-x + y` reads as `y - x`. Returns `None` if a coefficient can't be written.
+pub fn build_sum(mut terms: Vec<(Rational, Expr)>) -> Option<Expr> {
+    terms.retain(|(c, _)| !c.is_zero());
+    if let Some(first_positive) = terms.iter().position(|(c, _)| !c.is_negative()) {
+        let leading = terms.remove(first_positive);
+        terms.insert(0, leading);
+    }
+
+    let mut result: Option<Expr> = None;
+    for (c, m) in terms {
+        result = Some(match result {
+            None => with_coefficient(c, m)?,
+            Some(acc) if c.is_negative() => {
+                Expr::Sub(Box::new(acc), Box::new(with_coefficient(-c, m)?))
+            }
+            Some(acc) => Expr::Add(Box::new(acc), Box::new(with_coefficient(c, m)?)),
+        });
+    }
+    Some(result.unwrap_or(Expr::Const(0)))
+}
+
+/// Whether `e` is a power of a number, like `2^40`, which is how numbers beyond `u32` are
+/// written.
+fn is_numeric_power(e: &Expr) -> bool {
+    matches!(e, Expr::Pow(base, exponent)
+        if matches!(**base, Expr::Const(_)) && matches!(**exponent, Expr::Const(_)))
+}
+
+/// Whether both exponents are numbers of the same sign.
+fn same_sign(a: &Expr, b: &Expr) -> bool {
+    match (Rational::from_expr(a), Rational::from_expr(b)) {
+        (Some(a), Some(b)) => !a.is_zero() && !b.is_zero() && a.is_negative() == b.is_negative(),
+        _ => false,
+    }
+}
+
+/// Pushes the factors of `e` as `(base, exponent)` pairs and multiplies numbers into
+/// `coefficient`, except for integers and their powers if `by_base`. Returns `None` if the
+/// coefficient overflows.
+fn push_factors(
+    e: Expr,
+    by_base: bool,
+    coefficient: &mut Rational,
+    out: &mut Vec<(Expr, Expr)>,
+) -> Option<()> {
+    if by_base && (matches!(e, Expr::Const(n) if n > 1) || is_numeric_power(&e)) {
+        out.push(match e {
+            Expr::Pow(base, exponent) => (*base, *exponent),
+            _ => (e, Expr::Const(1)),
+        });
+        return Some(());
+    }
+    let compound = matches!(e, Expr::Mul(..) | Expr::Neg(_) | Expr::Div(..));
+    if let Some(r) = Rational::from_expr(&e).filter(|_| !(by_base && compound)) {
+        *coefficient = coefficient.checked_mul(r)?;
+        return Some(());
+    }
+    match e {
+        Expr::Mul(lhs, rhs) => {
+            push_factors(*lhs, by_base, coefficient, out)?;
+            push_factors(*rhs, by_base, coefficient, out)?;
+        }
+        Expr::Neg(inner) => {
+            *coefficient = -*coefficient;
+            push_factors(*inner, by_base, coefficient, out)?;
+        }
+        Expr::Div(dividend, divisor) => match Rational::from_expr(&divisor) {
+            Some(d) if !d.is_zero() => {
+                *coefficient = coefficient.checked_div(d)?;
+                push_factors(*dividend, by_base, coefficient, out)?;
+            }
+            _ => out.push((Expr::Div(dividend, divisor), Expr::Const(1))),
+        },
+        Expr::Pow(base, exponent) => out.push((*base, *exponent)),
+        factor => out.push((factor, Expr::Const(1))),
+    }
+    Some(())
+}
+
+/// Flattens nested `Mul` nodes (along with negations and numeric divisors), whose operands
+/// are already simplified, into a single product, multiplies out the numeric coefficient, adds up the exponents of equal bases
+/// and rebuilds the product in canonical order with the coefficient in front. Numbers too
+/// large to multiply out are kept as powers instead, adding up the exponents of equal
+/// bases, e.g. `2^64 * 2^64` becomes `2^128`; products that can't be written either way are
+/// returned as they are.
+pub fn collect_product(e: Expr) -> Expr {
+    collected_product(e.clone(), false)
+        .or_else(|| collected_product(e.clone(), true))
+        .unwrap_or(e)
+}
+
+fn collected_product(e: Expr, by_base: bool) -> Option<Expr> {
+    let mut coefficient = Rational::ONE;
+    let mut flat = Vec::new();
+    push_factors(e, by_base, &mut coefficient, &mut flat)?;
+
+    // x^a * x^b = x^(a + b) unless x = 0 and the exponents may differ in sign, as in
+    // x * x^-1, so other exponents of a base that can't be cancelled stay apart
+    let mut powers: BTreeMap<Expr, Vec<Expr>> = BTreeMap::new();
+    for (base, exponent) in flat {
+        let exponents = powers.entry(base.clone()).or_default();
+        let mergeable = |existing: &&mut Expr| same_sign(existing, &exponent) || cancellable(&base);
+        match exponents.iter_mut().find(mergeable) {
+            Some(existing) => {
+                let sum = Expr::Add(Box::new(existing.clone()), Box::new(exponent));
+                *existing = simplify(sum);
+            }
+            None => exponents.push(exponent),
+        }
+    }
+
+    let mut factors: Vec<Expr> = Vec::new();
+    for (base, exponent) in powers
+        .into_iter()
+        .flat_map(|(base, exponents)| exponents.into_iter().map(move |e| (base.clone(), e)))
+    {
+        let factor = simplify(Expr::Pow(Box::new(base), Box::new(exponent)));
+        match Rational::from_expr(&factor) {
+            _ if is_numeric_power(&factor) && by_base => factors.push(factor),
+            // a power too large to be a coefficient
+            None if is_numeric_power(&factor) => return None,
+            Some(r) => coefficient = coefficient.checked_mul(r)?,
+            None => factors.push(factor),
+        }
+    }
+    let product = |factors: Vec<Expr>| {
+        factors
+            .into_iter()
+            .reduce(|acc, factor| Expr::Mul(Box::new(acc), Box::new(factor)))
+    };
+    if coefficient.is_zero() {
+        // 0 * inf is undefined
+        return match factors.iter().any(contains_inf) {
+            true => Some(Expr::Mul(
+                Box::new(Expr::Const(0)),
+                Box::new(product(factors)?),
+            )),
+            false => Some(Expr::Const(0)),
+        };
+    }
+
+    // a product with fractions becomes a single fraction, so that a factor of the divisor
+    // can cancel against the other factors
+    let (fractions, mut numerator): (Vec<Expr>, Vec<Expr>) =
+        factors.into_iter().partition(is_fraction);
+    if let [Expr::Div(dividend, divisor)] = &fractions[..] {
+        if numerator.is_empty() && !split_coefficient(divisor.as_ref().clone()).0.is_one() {
+            // the coefficient cancels against the divisor's, as in `3 * (1 / (3 * x))`
+            let dividend = with_coefficient(coefficient, dividend.as_ref().clone())?;
+            return Some(simplify(Expr::Div(Box::new(dividend), divisor.clone())));
+        }
+    }
+    if fractions.is_empty() || (fractions.len() == 1 && numerator.is_empty()) {
+        let monomial = product(numerator.into_iter().chain(fractions).collect());
+        return with_coefficient(coefficient, monomial.unwrap_or(Expr::Const(1)));
+    }
+    let mut denominator = Vec::new();
+    for fraction in fractions {
+        if let Expr::Div(dividend, divisor) = fraction {
+            numerator.push(*dividend);
+            denominator.push(*divisor);
+        }
+    }
+    let numerator = product(numerator).expect("a fraction has a dividend");
+    let denominator = product(denominator).expect("a fraction has a divisor");
+    let quotient = simplify(Expr::Div(Box::new(numerator), Box::new(denominator)));
+    let (c, monomial) = split_coefficient(quotient);
+    with_coefficient(coefficient.checked_mul(c)?, monomial)
+}
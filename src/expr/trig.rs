@@ -0,0 +1,529 @@
+use crate::expr::assumptions::{Assumptions, Conditional};
+use crate::expr::rational::Rational;
+use crate::expr::simplify::{assuming, simplify};
+use crate::expr::terms::{build_sum, split_coefficient, sum_terms};
+use crate::expr::Expr;
+
+/// Upper bound on simplify/rewrite rounds, each round strictly applies identities that
+/// shrink the expression so this is only a safety net.
+const MAX_ROUNDS: usize = 16;
+
+fn func(name: &str, arg: Expr) -> Expr {
+    Expr::Func(name.into(), Box::new(arg))
+}
+
+fn squared(e: Expr) -> Expr {
+    Expr::Pow(Box::new(e), Box::new(Expr::Const(2)))
+}
+
+fn sqrt(n: u32) -> Expr {
+    Expr::Pow(
+        Box::new(Expr::Const(n)),
+        Box::new(Expr::Div(
+            Box::new(Expr::Const(1)),
+            Box::new(Expr::Const(2)),
+        )),
+    )
+}
+
+/// `arg` as a rational multiple of pi.
+fn pi_fraction(arg: &Expr) -> Option<Rational> {
+    match split_coefficient(arg.clone()) {
+        (r, Expr::Const(1)) if r.is_zero() => Some(r),
+        (r, Expr::Pi) => Some(r),
+        (r, Expr::Tau) => r.checked_mul(Rational::integer(2)),
+        _ => None,
+    }
+}
+
+/// `r - floor(r / period) * period`, in `[0, period)`.
+fn reduce(r: Rational, period: i128) -> Option<Rational> {
+    let period = Rational::integer(period);
+    let quotient = r.checked_div(period)?;
+    let turns = quotient.numer().div_euclid(quotient.denom());
+    r.checked_sub(period.checked_mul(Rational::integer(turns))?)
+}
+
+/// `sin(r pi)` for the multiples of pi/6 and pi/4 with a known closed form.
+fn exact_sin(r: Rational) -> Option<Expr> {
+    let r = reduce(r, 2)?;
+    if r >= Rational::ONE {
+        return exact_sin(r.checked_sub(Rational::ONE)?).map(|v| Expr::Neg(Box::new(v)));
+    }
+    let r = if r > Rational::new(1, 2) {
+        Rational::ONE.checked_sub(r)?
+    } else {
+        r
+    };
+    let half = |e: Expr| Expr::Div(Box::new(e), Box::new(Expr::Const(2)));
+    match (r.numer(), r.denom()) {
+        (0, _) => Some(Expr::Const(0)),
+        (1, 6) => Some(half(Expr::Const(1))),
+        (1, 4) => Some(half(sqrt(2))),
+        (1, 3) => Some(half(sqrt(3))),
+        (1, 2) => Some(Expr::Const(1)),
+        _ => None,
+    }
+}
+
+fn exact_tan(r: Rational) -> Option<Expr> {
+    let r = reduce(r, 1)?;
+    let negated = r > Rational::new(1, 2);
+    let r = if negated {
+        Rational::ONE.checked_sub(r)?
+    } else {
+        r
+    };
+    let value = match (r.numer(), r.denom()) {
+        (0, _) => Expr::Const(0),
+        (1, 6) => Expr::Div(Box::new(sqrt(3)), Box::new(Expr::Const(3))),
+        (1, 4) => Expr::Const(1),
+        (1, 3) => sqrt(3),
+        _ => return None,
+    };
+    Some(if negated {
+        Expr::Neg(Box::new(value))
+    } else {
+        value
+    })
+}
+
+/// Parity and exact values of a single function application.
+fn rewrite_func(name: String, arg: Expr) -> Expr {
+    if let Some(r) = pi_fraction(&arg) {
+        let exact = match name.as_str() {
+            "sin" => exact_sin(r),
+            "cos" => r.checked_add(Rational::new(1, 2)).and_then(exact_sin),
+            "tan" => exact_tan(r),
+            _ => None,
+        };
+        if let Some(value) = exact {
+            return value;
+        }
+    }
+    match (name.as_str(), arg) {
+        ("sin" | "tan", Expr::Neg(inner)) => Expr::Neg(Box::new(func(&name, *inner))),
+        ("cos", Expr::Neg(inner)) => func("cos", *inner),
+        (_, arg) => Expr::Func(name, Box::new(arg)),
+    }
+}
+
+/// A term of a sum, its monomial flattened into factors.
+#[derive(Debug, Clone, PartialEq)]
+struct Term {
+    coefficient: Rational,
+    factors: Vec<Expr>,
+}
+
+impl Term {
+    fn new(coefficient: Rational, monomial: Expr) -> Self {
+        let mut factors = Vec::new();
+        flatten_product(monomial, &mut factors);
+        factors.retain(|f| *f != Expr::Const(1));
+        factors.sort();
+        Term {
+            coefficient,
+            factors,
+        }
+    }
+
+    fn with(coefficient: Rational, rest: &[Expr], extra: Expr) -> Self {
+        let monomial = rest
+            .iter()
+            .cloned()
+            .chain([extra])
+            .reduce(|acc, f| Expr::Mul(Box::new(acc), Box::new(f)))
+            .expect("at least one factor");
+        Term::new(coefficient, monomial)
+    }
+
+    /// Removes the factor `name(arg)^power`, for any argument if `arg` is `None`, returning
+    /// the argument and the remaining factors.
+    fn take(&self, name: &str, power: u32, arg: Option<&Expr>) -> Vec<(Expr, Vec<Expr>)> {
+        let mut found = Vec::new();
+        for (i, factor) in self.factors.iter().enumerate() {
+            let (f, p) = match factor {
+                Expr::Pow(base, exponent) => (base.as_ref(), exponent.as_ref()),
+                other => (other, &Expr::Const(1)),
+            };
+            if let (Expr::Func(n, a), Expr::Const(k)) = (f, p) {
+                if n == name && *k == power && arg.is_none_or(|arg| arg == a.as_ref()) {
+                    let mut rest = self.factors.clone();
+                    rest.remove(i);
+                    found.push((a.as_ref().clone(), rest));
+                }
+            }
+        }
+        found
+    }
+
+    fn to_pair(&self) -> (Rational, Expr) {
+        let monomial = self
+            .factors
+            .iter()
+            .cloned()
+            .reduce(|acc, f| Expr::Mul(Box::new(acc), Box::new(f)))
+            .unwrap_or(Expr::Const(1));
+        (self.coefficient, monomial)
+    }
+}
+
+fn flatten_product(e: Expr, out: &mut Vec<Expr>) {
+    match e {
+        Expr::Mul(lhs, rhs) => {
+            flatten_product(*lhs, out);
+            flatten_product(*rhs, out);
+        }
+        other => out.push(other),
+    }
+}
+
+/// Identities combining two terms of a sum into fewer or smaller terms.
+fn combine_pair(a: &Term, b: &Term) -> Option<Vec<Term>> {
+    let (c, d) = (a.coefficient, b.coefficient);
+
+    for (u, rest) in a.take("sin", 2, None) {
+        for (_, other) in b.take("cos", 2, Some(&u)) {
+            if rest != other {
+                continue;
+            }
+            // a sin^2 + b cos^2 = m + (a - m) sin^2 + (b - m) cos^2
+            if c.is_negative() == d.is_negative() {
+                let m = if c.abs() < d.abs() { c } else { d };
+                return Some(vec![
+                    Term::with(m, &rest, Expr::Const(1)),
+                    Term::with(c.checked_sub(m)?, &rest, squared(func("sin", u.clone()))),
+                    Term::with(d.checked_sub(m)?, &rest, squared(func("cos", u.clone()))),
+                ]);
+            }
+            // cos^2 - sin^2 = cos(2u)
+            if c == -d {
+                return Some(vec![Term::with(d, &rest, func("cos", times_two(&u)))]);
+            }
+        }
+    }
+
+    // 1 + tan^2 = 1/cos^2, 1 - sin^2 = cos^2 and 1 - cos^2 = sin^2
+    for (u, rest) in b.take("tan", 2, None) {
+        if rest == a.factors && c == d {
+            let secant_squared =
+                Expr::Div(Box::new(Expr::Const(1)), Box::new(squared(func("cos", u))));
+            return Some(vec![Term::with(c, &rest, secant_squared)]);
+        }
+    }
+    for (name, complement) in [("sin", "cos"), ("cos", "sin")] {
+        for (u, rest) in b.take(name, 2, None) {
+            if rest == a.factors && c == -d {
+                return Some(vec![Term::with(c, &rest, squared(func(complement, u)))]);
+            }
+        }
+    }
+
+    // sin a cos b ± cos a sin b = sin(a ± b), cos a cos b ∓ sin a sin b = cos(a ± b)
+    for (x, with_cos) in a.take("sin", 1, None) {
+        let a_sin = Term {
+            coefficient: c,
+            factors: with_cos,
+        };
+        for (y, rest) in a_sin.take("cos", 1, None) {
+            if x == y {
+                continue;
+            }
+            for (_, b_rest) in b.take("cos", 1, Some(&x)).into_iter().flat_map(|(_, r)| {
+                Term {
+                    coefficient: d,
+                    factors: r,
+                }
+                .take("sin", 1, Some(&y))
+            }) {
+                if b_rest == rest && (c == d || c == -d) {
+                    let arg = if c == d { plus(&x, &y) } else { minus(&x, &y) };
+                    return Some(vec![Term::with(c, &rest, func("sin", arg))]);
+                }
+            }
+        }
+    }
+    for (x, with_cos) in a.take("cos", 1, None) {
+        let a_cos = Term {
+            coefficient: c,
+            factors: with_cos,
+        };
+        for (y, rest) in a_cos.take("cos", 1, None) {
+            for (_, b_rest) in b.take("sin", 1, Some(&x)).into_iter().flat_map(|(_, r)| {
+                Term {
+                    coefficient: d,
+                    factors: r,
+                }
+                .take("sin", 1, Some(&y))
+            }) {
+                if b_rest == rest && (c == d || c == -d) {
+                    let arg = if c == -d { plus(&x, &y) } else { minus(&x, &y) };
+                    return Some(vec![Term::with(c, &rest, func("cos", arg))]);
+                }
+            }
+        }
+    }
+    None
+}
+
+fn plus(a: &Expr, b: &Expr) -> Expr {
+    Expr::Add(Box::new(a.clone()), Box::new(b.clone()))
+}
+
+fn minus(a: &Expr, b: &Expr) -> Expr {
+    Expr::Sub(Box::new(a.clone()), Box::new(b.clone()))
+}
+
+fn times_two(u: &Expr) -> Expr {
+    Expr::Mul(Box::new(Expr::Const(2)), Box::new(u.clone()))
+}
+
+/// sin u cos u = sin(2u) / 2
+fn double_angle(term: &Term) -> Option<Term> {
+    for (u, rest) in term.take("sin", 1, None) {
+        let without_sin = Term {
+            coefficient: term.coefficient,
+            factors: rest,
+        };
+        if let Some((_, rest)) = without_sin.take("cos", 1, Some(&u)).into_iter().next() {
+            let halved = term.coefficient.checked_div(Rational::integer(2))?;
+            return Some(Term::with(halved, &rest, func("sin", times_two(&u))));
+        }
+    }
+    None
+}
+
+fn rewrite_sum(e: Expr) -> Expr {
+    let mut terms: Vec<Term> = sum_terms(e.clone())
+        .into_iter()
+        .map(|(c, m)| Term::new(c, m))
+        .collect();
+    'search: loop {
+        for i in 0..terms.len() {
+            if let Some(term) = double_angle(&terms[i]) {
+                terms[i] = term;
+                continue 'search;
+            }
+            for j in 0..terms.len() {
+                if i == j {
+                    continue;
+                }
+                if let Some(replacement) = combine_pair(&terms[i], &terms[j]) {
+                    let (first, second) = (i.min(j), i.max(j));
+                    terms.remove(second);
+                    terms.remove(first);
+                    terms.extend(replacement.into_iter().filter(|t| !t.coefficient.is_zero()));
+                    continue 'search;
+                }
+            }
+        }
+        break;
+    }
+    build_sum(terms.iter().map(Term::to_pair).collect()).unwrap_or(e)
+}
+
+/// One bottom-up pass of the trigonometric identities over a simplified expression.
+fn rewrite(e: Expr) -> Expr {
+    match e {
+        Expr::Func(name, arg) => rewrite_func(name, rewrite(*arg)),
+        Expr::Add(lhs, rhs) => {
+            rewrite_sum(Expr::Add(Box::new(rewrite(*lhs)), Box::new(rewrite(*rhs))))
+        }
+        Expr::Sub(lhs, rhs) => {
+            rewrite_sum(Expr::Sub(Box::new(rewrite(*lhs)), Box::new(rewrite(*rhs))))
+        }
+        Expr::Mul(lhs, rhs) => {
+            rewrite_sum(Expr::Mul(Box::new(rewrite(*lhs)), Box::new(rewrite(*rhs))))
+        }
+        Expr::Neg(inner) => rewrite_sum(Expr::Neg(Box::new(rewrite(*inner)))),
+        Expr::Div(lhs, rhs) => Expr::Div(Box::new(rewrite(*lhs)), Box::new(rewrite(*rhs))),
+        Expr::Pow(lhs, rhs) => Expr::Pow(Box::new(rewrite(*lhs)), Box::new(rewrite(*rhs))),
+        other => other,
+    }
+}
+
+/// `simplify` followed by Pythagorean, double-angle, sum/difference and parity identities
+/// and exact values of sin, cos and tan at multiples of pi/6 and pi/4, repeated until the
+/// expression stops changing.
+pub fn simplify_trig(expr: Expr) -> Expr {
+    simplify_trig_using(expr, &simplify)
+}
+
+/// [`simplify_trig`] with [`simplify_assuming`] in place of `simplify`, along with the
+/// conditions the result relies on.
+///
+/// [`simplify_assuming`]: crate::expr::simplify::simplify_assuming
+pub fn simplify_trig_assuming(expr: Expr, assumptions: &Assumptions) -> Conditional {
+    assuming(assumptions, |simplify| simplify_trig_using(expr, simplify))
+}
+
+fn simplify_trig_using(expr: Expr, simplify: &dyn Fn(Expr) -> Expr) -> Expr {
+    let mut current = simplify(expr);
+    for _ in 0..MAX_ROUNDS {
+        let next = simplify(rewrite(current.clone()));
+        if next == current {
+            break;
+        }
+        current = next;
+    }
+    current
+}
+
+/// Rewrites sines and cosines of sums and of doubled arguments in terms of their parts,
+/// the inverse direction of the sum/difference and double-angle identities.
+pub fn expand_trig(expr: Expr) -> Expr {
+    match expr {
+        Expr::Func(name, arg) => {
+            let arg = simplify(expand_trig(*arg));
+            let halves = match &arg {
+                Expr::Mul(lhs, rhs) if **lhs == Expr::Const(2) => Some((**rhs).clone()),
+                _ => None,
+            };
+            let parts = match &arg {
+                Expr::Add(lhs, rhs) => Some(((**lhs).clone(), (**rhs).clone(), false)),
+                Expr::Sub(lhs, rhs) => Some(((**lhs).clone(), (**rhs).clone(), true)),
+                _ => None,
+            };
+            let sin = |e: &Expr| func("sin", e.clone());
+            let cos = |e: &Expr| func("cos", e.clone());
+            let product = |a: Expr, b: Expr| Expr::Mul(Box::new(a), Box::new(b));
+            let expanded = match (name.as_str(), halves, parts) {
+                ("sin", Some(u), _) => product(Expr::Const(2), product(sin(&u), cos(&u))),
+                ("cos", Some(u), _) => {
+                    Expr::Sub(Box::new(squared(cos(&u))), Box::new(squared(sin(&u))))
+                }
+                ("sin", _, Some((a, b, negated))) => {
+                    let (lhs, rhs) = (product(sin(&a), cos(&b)), product(cos(&a), sin(&b)));
+                    if negated {
+                        minus(&lhs, &rhs)
+                    } else {
+                        plus(&lhs, &rhs)
+                    }
+                }
+                ("cos", _, Some((a, b, negated))) => {
+                    let (lhs, rhs) = (product(cos(&a), cos(&b)), product(sin(&a), sin(&b)));
+                    if negated {
+                        plus(&lhs, &rhs)
+                    } else {
+                        minus(&lhs, &rhs)
+                    }
+                }
+                _ => return Expr::Func(name, Box::new(arg)),
+            };
+            // the parts may be sums or doubled themselves
+            simplify(expand_trig_parts(expanded))
+        }
+        Expr::Add(lhs, rhs) => simplify(plus(&expand_trig(*lhs), &expand_trig(*rhs))),
+        Expr::Sub(lhs, rhs) => simplify(minus(&expand_trig(*lhs), &expand_trig(*rhs))),
+        Expr::Mul(lhs, rhs) => simplify(Expr::Mul(
+            Box::new(expand_trig(*lhs)),
+            Box::new(expand_trig(*rhs)),
+        )),
+        Expr::Div(lhs, rhs) => simplify(Expr::Div(
+            Box::new(expand_trig(*lhs)),
+            Box::new(expand_trig(*rhs)),
+        )),
+        Expr::Pow(lhs, rhs) => simplify(Expr::Pow(
+            Box::new(expand_trig(*lhs)),
+            Box::new(expand_trig(*rhs)),
+        )),
+        Expr::Neg(inner) => simplify(Expr::Neg(Box::new(expand_trig(*inner)))),
+        other => other,
+    }
+}
+
+fn expand_trig_parts(e: Expr) -> Expr {
+    match e {
+        Expr::Func(_, _) => expand_trig(e),
+        Expr::Add(lhs, rhs) => plus(&expand_trig_parts(*lhs), &expand_trig_parts(*rhs)),
+        Expr::Sub(lhs, rhs) => minus(&expand_trig_parts(*lhs), &expand_trig_parts(*rhs)),
+        Expr::Mul(lhs, rhs) => Expr::Mul(
+            Box::new(expand_trig_parts(*lhs)),
+            Box::new(expand_trig_parts(*rhs)),
+        ),
+        Expr::Pow(lhs, rhs) => Expr::Pow(Box::new(expand_trig_parts(*lhs)), rhs),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::syntax::*;
+
+    #[test]
+    fn pythagorean_identity() {
+        // sin(x)^2 + cos(x)^2 => 1
+        let expr = sin(X).pow(num(2)).plus(cos(X).pow(num(2)));
+        assert_eq!(simplify_trig(expr), num(1));
+        // 3y sin(x)^2 + 3y cos(x)^2 => 3y
+        let y = var('y');
+        let expr = num(3)
+            .times(y.clone())
+            .times(sin(X).pow(num(2)))
+            .plus(num(3).times(y.clone()).times(cos(X).pow(num(2))));
+        assert_eq!(simplify_trig(expr), num(3).times(y));
+    }
+
+    #[test]
+    fn one_plus_tan_squared() {
+        let expr = num(1).plus(tan(X).pow(num(2)));
+        assert_eq!(simplify_trig(expr), num(1).div(cos(X).pow(num(2))));
+    }
+
+    #[test]
+    fn double_angle() {
+        // 2 sin(x) cos(x) => sin(2x)
+        let expr = num(2).times(sin(X)).times(cos(X));
+        assert_eq!(simplify_trig(expr), sin(num(2).times(X)));
+        // cos(x)^2 - sin(x)^2 => cos(2x)
+        let expr = cos(X).pow(num(2)).minus(sin(X).pow(num(2)));
+        assert_eq!(simplify_trig(expr), cos(num(2).times(X)));
+    }
+
+    #[test]
+    fn sum_and_difference() {
+        let y = var('y');
+        // sin(x)cos(y) + cos(x)sin(y) => sin(x + y)
+        let expr = sin(X)
+            .times(cos(y.clone()))
+            .plus(cos(X).times(sin(y.clone())));
+        assert_eq!(simplify_trig(expr), sin(X.plus(y.clone())));
+        // cos(x)cos(y) + sin(x)sin(y) => cos(x - y)
+        let expr = cos(X)
+            .times(cos(y.clone()))
+            .plus(sin(X).times(sin(y.clone())));
+        assert_eq!(simplify_trig(expr), cos(X.minus(y)));
+    }
+
+    #[test]
+    fn parity() {
+        assert_eq!(simplify_trig(sin(X.neg())), sin(X).neg());
+        assert_eq!(simplify_trig(cos(X.neg())), cos(X));
+    }
+
+    #[test]
+    fn exact_values_at_rational_multiples_of_pi() {
+        assert_eq!(simplify_trig(sin(PI.div(num(6)))), num(1).div(num(2)));
+        assert_eq!(simplify_trig(cos(PI.div(num(3)))), num(1).div(num(2)));
+        assert_eq!(
+            simplify_trig(cos(num(5).times(PI).div(num(6)))),
+            sqrt(3).div(num(2)).neg()
+        );
+        assert_eq!(
+            simplify_trig(tan(num(3).times(PI).div(num(4)))),
+            num(1).neg()
+        );
+        assert_eq!(
+            simplify_trig(sin(num(7).times(PI).div(num(4)))),
+            sqrt(2).div(num(2)).neg()
+        );
+    }
+
+    #[test]
+    fn expand_is_undone_by_simplify() {
+        let y = var('y');
+        let expr = sin(X.plus(y.clone())).times(cos(num(2).times(X)));
+        assert_eq!(simplify_trig(expand_trig(expr.clone())), simplify(expr));
+    }
+}
@@ -0,0 +1,205 @@
+use crate::expr::assumptions::{Assumptions, Predicate};
+use crate::expr::Expr;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+
+/// A value with a standard deviation, written `1.2 ± 0.05`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+    pub value: f64,
+    pub sigma: f64,
+}
+
+impl Measurement {
+    pub fn new(value: f64, sigma: f64) -> Self {
+        Measurement { value, sigma }
+    }
+
+    pub fn exact(value: f64) -> Self {
+        Measurement { value, sigma: 0.0 }
+    }
+
+    /// Parses `1.2±0.05`, `1.2+-0.05` or just `1.2`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid measurement [{}]", s);
+        let number = |n: &str| n.trim().parse::<f64>().map_err(|_| invalid());
+        match s.split_once('±').or_else(|| s.split_once("+-")) {
+            Some((value, sigma)) => {
+                let sigma = number(sigma)?;
+                if sigma < 0.0 {
+                    return Err(invalid());
+                }
+                Ok(Measurement::new(number(value)?, sigma))
+            }
+            None => Ok(Measurement::exact(number(s)?)),
+        }
+    }
+}
+
+impl Display for Measurement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ± {}", self.value, self.sigma)
+    }
+}
+
+/// First-order propagation of uncertainty: the variance of `f(x1, x2, ...)` is
+/// `sum_ij df/dxi df/dxj cov(xi, xj)`, with `cov(xi, xi) = sigma_i^2`. Exact for linear
+/// functions, an approximation where `f` curves noticeably within a standard deviation.
+#[derive(Debug, Clone, Default)]
+pub struct Propagation {
+    inputs: BTreeMap<char, Measurement>,
+    covariances: BTreeMap<(char, char), f64>,
+}
+
+impl Propagation {
+    pub fn new() -> Self {
+        Propagation::default()
+    }
+
+    pub fn input(mut self, var: char, measurement: Measurement) -> Self {
+        self.inputs.insert(var, measurement);
+        self
+    }
+
+    pub fn inputs(mut self, inputs: &BTreeMap<char, Measurement>) -> Self {
+        self.inputs.extend(inputs);
+        self
+    }
+
+    /// Inputs are independent unless given a covariance here.
+    pub fn covariance(mut self, a: char, b: char, covariance: f64) -> Self {
+        self.covariances.insert((a.min(b), a.max(b)), covariance);
+        self
+    }
+
+    fn covariance_of(&self, a: char, b: char) -> f64 {
+        if a == b {
+            return self.inputs.get(&a).map_or(0.0, |m| m.sigma * m.sigma);
+        }
+        self.covariances
+            .get(&(a.min(b), a.max(b)))
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    /// The partial derivatives of `e` at the inputs with respect to the uncertain ones.
+    pub fn sensitivities(&self, e: &Expr) -> Result<BTreeMap<char, f64>, String> {
+        let point: HashMap<char, f64> = self.inputs.iter().map(|(&v, m)| (v, m.value)).collect();
+        // the derivatives only need to hold near the point, where inputs with positive
+        // values are positive
+        let assumptions = point
+            .iter()
+            .filter(|(_, &value)| value > 0.0)
+            .fold(Assumptions::new(), |a, (&v, _)| {
+                a.assume(v, Predicate::Positive)
+            });
+        let uncertain = self.inputs.iter().filter(|(_, m)| m.sigma != 0.0);
+        uncertain
+            .map(|(&var, _)| {
+                let partial = e
+                    .derivative()
+                    .with_respect_to_assuming(var, &assumptions)?
+                    .expr;
+                Ok((var, partial.solve_for(&point)?))
+            })
+            .collect()
+    }
+
+    pub fn propagate(&self, e: &Expr) -> Result<Measurement, String> {
+        let point = self.inputs.iter().map(|(&v, m)| (v, m.value)).collect();
+        let value = e.solve_for(&point)?;
+        let partials = self.sensitivities(e)?;
+        let (mut variance, mut scale) = (0.0, 0.0);
+        for (&a, da) in &partials {
+            for (&b, db) in &partials {
+                let term = da * db * self.covariance_of(a, b);
+                variance += term;
+                scale += term.abs();
+            }
+        }
+        // terms that cancel may leave a slightly negative rounding error
+        if variance < -1e-12 * scale {
+            return Err("covariances give a negative variance".into());
+        }
+        Ok(Measurement::new(value, variance.max(0.0).sqrt()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::syntax::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-12 * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn independent_inputs_add_in_quadrature() {
+        let y = var('y');
+        let sum = Propagation::new()
+            .input('x', Measurement::new(1.0, 0.3))
+            .input('y', Measurement::new(2.0, 0.4))
+            .propagate(&X.plus(y.clone()))
+            .unwrap();
+        assert!(close(sum.value, 3.0));
+        assert!(close(sum.sigma, 0.5));
+        // relative uncertainties of a product add in quadrature: 3% and 4% give 5%
+        let product = Propagation::new()
+            .input('x', Measurement::new(10.0, 0.3))
+            .input('y', Measurement::new(5.0, 0.2))
+            .propagate(&X.times(y))
+            .unwrap();
+        assert!(close(product.sigma, 0.05 * 50.0));
+    }
+
+    #[test]
+    fn functions_and_exact_inputs() {
+        // sigma of sin(x) is |cos(x)| sigma_x, exact inputs contribute nothing
+        let y = var('y');
+        let m = Propagation::new()
+            .input('x', Measurement::new(1.2, 0.05))
+            .input('y', Measurement::exact(3.0))
+            .propagate(&sin(X).times(y))
+            .unwrap();
+        assert!(close(m.value, 3.0 * 1.2f64.sin()));
+        assert!(close(m.sigma, 3.0 * 1.2f64.cos() * 0.05));
+        // x^y with an uncertain exponent needs ln x, fine near a positive x
+        let power = Propagation::new()
+            .input('x', Measurement::new(2.0, 0.0))
+            .input('y', Measurement::new(3.0, 0.1))
+            .propagate(&X.pow(var('y')))
+            .unwrap();
+        assert!(close(power.sigma, 8.0 * 2f64.ln() * 0.1));
+    }
+
+    #[test]
+    fn correlated_inputs() {
+        // x - y of fully correlated inputs with equal sigmas has no uncertainty
+        let inputs = Propagation::new()
+            .input('x', Measurement::new(5.0, 0.25))
+            .input('y', Measurement::new(3.0, 0.25))
+            .covariance('y', 'x', 0.0625);
+        let difference = inputs.propagate(&X.minus(var('y'))).unwrap();
+        assert_eq!(difference, Measurement::exact(2.0));
+        let sum = inputs.propagate(&X.plus(var('y'))).unwrap();
+        assert!(close(sum.sigma, 0.5));
+        let impossible = inputs.covariance('x', 'y', -1.0);
+        assert!(impossible.propagate(&X.plus(var('y'))).is_err());
+    }
+
+    #[test]
+    fn measurements_are_parsed() {
+        assert_eq!(
+            Measurement::parse("1.2±0.05"),
+            Ok(Measurement::new(1.2, 0.05))
+        );
+        assert_eq!(
+            Measurement::parse("-3+-0.5"),
+            Ok(Measurement::new(-3.0, 0.5))
+        );
+        assert_eq!(Measurement::parse("7"), Ok(Measurement::exact(7.0)));
+        assert!(Measurement::parse("1±-2").is_err());
+        assert_eq!(Measurement::new(1.5, 0.25).to_string(), "1.5 ± 0.25");
+    }
+}
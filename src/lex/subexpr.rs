@@ -9,6 +9,8 @@ pub enum SubExpr {
     Plus,
     Minus,
     Mul,
+    /// A product written without a sign, as in `2x` or `2(x + 1)`.
+    ImplicitMul,
     Div,
     Pow,
 }
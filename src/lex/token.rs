@@ -38,10 +38,30 @@ fn trimmed_chars(s: String) -> VecDeque<char> {
     s.replace(" ", "").chars().collect()
 }
 
+/// The constant a name stands for. These names are reserved, so there are no variables
+/// called `e`, `i`, `π`, `τ` or `φ`, and no functions called `pi` and so on without an
+/// argument.
+fn named_constant(name: &str) -> Option<Expr> {
+    match name {
+        "e" => Some(Expr::E),
+        "pi" | "π" => Some(Expr::Pi),
+        "tau" | "τ" => Some(Expr::Tau),
+        "phi" | "φ" => Some(Expr::Phi),
+        "i" => Some(Expr::I),
+        "inf" => Some(Expr::Inf),
+        _ => None,
+    }
+}
+
+/// Whether the variable `name` reads back as a named constant instead, like `e` and `i`.
+pub fn is_constant_name(name: char) -> bool {
+    named_constant(&name.to_string()).is_some()
+}
+
 pub fn tokenize(s: String) -> Result<Vec<SubExpr>, String> {
     let mut chars = trimmed_chars(s);
     let mut sub_expressions: Vec<SubExpr> = Vec::new();
-    
+
     while let Some(next) = chars.pop_front() {
         match next {
             '^' => sub_expressions.push(SubExpr::Pow),
@@ -61,35 +81,37 @@ pub fn tokenize(s: String) -> Result<Vec<SubExpr>, String> {
                 )?;
                 sub_expressions.push(SubExpr::S(Expr::Const(num)));
                 // if next is a variable, a function, or parenthesis, then that implicitly means multiplication:
-                match chars.get(0) {
-                    Some(&x) => {
-                        if x.is_alphabetic() || x == '(' {
-                            chars.push_front('*');
-                        }
+                if let Some(&x) = chars.front() {
+                    if x.is_alphabetic() || x == '(' || x == '∞' {
+                        sub_expressions.push(SubExpr::ImplicitMul);
                     }
-                    None => (),
                 }
             }
+            '∞' => sub_expressions.push(SubExpr::S(Expr::Inf)),
             a if a.is_alphabetic() => {
-                let next = chars.get(0);
+                let next = chars.front();
                 let next_is_alphabetic = next.is_some_and(|x| x.is_alphabetic());
                 let next_is_paren = next.is_some_and(|&x| x == '(');
-                // vars and e
+                // vars and single letter constants
                 if !next_is_alphabetic && !next_is_paren {
-                    let sub_expr = match a {
-                        'e' => SubExpr::S(Expr::E),
-                        _ => SubExpr::S(Expr::Var(a)),
+                    let sub_expr = match named_constant(&a.to_string()) {
+                        Some(constant) => SubExpr::S(constant),
+                        None => SubExpr::S(Expr::Var(a)),
                     };
                     sub_expressions.push(sub_expr)
                 } else {
-                    // func symbols
+                    // func symbols and named constants
                     let name = fold_while(
                         &mut chars,
                         |x| x.is_alphabetic(),
                         String::from(a),
                         |(s, ch)| format!("{}{}", s, ch),
                     );
-                    sub_expressions.push(SubExpr::F(name))
+                    let is_call = chars.front().is_some_and(|&x| x == '(');
+                    match named_constant(&name) {
+                        Some(constant) if !is_call => sub_expressions.push(SubExpr::S(constant)),
+                        _ => sub_expressions.push(SubExpr::F(name)),
+                    }
                 }
             }
             other => {
@@ -100,3 +122,59 @@ pub fn tokenize(s: String) -> Result<Vec<SubExpr>, String> {
 
     Ok(sub_expressions)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(s: &str) -> Vec<SubExpr> {
+        tokenize(s.into()).unwrap()
+    }
+
+    #[test]
+    fn names_of_constants() {
+        let constants = [
+            ("pi", Expr::Pi),
+            ("π", Expr::Pi),
+            ("tau", Expr::Tau),
+            ("τ", Expr::Tau),
+            ("phi", Expr::Phi),
+            ("φ", Expr::Phi),
+            ("e", Expr::E),
+            ("i", Expr::I),
+            ("inf", Expr::Inf),
+            ("∞", Expr::Inf),
+        ];
+        for (name, constant) in constants {
+            assert_eq!(tokens(name), [SubExpr::S(constant)], "{}", name);
+        }
+        // other letters are still variables
+        assert_eq!(tokens("p"), [SubExpr::S(Expr::Var('p'))]);
+    }
+
+    #[test]
+    fn constants_multiply_implicitly() {
+        for (s, constant) in [("2pi", Expr::Pi), ("3π", Expr::Pi), ("2∞", Expr::Inf)] {
+            let expected = [
+                SubExpr::S(Expr::Const(s[..1].parse().unwrap())),
+                SubExpr::ImplicitMul,
+                SubExpr::S(constant),
+            ];
+            assert_eq!(tokens(s), expected, "{}", s);
+        }
+    }
+
+    #[test]
+    fn names_followed_by_parentheses_are_functions() {
+        assert_eq!(
+            tokens("pi(x)"),
+            [
+                SubExpr::F("pi".into()),
+                SubExpr::OpenPar,
+                SubExpr::S(Expr::Var('x')),
+                SubExpr::ClosePar,
+            ]
+        );
+        assert_eq!(tokens("pi * (x)")[0], SubExpr::S(Expr::Pi));
+    }
+}
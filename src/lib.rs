@@ -1,5 +1,9 @@
 pub mod expr;
 pub mod lex;
 pub mod parser;
+pub mod poly;
 
 pub mod out;
+
+#[cfg(test)]
+mod test_util;
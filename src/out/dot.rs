@@ -0,0 +1,234 @@
+use super::Out;
+use crate::expr::dag::LetForm;
+use crate::expr::Expr;
+use std::collections::HashMap;
+
+/// Fill colors for shared subtrees, cycled through if there are more of them.
+const PALETTE: [&str; 8] = [
+    "#fbb4ae", "#b3cde3", "#ccebc5", "#decbe4", "#fed9a6", "#ffffcc", "#e5d8bd", "#fddaec",
+];
+
+/// The expression tree as a Graphviz digraph, one node per [`Expr`] variant with edges
+/// labelled by the operand they lead to.
+#[derive(Debug, Clone, Default)]
+pub struct DotOut {
+    highlight_shared: bool,
+}
+
+impl DotOut {
+    /// Fills every occurrence of a compound subtree that occurs more than once with the same
+    /// color.
+    pub fn highlight_shared(mut self) -> Self {
+        self.highlight_shared = true;
+        self
+    }
+
+    fn graph(&self, roots: &[&Expr]) -> Graph {
+        let mut graph = Graph::default();
+        if self.highlight_shared {
+            let mut counts = HashMap::new();
+            for root in roots {
+                count_subtrees(root, &mut counts);
+            }
+            // colors in the order the subtrees are first drawn, so output is stable
+            let mut order = Vec::new();
+            for root in roots {
+                shared_in_order(root, &counts, &mut order);
+            }
+            graph.colors = order
+                .into_iter()
+                .enumerate()
+                .map(|(i, e)| (e, PALETTE[i % PALETTE.len()]))
+                .collect();
+        }
+        graph
+    }
+}
+
+fn count_subtrees(e: &Expr, counts: &mut HashMap<Expr, usize>) {
+    if operands(e).is_empty() {
+        return;
+    }
+    *counts.entry(e.clone()).or_default() += 1;
+    for (_, operand) in operands(e) {
+        count_subtrees(operand, counts);
+    }
+}
+
+fn shared_in_order(e: &Expr, counts: &HashMap<Expr, usize>, order: &mut Vec<Expr>) {
+    if counts.get(e).is_some_and(|&n| n > 1) && !order.contains(e) {
+        order.push(e.clone());
+    }
+    for (_, operand) in operands(e) {
+        shared_in_order(operand, counts, order);
+    }
+}
+
+/// The operands of `e` with the labels of the edges leading to them.
+fn operands(e: &Expr) -> Vec<(&'static str, &Expr)> {
+    match e {
+        Expr::Add(lhs, rhs) | Expr::Sub(lhs, rhs) | Expr::Mul(lhs, rhs) | Expr::Div(lhs, rhs) => {
+            vec![("lhs", lhs), ("rhs", rhs)]
+        }
+        Expr::Pow(base, exponent) => vec![("base", base), ("exponent", exponent)],
+        Expr::Neg(inner) => vec![("inner", inner)],
+        Expr::Func(_, arg) => vec![("arg", arg)],
+        _ => vec![],
+    }
+}
+
+fn label(e: &Expr) -> String {
+    match e {
+        Expr::Const(n) => format!("Const({})", n),
+        Expr::Var(name) => format!("Var({})", name),
+        Expr::Func(name, _) => format!("Func({})", name),
+        Expr::E => "E".into(),
+        Expr::Pi => "Pi".into(),
+        Expr::Tau => "Tau".into(),
+        Expr::Phi => "Phi".into(),
+        Expr::I => "I".into(),
+        Expr::Inf => "Inf".into(),
+        Expr::Add(_, _) => "Add".into(),
+        Expr::Sub(_, _) => "Sub".into(),
+        Expr::Mul(_, _) => "Mul".into(),
+        Expr::Div(_, _) => "Div".into(),
+        Expr::Pow(_, _) => "Pow".into(),
+        Expr::Neg(_) => "Neg".into(),
+    }
+}
+
+fn quoted(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The statements of a digraph being drawn, with nodes numbered as they are added.
+#[derive(Default)]
+struct Graph {
+    statements: Vec<String>,
+    nodes: usize,
+    colors: HashMap<Expr, &'static str>,
+}
+
+impl Graph {
+    fn node(&mut self, label: &str, attributes: &str) -> String {
+        let id = format!("n{}", self.nodes);
+        self.nodes += 1;
+        self.statements
+            .push(format!("{} [label={}{}];", id, quoted(label), attributes));
+        id
+    }
+
+    fn edge(&mut self, from: &str, to: &str, label: &str) {
+        self.statements
+            .push(format!("{} -> {} [label={}];", from, to, quoted(label)));
+    }
+
+    /// Adds a node for every node of `e`, returning the root's.
+    fn tree(&mut self, e: &Expr) -> String {
+        let attributes = match self.colors.get(e) {
+            Some(color) => format!(", style=filled, fillcolor={}", quoted(color)),
+            None => String::new(),
+        };
+        let id = self.node(&label(e), &attributes);
+        for (edge, operand) in operands(e) {
+            let child = self.tree(operand);
+            self.edge(&id, &child, edge);
+        }
+        id
+    }
+
+    fn render(&self) -> String {
+        let mut lines = vec![
+            "digraph expr {".to_string(),
+            "    node [shape=box, fontname=\"monospace\"];".to_string(),
+        ];
+        lines.extend(self.statements.iter().map(|s| format!("    {}", s)));
+        lines.push("}".into());
+        lines.join("\n")
+    }
+}
+
+impl Out for DotOut {
+    fn output(&self, e: &Expr) -> Result<String, String> {
+        let mut graph = self.graph(&[e]);
+        graph.tree(e);
+        Ok(graph.render())
+    }
+
+    /// A whole graph.
+    fn is_inline(&self) -> bool {
+        false
+    }
+
+    /// One graph holding a tree per binding, hanging from a node naming it, and the body.
+    fn output_bindings(&self, form: &LetForm) -> Result<String, String> {
+        let mut roots: Vec<&Expr> = form.bindings.iter().map(|(_, value)| value).collect();
+        roots.push(&form.body);
+        let mut graph = self.graph(&roots);
+        for (name, value) in &form.bindings {
+            let binding = graph.node(&format!("let {}", name), ", shape=ellipse");
+            let value = graph.tree(value);
+            graph.edge(&binding, &value, "value");
+        }
+        graph.tree(&form.body);
+        Ok(graph.render())
+    }
+
+    fn output_derivative(&self, e: &Expr, var: char) -> Result<String, String> {
+        let mut graph = self.graph(&[e]);
+        let derivative = graph.node(&format!("d/d{}", var), ", shape=ellipse");
+        let body = graph.tree(e);
+        graph.edge(&derivative, &body, "of");
+        Ok(graph.render())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::expr::syntax::*;
+    use crate::out::{dot, Out};
+
+    #[test]
+    fn nodes_and_labelled_edges() {
+        let s = dot().output(&X.pow(num(2)).plus(sin(X))).unwrap();
+        let expected = [
+            "digraph expr {",
+            "    node [shape=box, fontname=\"monospace\"];",
+            "    n0 [label=\"Add\"];",
+            "    n1 [label=\"Pow\"];",
+            "    n2 [label=\"Var(x)\"];",
+            "    n1 -> n2 [label=\"base\"];",
+            "    n3 [label=\"Const(2)\"];",
+            "    n1 -> n3 [label=\"exponent\"];",
+            "    n0 -> n1 [label=\"lhs\"];",
+            "    n4 [label=\"Func(sin)\"];",
+            "    n5 [label=\"Var(x)\"];",
+            "    n4 -> n5 [label=\"arg\"];",
+            "    n0 -> n4 [label=\"rhs\"];",
+            "}",
+        ];
+        assert_eq!(s, expected.join("\n"));
+    }
+
+    #[test]
+    fn shared_subtrees_are_highlighted() {
+        let e = sin(X).plus(num(1)).times(sin(X).plus(num(1)).pow(num(2)));
+        assert!(!dot().output(&e).unwrap().contains("fillcolor"));
+        let s = dot().highlight_shared().output(&e).unwrap();
+        // both `sin(x) + 1` and the `sin(x)` inside them, but not the leaves
+        assert_eq!(s.matches("fillcolor=\"#fbb4ae\"").count(), 2);
+        assert_eq!(s.matches("fillcolor=\"#b3cde3\"").count(), 2);
+        assert_eq!(s.matches("fillcolor").count(), 4);
+    }
+
+    #[test]
+    fn bindings_and_notation_stay_one_graph() {
+        let form = sin(X).plus(sin(X).pow(num(2))).cse();
+        let s = dot().output_bindings(&form).unwrap();
+        assert_eq!(s.matches("digraph").count(), 1);
+        assert!(s.contains("[label=\"let u\", shape=ellipse];"));
+        let s = dot().output_derivative(&sin(X), 'x').unwrap();
+        assert!(s.contains("n0 [label=\"d/dx\", shape=ellipse];"));
+        assert!(s.contains("n0 -> n1 [label=\"of\"];"));
+    }
+}
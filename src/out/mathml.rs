@@ -0,0 +1,303 @@
+use super::{juxtaposes, precedence, Out};
+use crate::expr::dag::LetForm;
+use crate::expr::Expr;
+
+const NAMESPACE: &str = "http://www.w3.org/1998/Math/MathML";
+
+fn math(body: &str) -> String {
+    format!("<math xmlns=\"{}\">{}</math>", NAMESPACE, body)
+}
+
+/// `name` as element text, with the characters XML reserves there escaped.
+fn escaped(name: &str) -> String {
+    name.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Presentation MathML, describing how the expression looks.
+pub struct MathMlOut {}
+
+impl MathMlOut {
+    fn mrow(parts: &[String]) -> String {
+        format!("<mrow>{}</mrow>", parts.concat())
+    }
+
+    fn mo(op: &str) -> String {
+        format!("<mo>{}</mo>", op)
+    }
+
+    fn parenthesized(inner: String) -> String {
+        Self::mrow(&[Self::mo("("), inner, Self::mo(")")])
+    }
+
+    /// `e` in parentheses if it binds more loosely than `at_least`.
+    fn operand(&self, e: &Expr, at_least: u8) -> String {
+        let inner = self.presentation(e);
+        if precedence(e) < at_least {
+            Self::parenthesized(inner)
+        } else {
+            inner
+        }
+    }
+
+    fn binary(&self, lhs: &Expr, op: &str, rhs: &Expr, level: u8) -> String {
+        Self::mrow(&[
+            self.operand(lhs, level),
+            Self::mo(op),
+            self.operand(rhs, level + 1),
+        ])
+    }
+
+    fn presentation(&self, e: &Expr) -> String {
+        match e {
+            Expr::Var(name) => format!("<mi>{}</mi>", escaped(&name.to_string())),
+            Expr::Const(n) => format!("<mn>{}</mn>", n),
+            Expr::E => "<mi>e</mi>".into(),
+            Expr::Pi => "<mi>π</mi>".into(),
+            Expr::Tau => "<mi>τ</mi>".into(),
+            Expr::Phi => "<mi>φ</mi>".into(),
+            Expr::I => "<mi>i</mi>".into(),
+            Expr::Inf => "<mi>∞</mi>".into(),
+            Expr::Add(lhs, rhs) => self.binary(lhs, "+", rhs, 1),
+            Expr::Sub(lhs, rhs) => self.binary(lhs, "−", rhs, 1),
+            // invisible times where the factors can be juxtaposed, a middle dot elsewhere
+            Expr::Mul(lhs, rhs) => {
+                let times = if juxtaposes(rhs) { "\u{2062}" } else { "·" };
+                self.binary(lhs, times, rhs, 2)
+            }
+            Expr::Div(lhs, rhs) => format!(
+                "<mfrac>{}{}</mfrac>",
+                Self::mrow(&[self.presentation(lhs)]),
+                Self::mrow(&[self.presentation(rhs)])
+            ),
+            Expr::Pow(base, exponent) => format!(
+                "<msup>{}{}</msup>",
+                self.operand(base, 5),
+                Self::mrow(&[self.presentation(exponent)])
+            ),
+            Expr::Neg(inner) => Self::mrow(&[Self::mo("−"), self.operand(inner, 3)]),
+            Expr::Func(name, arg) if name == "sqrt" => {
+                format!("<msqrt>{}</msqrt>", self.presentation(arg))
+            }
+            // the invisible function application operator, then the argument
+            Expr::Func(name, arg) => Self::mrow(&[
+                format!("<mi>{}</mi>", escaped(name)),
+                Self::mo("\u{2061}"),
+                Self::parenthesized(self.presentation(arg)),
+            ]),
+        }
+    }
+}
+
+impl Out for MathMlOut {
+    fn output(&self, e: &Expr) -> Result<String, String> {
+        Ok(math(&self.presentation(e)))
+    }
+
+    /// A whole `<math>` element.
+    fn is_inline(&self) -> bool {
+        false
+    }
+
+    /// The body followed by `where u = ..., v = ...`, all in one `<math>` element.
+    fn output_bindings(&self, form: &LetForm) -> Result<String, String> {
+        let mut parts = vec![self.presentation(&form.body)];
+        for (i, (name, value)) in form.bindings.iter().enumerate() {
+            parts.push(match i {
+                0 => "<mtext>\u{a0}where\u{a0}</mtext>".into(),
+                _ => Self::mo(","),
+            });
+            parts.push(Self::mrow(&[
+                format!("<mi>{}</mi>", name),
+                Self::mo("="),
+                self.presentation(value),
+            ]));
+        }
+        Ok(math(&Self::mrow(&parts)))
+    }
+
+    /// A `d/dx` fraction in front of `e`, in the same `<math>` element.
+    fn output_derivative(&self, e: &Expr, var: char) -> Result<String, String> {
+        let var = format!("<mi>{}</mi>", escaped(&var.to_string()));
+        let operator = format!(
+            "<mfrac><mi>d</mi>{}</mfrac>",
+            Self::mrow(&["<mi>d</mi>".into(), var])
+        );
+        Ok(math(&Self::mrow(&[operator, self.operand(e, 5)])))
+    }
+}
+
+/// Content MathML, describing what the expression means.
+pub struct ContentMathMlOut {}
+
+impl ContentMathMlOut {
+    fn apply(op: &str, args: &[&Expr]) -> String {
+        let args: String = args.iter().map(|arg| Self::content(arg)).collect();
+        format!("<apply>{}{}</apply>", op, args)
+    }
+
+    fn bvar(name: char) -> String {
+        format!("<bvar><ci>{}</ci></bvar>", escaped(&name.to_string()))
+    }
+
+    fn content(e: &Expr) -> String {
+        match e {
+            Expr::Var(name) => format!("<ci>{}</ci>", escaped(&name.to_string())),
+            Expr::Const(n) => format!("<cn type=\"integer\">{}</cn>", n),
+            Expr::E => "<exponentiale/>".into(),
+            Expr::Pi => "<pi/>".into(),
+            Expr::Tau => Self::apply("<times/>", &[&Expr::Const(2), &Expr::Pi]),
+            Expr::Phi => "<ci>φ</ci>".into(),
+            Expr::I => "<imaginaryi/>".into(),
+            Expr::Inf => "<infinity/>".into(),
+            Expr::Add(lhs, rhs) => Self::apply("<plus/>", &[lhs, rhs]),
+            Expr::Sub(lhs, rhs) => Self::apply("<minus/>", &[lhs, rhs]),
+            Expr::Mul(lhs, rhs) => Self::apply("<times/>", &[lhs, rhs]),
+            Expr::Div(lhs, rhs) => Self::apply("<divide/>", &[lhs, rhs]),
+            Expr::Pow(base, exponent) => Self::apply("<power/>", &[base, exponent]),
+            Expr::Neg(inner) => Self::apply("<minus/>", &[inner]),
+            Expr::Func(name, arg) => match name.as_str() {
+                "sqrt" => Self::apply("<root/>", &[arg]),
+                "sin" | "cos" | "tan" | "cot" | "ln" | "exp" => {
+                    Self::apply(&format!("<{}/>", name), &[arg])
+                }
+                other => Self::apply(&format!("<ci>{}</ci>", escaped(other)), &[arg]),
+            },
+        }
+    }
+}
+
+impl Out for ContentMathMlOut {
+    fn output(&self, e: &Expr) -> Result<String, String> {
+        Ok(math(&Self::content(e)))
+    }
+
+    fn is_inline(&self) -> bool {
+        false
+    }
+
+    /// Each binding as a lambda applied to its value, so the body reads `u` as the value
+    /// bound to it, the outermost binding first.
+    fn output_bindings(&self, form: &LetForm) -> Result<String, String> {
+        let mut content = Self::content(&form.body);
+        for (name, value) in form.bindings.iter().rev() {
+            content = format!(
+                "<apply><lambda>{}{}</lambda>{}</apply>",
+                Self::bvar(*name),
+                content,
+                Self::content(value)
+            );
+        }
+        Ok(math(&content))
+    }
+
+    fn output_derivative(&self, e: &Expr, var: char) -> Result<String, String> {
+        Ok(math(&format!(
+            "<apply><diff/>{}{}</apply>",
+            Self::bvar(var),
+            Self::content(e)
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::expr::syntax::*;
+    use crate::out::{content_mathml, mathml, Out};
+
+    fn body(s: String) -> String {
+        let start = s.find('>').unwrap() + 1;
+        s[start..s.len() - "</math>".len()].to_string()
+    }
+
+    #[test]
+    fn presentation_markup() {
+        let p = |e| body(mathml().output(&e).unwrap());
+        assert_eq!(
+            p(num(1).div(X.plus(num(1)))),
+            "<mfrac><mrow><mn>1</mn></mrow><mrow><mrow><mi>x</mi><mo>+</mo><mn>1</mn></mrow></mrow></mfrac>"
+        );
+        assert_eq!(
+            p(X.plus(num(1)).pow(num(2))),
+            "<msup><mrow><mo>(</mo><mrow><mi>x</mi><mo>+</mo><mn>1</mn></mrow><mo>)</mo></mrow><mrow><mn>2</mn></mrow></msup>"
+        );
+        assert_eq!(
+            p(num(2).times(X)),
+            "<mrow><mn>2</mn><mo>\u{2062}</mo><mi>x</mi></mrow>"
+        );
+        assert_eq!(
+            p(X.times(num(2))),
+            "<mrow><mi>x</mi><mo>·</mo><mn>2</mn></mrow>"
+        );
+        assert_eq!(
+            p(sin(PI.minus(X))),
+            "<mrow><mi>sin</mi><mo>\u{2061}</mo><mrow><mo>(</mo><mrow><mi>π</mi><mo>−</mo><mi>x</mi></mrow><mo>)</mo></mrow></mrow>"
+        );
+        assert_eq!(p(func("sqrt", X)), "<msqrt><mi>x</mi></msqrt>");
+        let full = mathml().output(&X).unwrap();
+        assert_eq!(
+            full,
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mi>x</mi></math>"
+        );
+    }
+
+    #[test]
+    fn content_markup() {
+        let c = |e| body(content_mathml().output(&e).unwrap());
+        assert_eq!(
+            c(num(2).times(X).minus(func("ln", X))),
+            "<apply><minus/><apply><times/><cn type=\"integer\">2</cn><ci>x</ci></apply><apply><ln/><ci>x</ci></apply></apply>"
+        );
+        assert_eq!(
+            c(E.pow(X.neg())),
+            "<apply><power/><exponentiale/><apply><minus/><ci>x</ci></apply></apply>"
+        );
+    }
+
+    #[test]
+    fn names_are_escaped() {
+        let e = func("a<b&c>", X);
+        let presentation = body(mathml().output(&e).unwrap());
+        assert!(presentation.starts_with("<mrow><mi>a&lt;b&amp;c&gt;</mi>"));
+        assert_eq!(
+            body(content_mathml().output(&e).unwrap()),
+            "<apply><ci>a&lt;b&amp;c&gt;</ci><ci>x</ci></apply>"
+        );
+    }
+
+    #[test]
+    fn bindings_share_one_math_element() {
+        let form = sin(X).plus(sin(X).pow(num(2))).cse();
+        let s = mathml().output_bindings(&form).unwrap();
+        assert_eq!(s.matches("<math").count(), 1);
+        assert!(s.contains("<mtext>\u{a0}where\u{a0}</mtext>"));
+        let s = content_mathml().output_bindings(&form).unwrap();
+        assert_eq!(s.matches("<math").count(), 1);
+        assert!(!s.contains("let "));
+        let (name, _) = form.bindings[0];
+        assert_eq!(
+            body(s),
+            format!(
+                "<apply><lambda><bvar><ci>{0}</ci></bvar><apply><plus/><ci>{0}</ci>\
+                 <apply><power/><ci>{0}</ci><cn type=\"integer\">2</cn></apply></apply>\
+                 </lambda><apply><sin/><ci>x</ci></apply></apply>",
+                name
+            )
+        );
+    }
+
+    #[test]
+    fn derivative_notation() {
+        let e = X.pow(num(2)).plus(X);
+        let presentation = mathml().output_derivative(&e, 'x').unwrap();
+        assert_eq!(presentation.matches("<math").count(), 1);
+        assert!(body(presentation).starts_with(
+            "<mrow><mfrac><mi>d</mi><mrow><mi>d</mi><mi>x</mi></mrow></mfrac><mrow><mo>(</mo>"
+        ));
+        assert_eq!(
+            body(content_mathml().output_derivative(&sin(X), 'x').unwrap()),
+            "<apply><diff/><bvar><ci>x</ci></bvar><apply><sin/><ci>x</ci></apply></apply>"
+        );
+    }
+}
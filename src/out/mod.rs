@@ -1,61 +1,226 @@
+mod dot;
+mod mathml;
+mod pretty;
+
+use crate::expr::dag::LetForm;
 use crate::expr::Expr;
+use crate::lex::is_constant_name;
+
+pub use dot::DotOut;
 
 pub trait Out {
     fn output(&self, e: &Expr) -> Result<String, String>;
+
+    /// Whether the output is a single line of text that other text can be written around,
+    /// as in `d/dx x^2 = 2x`.
+    fn is_inline(&self) -> bool {
+        true
+    }
+
+    /// `form` as one `let` line per binding followed by its body.
+    fn output_bindings(&self, form: &LetForm) -> Result<String, String> {
+        let mut lines = Vec::new();
+        for (name, value) in &form.bindings {
+            lines.push(format!("let {} = {}", name, self.output(value)?));
+        }
+        lines.push(self.output(&form.body)?);
+        Ok(lines.join("\n"))
+    }
+
+    /// The derivative of `e` with respect to `var`, as notation rather than its result.
+    fn output_derivative(&self, e: &Expr, var: char) -> Result<String, String> {
+        Ok(format!("d/d{} {}", var, self.output_group(e)?))
+    }
+
+    /// `e`, parenthesized unless it is a single symbol or call.
+    fn output_group(&self, e: &Expr) -> Result<String, String> {
+        match precedence(e) {
+            5 => self.output(e),
+            _ => Ok(format!("({})", self.output(e)?)),
+        }
+    }
 }
 
 pub fn standard() -> impl Out {
     StandardOut {}
 }
 
+/// `e` as [`standard`] writes it, or its debug form where it can't, for messages that
+/// can't fail.
+pub(crate) fn describe(e: &Expr) -> String {
+    standard().output(e).unwrap_or_else(|_| format!("{:?}", e))
+}
+
 pub fn latex() -> impl Out {
     LatexOut {}
 }
 
+/// Presentation MathML, for rendering.
+pub fn mathml() -> impl Out {
+    mathml::MathMlOut {}
+}
+
+/// Content MathML, for other programs to read.
+pub fn content_mathml() -> impl Out {
+    mathml::ContentMathMlOut {}
+}
+
+/// Two-dimensional layout for terminals, drawn with Unicode box-drawing characters.
+pub fn pretty() -> impl Out {
+    pretty::PrettyOut { unicode: true }
+}
+
+/// Two-dimensional layout for terminals, in plain ASCII.
+pub fn ascii_pretty() -> impl Out {
+    pretty::PrettyOut { unicode: false }
+}
+
+/// The expression tree as a Graphviz DOT digraph.
+pub fn dot() -> DotOut {
+    DotOut::default()
+}
+
 struct StandardOut {}
 
+/// How tightly an expression binds, matching the grammar of [`crate::parser`]: operands
+/// that bind more loosely than their operator expects are parenthesized.
+fn precedence(e: &Expr) -> u8 {
+    match e {
+        Expr::Add(_, _) | Expr::Sub(_, _) => 1,
+        Expr::Mul(_, _) | Expr::Div(_, _) => 2,
+        Expr::Neg(_) => 3,
+        Expr::Pow(_, _) => 4,
+        _ => 5,
+    }
+}
+
+impl StandardOut {
+    /// `e`, in parentheses if it binds more loosely than `at_least`.
+    fn operand(&self, e: &Expr, at_least: u8) -> Result<String, String> {
+        let s = self.output(e)?;
+        if precedence(e) < at_least {
+            Ok(format!("({})", s))
+        } else {
+            Ok(s)
+        }
+    }
+
+    /// Whether `n * e` can be written `ne`, which the lexer reads back as a product: `e`
+    /// must start with a letter and not need parentheses.
+    fn juxtaposes(e: &Expr) -> bool {
+        match e {
+            Expr::Var(_) | Expr::E | Expr::Pi | Expr::Tau | Expr::Phi | Expr::I | Expr::Inf => true,
+            Expr::Func(_, _) => true,
+            Expr::Pow(base, _) => Self::juxtaposes(base),
+            _ => false,
+        }
+    }
+
+    fn binary(&self, lhs: &Expr, op: &str, rhs: &Expr, level: u8) -> Result<String, String> {
+        // left associative, so only the right operand needs parentheses at the same level
+        Ok(format!(
+            "{} {} {}",
+            self.operand(lhs, level)?,
+            op,
+            self.operand(rhs, level + 1)?
+        ))
+    }
+}
+
 impl Out for StandardOut {
+    /// `e` with only the parentheses needed to parse it back to `e`.
     fn output(&self, e: &Expr) -> Result<String, String> {
         match e {
+            // there is no way to write these that doesn't parse back to the constant
+            Expr::Var(c) if is_constant_name(*c) => Err(format!(
+                "variable [{}] can't be written, it reads as a constant",
+                c
+            )),
             Expr::Var(c) => Ok(c.to_string()),
             Expr::Const(num) => Ok(num.to_string()),
             Expr::E => Ok("e".into()),
-            Expr::Add(lhs, rhs) => Ok(format!("({} + {})", self.output(lhs)?, self.output(rhs)?)),
-            Expr::Mul(lhs, rhs) if matches!(**rhs, Expr::Var(_)) => {
+            Expr::Pi => Ok("pi".into()),
+            Expr::Tau => Ok("tau".into()),
+            Expr::Phi => Ok("phi".into()),
+            Expr::I => Ok("i".into()),
+            Expr::Inf => Ok("inf".into()),
+            Expr::Add(lhs, rhs) => self.binary(lhs, "+", rhs, 1),
+            Expr::Sub(lhs, rhs) => self.binary(lhs, "-", rhs, 1),
+            Expr::Mul(lhs, rhs) if matches!(**lhs, Expr::Const(_)) && Self::juxtaposes(rhs) => {
                 Ok(format!("{}{}", self.output(lhs)?, self.output(rhs)?))
             }
-            Expr::Mul(lhs, rhs) if matches!(**lhs, Expr::Var(_)) => {
-                Ok(format!("{}{}", self.output(rhs)?, self.output(lhs)?))
-            }
-            Expr::Mul(lhs, rhs) => Ok(format!("({} * {})", self.output(lhs)?, self.output(rhs)?)),
-            Expr::Sub(lhs, rhs) => Ok(format!("({} - {})", self.output(lhs)?, self.output(rhs)?)),
-            Expr::Div(lhs, rhs) => Ok(format!("({} / {})", self.output(lhs)?, self.output(rhs)?)),
+            Expr::Mul(lhs, rhs) => self.binary(lhs, "*", rhs, 2),
+            Expr::Div(lhs, rhs) => self.binary(lhs, "/", rhs, 2),
+            // right associative, and the exponent may be negated
             Expr::Pow(base, exponent) => Ok(format!(
-                "({}^{})",
-                self.output(base)?,
-                self.output(exponent)?
+                "{}^{}",
+                self.operand(base, 5)?,
+                self.operand(exponent, 3)?
             )),
-            Expr::Neg(expr) => Ok(format!("-{}", self.output(expr)?)),
-            Expr::Func(name, expr) => Ok(format!("{}({})", name, self.output(expr)?)),
+            Expr::Neg(inner) => Ok(format!("-{}", self.operand(inner, 3)?)),
+            Expr::Func(name, arg) => Ok(format!("{}({})", name, self.output(arg)?)),
         }
     }
 }
 
+/// Whether the factor `e` reads as part of a product when typeset right after another one,
+/// like `2x` or `x sin(x)`. Factors starting with a digit need a multiplication sign, and
+/// so do fractions, as `2 1/3` would read as a mixed number.
+fn juxtaposes(e: &Expr) -> bool {
+    match e {
+        Expr::Var(_) | Expr::E | Expr::Pi | Expr::Tau | Expr::Phi | Expr::I | Expr::Inf => true,
+        // in parentheses
+        Expr::Func(_, _) | Expr::Add(_, _) | Expr::Sub(_, _) => true,
+        Expr::Pow(base, _) => !matches!(**base, Expr::Const(_)),
+        Expr::Mul(lhs, _) => juxtaposes(lhs),
+        _ => false,
+    }
+}
+
 pub struct LatexOut {}
 
 impl LatexOut {
-    fn var_appears_next(e: &Expr) -> bool {
-        match e {
-            Expr::Var(_) | Expr::E => true,
-            Expr::Pow(lhs, _) => Self::var_appears_next(lhs.as_ref()),
-            _ => false,
+    /// `e` wrapped in `\left(` and `\right)` if it binds more loosely than `at_least`.
+    fn operand(&self, e: &Expr, at_least: u8) -> Result<String, String> {
+        let s = self.output(e)?;
+        if precedence(e) < at_least {
+            Ok(Self::parenthesized(&s))
+        } else {
+            Ok(s)
         }
     }
 
-    fn add_or_sub(e: &Expr) -> bool {
+    fn parenthesized(s: &str) -> String {
+        format!("\\left({}\\right)", s)
+    }
+
+    /// The command typesetting a function, upright like `\sin` rather than italic.
+    fn function_name(name: &str) -> String {
+        match name {
+            "sin" | "cos" | "tan" | "cot" | "ln" | "exp" => format!("\\{}", name),
+            other => format!("\\operatorname{{{}}}", other),
+        }
+    }
+
+    /// `-` and the magnitude of `e` if it prints with a leading minus sign.
+    fn negated(&self, e: &Expr) -> Result<Option<String>, String> {
+        let s = self.output(e)?;
         match e {
-            Expr::Add(_, _) | Expr::Sub(_, _) => true,
-            _ => false,
+            Expr::Neg(_) | Expr::Div(_, _) => Ok(s.strip_prefix('-').map(str::to_string)),
+            _ => Ok(None),
+        }
+    }
+
+    /// The magnitude of a numerator or denominator and whether it is negative. `\frac`
+    /// already sets it apart, so it isn't parenthesized.
+    fn fraction_part(&self, e: &Expr) -> Result<(String, bool), String> {
+        if let Expr::Neg(inner) = e {
+            let (magnitude, negative) = self.fraction_part(inner)?;
+            return Ok((magnitude, !negative));
+        }
+        match self.negated(e)? {
+            Some(magnitude) => Ok((magnitude, true)),
+            None => Ok((self.output(e)?, false)),
         }
     }
 }
@@ -64,40 +229,264 @@ impl Out for LatexOut {
     fn output(&self, e: &Expr) -> Result<String, String> {
         match e {
             Expr::Var(name) => Ok(name.to_string()),
-            Expr::Const(num) => Ok(format!("{{{num}}}")),
+            Expr::Const(num) => Ok(num.to_string()),
             Expr::E => Ok("e".into()),
-            Expr::Add(lhs, rhs) => Ok(format!("({} + {})", self.output(lhs)?, self.output(rhs)?)),
-            Expr::Sub(lhs, rhs) => Ok(format!("({} - {})", self.output(lhs)?, self.output(rhs)?)),
-            Expr::Mul(lhs, rhs) if matches!(**rhs, Expr::Func(_, _)) => {
-                Ok(format!("{}{}", self.output(lhs)?, self.output(rhs)?))
+            Expr::Pi => Ok("\\pi".into()),
+            Expr::Tau => Ok("\\tau".into()),
+            Expr::Phi => Ok("\\varphi".into()),
+            Expr::I => Ok("i".into()),
+            Expr::Inf => Ok("\\infty".into()),
+            // `a + -b` reads as `a - b`
+            Expr::Add(lhs, rhs) => match self.negated(rhs)? {
+                Some(magnitude) => Ok(format!("{} - {}", self.output(lhs)?, magnitude)),
+                None => Ok(format!("{} + {}", self.output(lhs)?, self.output(rhs)?)),
+            },
+            Expr::Sub(lhs, rhs) => {
+                let rhs = match self.negated(rhs)? {
+                    Some(_) => Self::parenthesized(&self.output(rhs)?),
+                    None => self.operand(rhs, 2)?,
+                };
+                Ok(format!("{} - {}", self.output(lhs)?, rhs))
             }
-            Expr::Mul(lhs, rhs) if matches!(**lhs, Expr::Func(_, _)) => {
-                Ok(format!("{}{}", self.output(rhs)?, self.output(lhs)?))
+            // products print the same however they associate
+            Expr::Mul(lhs, rhs) => {
+                let l = self.operand(lhs, 2)?;
+                let r = match self.negated(rhs)? {
+                    Some(_) => Self::parenthesized(&self.output(rhs)?),
+                    None => self.operand(rhs, 2)?,
+                };
+                if r.starts_with("\\left(") || juxtaposes(rhs) {
+                    if l.ends_with(|c: char| c.is_alphabetic())
+                        && r.starts_with(|c: char| c.is_alphabetic())
+                    {
+                        // `x y`, and `\pi x` rather than the undefined `\pix`
+                        Ok(format!("{} {}", l, r))
+                    } else {
+                        Ok(format!("{}{}", l, r))
+                    }
+                } else {
+                    Ok(format!("{} \\cdot {}", l, r))
+                }
             }
-            Expr::Mul(lhs, rhs) if Self::var_appears_next(rhs) => {
-                Ok(format!("{}{}", self.output(lhs)?, self.output(rhs)?))
+            // the sign goes in front of the fraction, not in its numerator or denominator
+            Expr::Div(dividend, divisor) => {
+                let (num, num_negative) = self.fraction_part(dividend)?;
+                let (den, den_negative) = self.fraction_part(divisor)?;
+                let sign = if num_negative != den_negative {
+                    "-"
+                } else {
+                    ""
+                };
+                Ok(format!("{}\\frac{{{}}}{{{}}}", sign, num, den))
             }
-            Expr::Mul(lhs, rhs) if Self::var_appears_next(lhs) => {
-                Ok(format!("{}{}", self.output(rhs)?, self.output(lhs)?))
+            // `\sin^{2}\left(x\right)` rather than `\left(\sin\left(x\right)\right)^{2}`, but
+            // only for positive integer powers, as `\sin^{-1}` reads as the inverse function
+            Expr::Pow(base, exponent) => match (base.as_ref(), exponent.as_ref()) {
+                (Expr::Func(name, arg), Expr::Const(n))
+                    if *n > 0 && ["sin", "cos", "tan", "cot"].contains(&name.as_str()) =>
+                {
+                    Ok(format!(
+                        "{}^{{{}}}{}",
+                        Self::function_name(name),
+                        n,
+                        Self::parenthesized(&self.output(arg)?)
+                    ))
+                }
+                // `\ln\left(x\right)^{2}` would read as the logarithm of a square
+                (Expr::Func(name, _), _) if name != "sqrt" => Ok(format!(
+                    "{}^{{{}}}",
+                    Self::parenthesized(&self.output(base)?),
+                    self.output(exponent)?
+                )),
+                _ => Ok(format!(
+                    "{}^{{{}}}",
+                    self.operand(base, 5)?,
+                    self.output(exponent)?
+                )),
+            },
+            Expr::Neg(inner) => {
+                let s = self.operand(inner, 2)?;
+                match s.starts_with('-') {
+                    true => Ok(format!("-{}", Self::parenthesized(&s))),
+                    false => Ok(format!("-{}", s)),
+                }
             }
-            Expr::Mul(lhs, rhs)
-                if !Self::add_or_sub(lhs.as_ref()) && !Self::add_or_sub(rhs.as_ref()) =>
-            {
-                Ok(format!("{} * {}", self.output(lhs)?, self.output(rhs)?))
+            Expr::Func(name, arg) if name == "sqrt" => {
+                Ok(format!("\\sqrt{{{}}}", self.output(arg)?))
             }
-            Expr::Mul(lhs, rhs) => Ok(format!("({} * {})", self.output(lhs)?, self.output(rhs)?)),
-            Expr::Pow(base, exponent) => Ok(format!(
-                "{} ^ {}",
-                self.output(base)?,
-                self.output(exponent)?
-            )),
-            Expr::Div(dividend, divisor) => Ok(format!(
-                "\\frac{{{}}}{{{}}}",
-                self.output(dividend)?,
-                self.output(divisor)?
+            Expr::Func(name, arg) => Ok(format!(
+                "{}{}",
+                Self::function_name(name),
+                Self::parenthesized(&self.output(arg)?)
             )),
-            Expr::Neg(inner) => Ok(format!("-{}", self.output(inner)?)),
-            Expr::Func(name, arg) => Ok(format!("{}({})", name, self.output(arg)?)),
+        }
+    }
+
+    fn output_derivative(&self, e: &Expr, var: char) -> Result<String, String> {
+        let body = match precedence(e) {
+            5 => self.output(e)?,
+            _ => Self::parenthesized(&self.output(e)?),
+        };
+        Ok(format!("\\frac{{d}}{{d{}}} {}", var, body))
+    }
+
+    fn output_bindings(&self, form: &LetForm) -> Result<String, String> {
+        let body = self.output(&form.body)?;
+        if form.bindings.is_empty() {
+            return Ok(body);
+        }
+        let mut bindings = Vec::new();
+        for (name, value) in &form.bindings {
+            bindings.push(format!("{} = {}", name, self.output(value)?));
+        }
+        Ok(format!(
+            "{} \\quad \\text{{where}} \\quad {}",
+            body,
+            bindings.join(", \\; ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::syntax::*;
+    use crate::parser::parse_str;
+    use crate::test_util::SplitMix64;
+
+    fn text(e: &Expr) -> String {
+        standard().output(e).unwrap()
+    }
+
+    /// A random expression of at most `depth` levels. `e` and `i` read back as constants,
+    /// so variables are drawn from `x`, `y` and `z` only.
+    fn random_expr(rng: &mut SplitMix64, depth: u32) -> Expr {
+        let choice = rng.next_u64() % if depth == 0 { 4 } else { 12 };
+        let pick = rng.next_u64() as usize;
+        let mut sub = || Box::new(random_expr(rng, depth - 1));
+        match choice {
+            0 => Expr::Const((pick % 20) as u32),
+            1 => Expr::Var(['x', 'y', 'z'][pick % 3]),
+            2 => [E, PI, TAU, PHI, I, INF][pick % 6].clone(),
+            3 => Expr::Var('x'),
+            4 => Expr::Add(sub(), sub()),
+            5 => Expr::Sub(sub(), sub()),
+            6 => Expr::Mul(sub(), sub()),
+            7 => Expr::Div(sub(), sub()),
+            8 => Expr::Pow(sub(), sub()),
+            9 => Expr::Neg(sub()),
+            10 => Expr::Mul(Box::new(Expr::Const(3)), sub()),
+            _ => {
+                let name = ["sin", "cos", "tan", "ln", "exp", "sqrt"][pick % 6];
+                Expr::Func(name.into(), sub())
+            }
+        }
+    }
+
+    #[test]
+    fn minimal_parentheses() {
+        assert_eq!(text(&X.pow(num(2)).plus(num(2).times(X))), "x^2 + 2x");
+        assert_eq!(text(&X.plus(num(1)).times(var('y'))), "(x + 1) * y");
+        assert_eq!(text(&X.times(X.plus(num(1)))), "x * (x + 1)");
+        assert_eq!(text(&X.minus(var('y').minus(num(1)))), "x - (y - 1)");
+        assert_eq!(text(&X.minus(var('y')).minus(num(1))), "x - y - 1");
+        assert_eq!(text(&X.div(num(2).times(var('y')))), "x / (2y)");
+        assert_eq!(text(&X.pow(var('y')).pow(num(2))), "(x^y)^2");
+        assert_eq!(text(&X.pow(var('y').pow(num(2)))), "x^y^2");
+        assert_eq!(text(&X.neg().pow(num(2))), "(-x)^2");
+        assert_eq!(text(&X.pow(num(2)).neg()), "-x^2");
+        assert_eq!(text(&X.times(num(2))), "x * 2");
+        assert_eq!(text(&num(2).times(sin(X.plus(PI)))), "2sin(x + pi)");
+    }
+
+    #[test]
+    fn variables_named_like_constants_are_not_written() {
+        assert!(standard().output(&var('p').times(var('i'))).is_err());
+        assert_eq!(
+            standard().output(&var('e')),
+            Err("variable [e] can't be written, it reads as a constant".into())
+        );
+        assert!(standard().output(&var('π')).is_err());
+        let product = var('p').times(I).plus(E);
+        assert_eq!(text(&product), "p * i + e");
+        assert_eq!(parse_str(text(&product)), Ok(product));
+    }
+
+    fn tex(e: &Expr) -> String {
+        latex().output(e).unwrap()
+    }
+
+    #[test]
+    fn latex_powers_functions_and_products() {
+        assert_eq!(tex(&X.pow(num(10))), "x^{10}");
+        assert_eq!(
+            tex(&X.plus(num(1)).pow(num(2).times(X))),
+            "\\left(x + 1\\right)^{2x}"
+        );
+        assert_eq!(tex(&sin(X).pow(num(2))), "\\sin^{2}\\left(x\\right)");
+        assert_eq!(
+            tex(&sin(X).pow(num(1).neg())),
+            "\\left(\\sin\\left(x\\right)\\right)^{-1}"
+        );
+        assert_eq!(
+            tex(&func("ln", X).pow(num(2))),
+            "\\left(\\ln\\left(x\\right)\\right)^{2}"
+        );
+        assert_eq!(tex(&func("ln", X)), "\\ln\\left(x\\right)");
+        assert_eq!(tex(&func("sqrt", X.plus(num(1)))), "\\sqrt{x + 1}");
+        assert_eq!(tex(&func("f", X)), "\\operatorname{f}\\left(x\\right)");
+        // factors keep their order, and are juxtaposed unless that would be ambiguous
+        assert_eq!(tex(&X.times(num(2))), "x \\cdot 2");
+        assert_eq!(tex(&num(2).times(X).times(var('y'))), "2x y");
+        assert_eq!(tex(&PI.times(X)), "\\pi x");
+        assert_eq!(tex(&num(2).times(X.plus(num(1)))), "2\\left(x + 1\\right)");
+        assert_eq!(tex(&X.times(X.neg())), "x\\left(-x\\right)");
+        assert_eq!(tex(&num(2).times(num(1).div(X))), "2 \\cdot \\frac{1}{x}");
+    }
+
+    #[test]
+    fn latex_signs_and_fractions() {
+        assert_eq!(tex(&X.neg().div(num(2))), "-\\frac{x}{2}");
+        assert_eq!(tex(&X.neg().div(num(2).neg())), "\\frac{x}{2}");
+        assert_eq!(
+            tex(&X.div(var('y').plus(num(1)).neg())),
+            "-\\frac{x}{y + 1}"
+        );
+        assert_eq!(
+            tex(&X.minus(num(1)).neg().div(X.neg().neg())),
+            "-\\frac{x - 1}{x}"
+        );
+        assert_eq!(tex(&var('y').plus(X.div(num(2).neg()))), "y - \\frac{x}{2}");
+        assert_eq!(tex(&var('y').plus(X.neg())), "y - x");
+        assert_eq!(tex(&var('y').minus(X.neg())), "y - \\left(-x\\right)");
+        assert_eq!(
+            tex(&var('y').minus(X.minus(num(1)))),
+            "y - \\left(x - 1\\right)"
+        );
+        assert_eq!(tex(&Expr::Neg(Box::new(X.neg()))), "-\\left(-x\\right)");
+        assert_eq!(tex(&X.plus(num(1)).neg()), "-\\left(x + 1\\right)");
+    }
+
+    #[test]
+    fn derivative_notation() {
+        let e = X.pow(num(2)).plus(X);
+        assert_eq!(
+            latex().output_derivative(&e, 'x').unwrap(),
+            "\\frac{d}{dx} \\left(x^{2} + x\\right)"
+        );
+        assert_eq!(
+            standard().output_derivative(&e, 'x').unwrap(),
+            "d/dx (x^2 + x)"
+        );
+    }
+
+    #[test]
+    fn output_parses_back_to_the_same_expression() {
+        let mut rng = SplitMix64::new(0x0123_4567_89ab_cdef);
+        for _ in 0..2000 {
+            let e = random_expr(&mut rng, 4);
+            let s = text(&e);
+            assert_eq!(parse_str(s.clone()), Ok(e), "{}", s);
         }
     }
 }
@@ -0,0 +1,327 @@
+use super::{juxtaposes, Out};
+use crate::expr::dag::LetForm;
+use crate::expr::Expr;
+
+/// A rectangle of text, every line the same width, that lines up with its neighbours on
+/// its `baseline` row.
+#[derive(Debug, Clone)]
+struct Block {
+    lines: Vec<String>,
+    baseline: usize,
+}
+
+impl Block {
+    fn text(s: &str) -> Block {
+        Block {
+            lines: vec![s.to_string()],
+            baseline: 0,
+        }
+    }
+
+    /// One character per row, for delimiters as tall as `rows`.
+    fn column(top: char, middle: char, bottom: char, rows: usize, baseline: usize) -> Block {
+        let lines = (0..rows)
+            .map(|row| match row {
+                0 => top,
+                r if r + 1 == rows => bottom,
+                _ => middle,
+            })
+            .map(String::from)
+            .collect();
+        Block { lines, baseline }
+    }
+
+    fn width(&self) -> usize {
+        self.lines.first().map_or(0, |line| line.chars().count())
+    }
+
+    fn height(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// `self` padded with spaces on both sides to `width`.
+    fn centered(&self, width: usize) -> Vec<String> {
+        let left = (width - self.width()) / 2;
+        let right = width - self.width() - left;
+        self.lines
+            .iter()
+            .map(|line| format!("{}{}{}", " ".repeat(left), line, " ".repeat(right)))
+            .collect()
+    }
+
+    /// `parts` side by side with their baselines on the same row.
+    fn beside(parts: &[Block]) -> Block {
+        let above = parts.iter().map(|b| b.baseline).max().unwrap_or(0);
+        let below = parts
+            .iter()
+            .map(|b| b.height() - b.baseline - 1)
+            .max()
+            .unwrap_or(0);
+        let lines = (0..above + below + 1)
+            .map(|row| {
+                let mut line = String::new();
+                for part in parts {
+                    let top = above - part.baseline;
+                    match row.checked_sub(top).and_then(|r| part.lines.get(r)) {
+                        Some(l) => line.push_str(l),
+                        None => line.push_str(&" ".repeat(part.width())),
+                    }
+                }
+                line
+            })
+            .collect();
+        Block {
+            lines,
+            baseline: above,
+        }
+    }
+
+    /// `numerator` over `denominator`, both centered on a bar as wide as the wider one and
+    /// `overhang` more on each side.
+    fn fraction(numerator: Block, denominator: Block, bar: char, overhang: usize) -> Block {
+        let width = numerator.width().max(denominator.width()) + 2 * overhang;
+        let mut lines = numerator.centered(width);
+        lines.push(bar.to_string().repeat(width));
+        lines.extend(denominator.centered(width));
+        Block {
+            lines,
+            baseline: numerator.height(),
+        }
+    }
+
+    /// `base` with `exponent` on the rows above it, to its right.
+    fn raised(base: Block, exponent: Block) -> Block {
+        let (base_width, exponent_width) = (base.width(), exponent.width());
+        let mut lines: Vec<String> = exponent
+            .lines
+            .iter()
+            .map(|line| format!("{}{}", " ".repeat(base_width), line))
+            .collect();
+        lines.extend(
+            base.lines
+                .iter()
+                .map(|line| format!("{}{}", line, " ".repeat(exponent_width))),
+        );
+        Block {
+            lines,
+            baseline: exponent.height() + base.baseline,
+        }
+    }
+
+    fn render(&self) -> String {
+        let lines: Vec<&str> = self.lines.iter().map(|line| line.trim_end()).collect();
+        lines.join("\n")
+    }
+}
+
+/// Fractions, powers and parentheses laid out over several lines, with box-drawing
+/// characters or, if not `unicode`, only ASCII.
+pub struct PrettyOut {
+    pub unicode: bool,
+}
+
+/// Stacked fractions delimit themselves, so they only need parentheses as the base of a
+/// power.
+fn precedence(e: &Expr) -> u8 {
+    match e {
+        Expr::Div(_, _) => 5,
+        other => super::precedence(other),
+    }
+}
+
+impl PrettyOut {
+    fn symbol(&self, unicode: &str, ascii: &str) -> Block {
+        Block::text(if self.unicode { unicode } else { ascii })
+    }
+
+    /// `inner` in parentheses as tall as it is.
+    fn parenthesized(&self, inner: Block) -> Block {
+        let (rows, baseline) = (inner.height(), inner.baseline);
+        let (left, right) = match (rows, self.unicode) {
+            (1, _) => (Block::text("("), Block::text(")")),
+            (_, true) => (
+                Block::column('⎛', '⎜', '⎝', rows, baseline),
+                Block::column('⎞', '⎟', '⎠', rows, baseline),
+            ),
+            (_, false) => (
+                Block::column('/', '|', '\\', rows, baseline),
+                Block::column('\\', '|', '/', rows, baseline),
+            ),
+        };
+        Block::beside(&[left, inner, right])
+    }
+
+    /// `e`, in parentheses if it binds more loosely than `at_least`.
+    fn operand(&self, e: &Expr, at_least: u8) -> Block {
+        let inner = self.block(e);
+        if precedence(e) < at_least {
+            self.parenthesized(inner)
+        } else {
+            inner
+        }
+    }
+
+    fn binary(&self, lhs: &Expr, op: &str, rhs: &Expr, level: u8) -> Block {
+        Block::beside(&[
+            self.operand(lhs, level),
+            Block::text(op),
+            self.operand(rhs, level + 1),
+        ])
+    }
+
+    fn fraction(&self, numerator: Block, denominator: Block, overhang: usize) -> Block {
+        let bar = if self.unicode { '─' } else { '-' };
+        Block::fraction(numerator, denominator, bar, overhang)
+    }
+
+    fn block(&self, e: &Expr) -> Block {
+        match e {
+            Expr::Var(name) => Block::text(&name.to_string()),
+            Expr::Const(n) => Block::text(&n.to_string()),
+            Expr::E => Block::text("e"),
+            Expr::Pi => self.symbol("π", "pi"),
+            Expr::Tau => self.symbol("τ", "tau"),
+            Expr::Phi => self.symbol("φ", "phi"),
+            Expr::I => Block::text("i"),
+            Expr::Inf => self.symbol("∞", "inf"),
+            Expr::Add(lhs, rhs) => self.binary(lhs, " + ", rhs, 1),
+            Expr::Sub(lhs, rhs) => self.binary(lhs, " - ", rhs, 1),
+            Expr::Mul(lhs, rhs) if matches!(**lhs, Expr::Const(_)) && juxtaposes(rhs) => {
+                self.binary(lhs, "", rhs, 2)
+            }
+            Expr::Mul(lhs, rhs) => self.binary(lhs, if self.unicode { "⋅" } else { "*" }, rhs, 2),
+            Expr::Div(lhs, rhs) => {
+                // a longer bar than that of a fraction right above or below it, so that
+                // (a/b)/c and a/(b/c) look different
+                let nested = matches!(**lhs, Expr::Div(_, _)) || matches!(**rhs, Expr::Div(_, _));
+                self.fraction(self.block(lhs), self.block(rhs), usize::from(nested))
+            }
+            Expr::Pow(base, exponent) => {
+                let base = match **base {
+                    Expr::Div(_, _) => self.parenthesized(self.block(base)),
+                    _ => self.operand(base, 5),
+                };
+                Block::raised(base, self.block(exponent))
+            }
+            Expr::Neg(inner) => Block::beside(&[Block::text("-"), self.operand(inner, 3)]),
+            // an overlined radical where the argument fits on one line
+            Expr::Func(name, arg) if name == "sqrt" && self.unicode => {
+                let arg = self.block(arg);
+                match arg.height() {
+                    1 => Block {
+                        lines: vec![
+                            format!(" {}", "_".repeat(arg.width())),
+                            format!("√{}", arg.lines[0]),
+                        ],
+                        baseline: 1,
+                    },
+                    _ => Block::beside(&[Block::text("√"), self.parenthesized(arg)]),
+                }
+            }
+            Expr::Func(name, arg) => {
+                Block::beside(&[Block::text(name), self.parenthesized(self.block(arg))])
+            }
+        }
+    }
+
+    /// `e`, in parentheses unless it is a single symbol or call.
+    fn group(&self, e: &Expr) -> Block {
+        match precedence(e) {
+            5 => self.block(e),
+            _ => self.parenthesized(self.block(e)),
+        }
+    }
+}
+
+impl Out for PrettyOut {
+    fn output(&self, e: &Expr) -> Result<String, String> {
+        Ok(self.block(e).render())
+    }
+
+    /// Several rows of text.
+    fn is_inline(&self) -> bool {
+        false
+    }
+
+    /// Each binding on its own rows, then the body.
+    fn output_bindings(&self, form: &LetForm) -> Result<String, String> {
+        let mut blocks = Vec::new();
+        for (name, value) in &form.bindings {
+            let name = Block::text(&format!("let {} = ", name));
+            blocks.push(Block::beside(&[name, self.block(value)]).render());
+        }
+        blocks.push(self.block(&form.body).render());
+        Ok(blocks.join("\n"))
+    }
+
+    fn output_derivative(&self, e: &Expr, var: char) -> Result<String, String> {
+        let operator = self.fraction(Block::text("d"), Block::text(&format!("d{}", var)), 0);
+        Ok(Block::beside(&[operator, self.group(e)]).render())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::expr::syntax::*;
+    use crate::expr::Expr;
+    use crate::out::{ascii_pretty, pretty, Out};
+
+    fn unicode(e: &Expr) -> String {
+        pretty().output(e).unwrap()
+    }
+
+    fn ascii(e: &Expr) -> String {
+        ascii_pretty().output(e).unwrap()
+    }
+
+    #[test]
+    fn fractions_stack_and_exponents_rise() {
+        let e = X.pow(num(2)).div(num(3).plus(X));
+        assert_eq!(unicode(&e), "  2\n x\n─────\n3 + x");
+        assert_eq!(ascii(&e), "  2\n x\n-----\n3 + x");
+        // the baselines of the operands line up with the fraction bar
+        let e = num(1).plus(num(1).div(X));
+        assert_eq!(unicode(&e), "    1\n1 + ─\n    x");
+        let e = X.neg().div(num(2));
+        assert_eq!(unicode(&e), "-x\n──\n2");
+        assert_eq!(unicode(&num(2).times(X)), "2x");
+        assert_eq!(unicode(&X.times(PI)), "x⋅π");
+        assert_eq!(ascii(&X.times(PI).plus(INF)), "x*pi + inf");
+    }
+
+    #[test]
+    fn nested_fractions_have_longer_bars() {
+        let (a, b, c) = (var('a'), var('b'), var('c'));
+        let left = a.clone().div(b.clone()).div(c.clone());
+        assert_eq!(unicode(&left), " a\n ─\n b\n───\n c");
+        assert_eq!(ascii(&left), " a\n -\n b\n---\n c");
+        let right = a.div(b.div(c));
+        assert_eq!(unicode(&right), " a\n───\n b\n ─\n c");
+        assert_eq!(ascii(&right), " a\n---\n b\n -\n c");
+    }
+
+    #[test]
+    fn parentheses_grow_with_their_contents() {
+        let e = X.div(num(2)).pow(num(3));
+        assert_eq!(unicode(&e), "   3\n⎛x⎞\n⎜─⎟\n⎝2⎠");
+        assert_eq!(ascii(&e), "   3\n/x\\\n|-|\n\\2/");
+        let e = sin(num(1).div(X));
+        assert_eq!(unicode(&e), "   ⎛1⎞\nsin⎜─⎟\n   ⎝x⎠");
+        assert_eq!(unicode(&X.plus(num(1)).times(var('y'))), "(x + 1)⋅y");
+        assert_eq!(unicode(&func("sqrt", X.plus(num(1)))), " _____\n√x + 1");
+        assert_eq!(ascii(&func("sqrt", X)), "sqrt(x)");
+    }
+
+    #[test]
+    fn notation_and_bindings() {
+        let e = X.pow(num(2)).plus(X);
+        assert_eq!(
+            pretty().output_derivative(&e, 'x').unwrap(),
+            "d ⎛ 2    ⎞\n──⎝x  + x⎠\ndx"
+        );
+        let form = sin(X).plus(sin(X).pow(num(2))).cse();
+        let s = ascii_pretty().output_bindings(&form).unwrap();
+        assert!(s.starts_with("let "));
+        assert_eq!(s.lines().count(), 3);
+    }
+}
@@ -1,117 +1,132 @@
 use crate::expr::Expr;
 use crate::lex::SubExpr;
 
-fn function_of_s(it: &Vec<SubExpr>) -> Option<(usize, SubExpr)> {
-    // windows of size 4 because we're looking for [F(name), OpenPar, Arg(single S), ClosePar]
-    it.windows(4).enumerate().find_map(|(index, slice)| {
-        if let [SubExpr::F(name), SubExpr::OpenPar, SubExpr::S(arg), SubExpr::ClosePar] = slice {
-            Some((
-                index,
-                SubExpr::S(Expr::Func(name.clone(), Box::new(arg.clone()))),
-            ))
-        } else {
-            None
-        }
-    })
+/// Recursive descent over the tokens, from the loosest binding operators to the tightest:
+///
+/// ```text
+/// sum      = product (("+" | "-") product)*
+/// product  = implicit (("*" | "/") implicit)*
+/// implicit = unary (ImplicitMul unary)*
+/// unary    = "-" unary | power
+/// power    = atom ("^" unary)?
+/// atom     = S | "(" sum ")" | F "(" sum ")"
+/// ```
+///
+/// so `+`, `-`, `*` and `/` associate to the left, `^` to the right, and `-x^2` is
+/// `-(x^2)`. Products without a sign bind tighter than `*` and `/`: `2x/3y` is
+/// `(2x)/(3y)`, while `x/3*y` is `(x/3)*y`.
+struct Parser {
+    tokens: Vec<SubExpr>,
+    position: usize,
 }
 
-fn s_in_parenthesis(it: &Vec<SubExpr>) -> Option<(usize, SubExpr)> {
-    // windows of size 3 because we're looking for [OpenPar, S(_), ClosePar]
-    it.windows(3).enumerate().find_map(|(index, slice)| {
-        if let [SubExpr::OpenPar, s @ SubExpr::S(_), SubExpr::ClosePar] = slice {
-            Some((index, s.clone()))
-        } else {
-            None
+impl Parser {
+    fn peek(&self) -> Option<&SubExpr> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<SubExpr> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    /// Consumes the next token if it is `token`.
+    fn eat(&mut self, token: &SubExpr) -> bool {
+        let found = self.peek() == Some(token);
+        if found {
+            self.position += 1;
         }
-    })
-}
+        found
+    }
 
-fn s_and_s_exponentiation(it: &Vec<SubExpr>) -> Option<(usize, SubExpr)> {
-    // windows of size 3 because we're looking for [S(_), ^, S(_)]
-    it.windows(3).enumerate().find_map(|(index, slice)| {
-        if let [SubExpr::S(base), SubExpr::Pow, SubExpr::S(exp)] = slice {
-            let expr = Expr::Pow(Box::new(base.clone()), Box::new(exp.clone()));
-            Some((index, SubExpr::S(expr)))
-        } else {
-            None
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.product()?;
+        loop {
+            if self.eat(&SubExpr::Plus) {
+                lhs = Expr::Add(Box::new(lhs), Box::new(self.product()?));
+            } else if self.eat(&SubExpr::Minus) {
+                lhs = Expr::Sub(Box::new(lhs), Box::new(self.product()?));
+            } else {
+                return Ok(lhs);
+            }
         }
-    })
-}
+    }
 
-fn s_and_s_multiplication(it: &Vec<SubExpr>) -> Option<(usize, SubExpr)> {
-    // windows of size 3 because we're looking for [S(_), *, S(_)]
-    it.windows(3).enumerate().find_map(|(index, slice)| {
-        if let [SubExpr::S(lhs), SubExpr::Mul, SubExpr::S(rhs)] = slice {
-            let expr = Expr::Mul(Box::new(lhs.clone()), Box::new(rhs.clone()));
-            Some((index, SubExpr::S(expr)))
-        } else {
-            None
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.implicit()?;
+        loop {
+            if self.eat(&SubExpr::Mul) {
+                lhs = Expr::Mul(Box::new(lhs), Box::new(self.implicit()?));
+            } else if self.eat(&SubExpr::Div) {
+                lhs = Expr::Div(Box::new(lhs), Box::new(self.implicit()?));
+            } else {
+                return Ok(lhs);
+            }
         }
-    })
-}
+    }
 
-fn s_and_s_division(it: &Vec<SubExpr>) -> Option<(usize, SubExpr)> {
-    // windows of size 3 because we're looking for [S(_), /, S(_)]
-    it.windows(3).enumerate().find_map(|(index, slice)| {
-        if let [SubExpr::S(lhs), SubExpr::Div, SubExpr::S(rhs)] = slice {
-            let expr = Expr::Div(Box::new(lhs.clone()), Box::new(rhs.clone()));
-            Some((index, SubExpr::S(expr)))
-        } else {
-            None
+    fn implicit(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        while self.eat(&SubExpr::ImplicitMul) {
+            lhs = Expr::Mul(Box::new(lhs), Box::new(self.unary()?));
         }
-    })
-}
+        Ok(lhs)
+    }
 
-fn s_and_s_addition(it: &Vec<SubExpr>) -> Option<(usize, SubExpr)> {
-    // windows of size 3 because we're looking for [S(_), +, S(_)]
-    it.windows(3).enumerate().find_map(|(index, slice)| {
-        if let [SubExpr::S(lhs), SubExpr::Plus, SubExpr::S(rhs)] = slice {
-            let expr = Expr::Add(Box::new(lhs.clone()), Box::new(rhs.clone()));
-            Some((index, SubExpr::S(expr)))
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat(&SubExpr::Minus) {
+            Ok(Expr::Neg(Box::new(self.unary()?)))
         } else {
-            None
+            self.power()
         }
-    })
-}
+    }
 
-fn s_and_s_subtraction(it: &Vec<SubExpr>) -> Option<(usize, SubExpr)> {
-    // windows of size 3 because we're looking for [S(_), -, S(_)]
-    it.windows(3).enumerate().find_map(|(index, slice)| {
-        if let [SubExpr::S(lhs), SubExpr::Minus, SubExpr::S(rhs)] = slice {
-            let expr = Expr::Sub(Box::new(lhs.clone()), Box::new(rhs.clone()));
-            Some((index, SubExpr::S(expr)))
+    fn power(&mut self) -> Result<Expr, String> {
+        let base = self.atom()?;
+        if self.eat(&SubExpr::Pow) {
+            Ok(Expr::Pow(Box::new(base), Box::new(self.unary()?)))
         } else {
-            None
+            Ok(base)
         }
-    })
-}
+    }
 
-pub fn parse(tokens: Vec<SubExpr>) -> Result<Expr, String> {
-    let mut remaining_tokens = tokens;
-    while !matches!(&remaining_tokens[..], &[SubExpr::S(_)]) {
-        if let Some((f_index, f)) = function_of_s(&remaining_tokens) {
-            _ = remaining_tokens.splice(f_index..f_index + 4, [f]);
-        } else if let Some((s_index, s)) = s_in_parenthesis(&remaining_tokens) {
-            _ = remaining_tokens.splice(s_index..s_index + 3, [s]);
-        } else if let Some((exp_index, exp)) = s_and_s_exponentiation(&remaining_tokens) {
-            _ = remaining_tokens.splice(exp_index..exp_index + 3, [exp]);
-        } else if let Some((mul_index, mul)) = s_and_s_multiplication(&remaining_tokens) {
-            _ = remaining_tokens.splice(mul_index..mul_index + 3, [mul]);
-        } else if let Some((div_index, div)) = s_and_s_division(&remaining_tokens) {
-            _ = remaining_tokens.splice(div_index..div_index + 3, [div]);
-        } else if let Some((addition_index, addition)) = s_and_s_addition(&remaining_tokens) {
-            _ = remaining_tokens.splice(addition_index..addition_index + 3, [addition]);
-        } else if let Some((sub_index, sub)) = s_and_s_subtraction(&remaining_tokens) {
-            _ = remaining_tokens.splice(sub_index..sub_index + 3, [sub]);
+    fn atom(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(SubExpr::S(e)) => Ok(e),
+            Some(SubExpr::OpenPar) => self.closed(),
+            Some(SubExpr::F(name)) => match self.next() {
+                Some(SubExpr::OpenPar) => Ok(Expr::Func(name, Box::new(self.closed()?))),
+                _ => Err(format!(
+                    "expected an argument in parentheses after [{}]",
+                    name
+                )),
+            },
+            Some(other) => Err(format!("Unrecognizable pattern: unexpected {:?}", other)),
+            None => Err("Unrecognizable pattern: unexpected end of expression".into()),
+        }
+    }
+
+    /// The rest of a parenthesized group, after its opening parenthesis.
+    fn closed(&mut self) -> Result<Expr, String> {
+        let inner = self.sum()?;
+        if self.eat(&SubExpr::ClosePar) {
+            Ok(inner)
         } else {
-            return Err(format!("Unrecognizable pattern: {:?}", &remaining_tokens));
+            Err("missing closing parenthesis".into())
         }
     }
+}
 
-    if let [SubExpr::S(e)] = &remaining_tokens[..] {
-        Ok(e.clone())
-    } else {
-        Err("Something went wrong".into())
+pub fn parse(tokens: Vec<SubExpr>) -> Result<Expr, String> {
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+    };
+    let e = parser.sum()?;
+    match parser.peek() {
+        None => Ok(e),
+        Some(token) => Err(format!("Unrecognizable pattern: unexpected {:?}", token)),
     }
 }
 
@@ -120,3 +135,55 @@ pub fn parse_str(s: String) -> Result<Expr, String> {
     let tokens = lex::tokenize(s)?;
     parse(tokens)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::syntax::*;
+
+    fn parsed(s: &str) -> Expr {
+        parse_str(s.into()).unwrap()
+    }
+
+    #[test]
+    fn operators_bind_by_precedence() {
+        assert_eq!(
+            parsed("1/x + 1/(x+1)"),
+            num(1).div(X).plus(num(1).div(X.plus(num(1))))
+        );
+        assert_eq!(parsed("a - b + c"), var('a').minus(var('b')).plus(var('c')));
+        assert_eq!(parsed("a / b * c"), var('a').div(var('b')).times(var('c')));
+        assert_eq!(parsed("2^3^x"), num(2).pow(num(3).pow(X)));
+        assert_eq!(parsed("-x^2"), X.pow(num(2)).neg());
+        assert_eq!(parsed("x^-1 - -x"), X.pow(num(1).neg()).minus(X.neg()));
+        assert_eq!(parsed("2sin(x + 1)"), num(2).times(sin(X.plus(num(1)))));
+    }
+
+    #[test]
+    fn powers_are_right_associative() {
+        // 2^3^2 is 2^9 = 512, not 8^2 = 64
+        assert_eq!(parsed("2^3^2"), parsed("2^(3^2)"));
+        assert_ne!(parsed("2^3^2"), parsed("(2^3)^2"));
+        assert_eq!(parsed("2^3^2").solve_for(&Default::default()), Ok(512.0));
+    }
+
+    #[test]
+    fn products_without_a_sign_bind_tighter_than_division() {
+        let y = var('y');
+        assert_eq!(
+            parsed("2x/3y"),
+            num(2).times(X).div(num(3).times(y.clone()))
+        );
+        assert_eq!(parsed("1/2x^2"), num(1).div(num(2).times(X.pow(num(2)))));
+        assert_eq!(parsed("x/3*y"), X.div(num(3)).times(y.clone()));
+        assert_eq!(parsed("2x * 3y"), num(2).times(X).times(num(3).times(y)));
+        assert_eq!(parsed("-2x"), num(2).neg().times(X));
+    }
+
+    #[test]
+    fn malformed_input_is_an_error() {
+        for s in ["x +", "(x + 1", "x + 1)", "sin x", "* x"] {
+            assert!(parse_str(s.into()).is_err(), "{}", s);
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use crate::expr::rational::Rational;
+use crate::expr::terms::with_coefficient;
+use crate::expr::Expr;
+use crate::poly::polynomial::{overflow, Monomial, Poly};
+use crate::poly::univariate::{content, UniPoly};
+
+/// Constant terms above this are not searched for rational roots, the divisor enumeration
+/// would take too long.
+const MAX_ROOT_SEARCH: i128 = 1_000_000_000_000;
+
+/// Polynomials of a higher degree in any generator are not factored, as univariate
+/// arithmetic works on dense coefficient vectors as long as the degree.
+pub(crate) const MAX_FACTORED_DEGREE: u32 = 256;
+
+pub(crate) fn degree_too_high() -> String {
+    format!(
+        "polynomials of degree above {} are not factored",
+        MAX_FACTORED_DEGREE
+    )
+}
+
+/// A polynomial written as `content * f1^k1 * f2^k2 * ...`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Factorization {
+    pub content: Rational,
+    pub factors: Vec<(Poly, u32)>,
+}
+
+impl Factorization {
+    /// The factorization of the product of both factorizations, merging equal factors.
+    /// Returns `None` if the content or a multiplicity overflows.
+    pub fn times(mut self, that: Factorization) -> Option<Factorization> {
+        self.content = self.content.checked_mul(that.content)?;
+        for (f, k) in that.factors {
+            match self.factors.iter_mut().find(|(g, _)| *g == f) {
+                Some((_, multiplicity)) => *multiplicity = multiplicity.checked_add(k)?,
+                None => self.factors.push((f, k)),
+            }
+        }
+        self.sort_factors();
+        Some(self)
+    }
+
+    /// Returns `None` if the content or a multiplicity overflows.
+    pub fn pow(mut self, exponent: u32) -> Option<Factorization> {
+        self.content = self.content.checked_pow(i32::try_from(exponent).ok()?)?;
+        for (_, k) in self.factors.iter_mut() {
+            *k = k.checked_mul(exponent)?;
+        }
+        Some(self)
+    }
+
+    /// Monomial factors first, then by degree.
+    fn sort_factors(&mut self) {
+        self.factors.sort_by(|(a, _), (b, _)| {
+            let a_is_generator = a.len() == 1 && a.as_constant().is_none();
+            let b_is_generator = b.len() == 1 && b.as_constant().is_none();
+            b_is_generator
+                .cmp(&a_is_generator)
+                .then_with(|| a.total_degree().cmp(&b.total_degree()))
+                .then_with(|| a.to_expr().cmp(&b.to_expr()))
+        });
+    }
+
+    /// Returns `None` if a coefficient can't be written, see [`Rational::to_expr`].
+    pub fn to_expr(&self) -> Option<Expr> {
+        let mut factors = Vec::new();
+        for (f, k) in &self.factors {
+            factors.push(match k {
+                1 => f.to_expr()?,
+                _ => Expr::Pow(Box::new(f.to_expr()?), Box::new(Expr::Const(*k))),
+            });
+        }
+        let product = factors
+            .into_iter()
+            .reduce(|acc, f| Expr::Mul(Box::new(acc), Box::new(f)))
+            .unwrap_or(Expr::Const(1));
+        with_coefficient(self.content, product)
+    }
+}
+
+/// Splits off the rational content, leaving integer coefficients without a common divisor
+/// and a positive leading term. Returns `None` if the common denominator overflows.
+pub fn primitive_part(p: &Poly) -> Option<(Rational, Poly)> {
+    let Some(&(leading, _)) = p.sorted_terms().first() else {
+        return Some((Rational::ONE, Poly::zero()));
+    };
+    let coefficients: Vec<Rational> = p.terms().map(|(_, c)| c).collect();
+    let content = content(&coefficients, leading)?;
+    Some((content, p.scale(content.recip())?))
+}
+
+/// Yun's square-free decomposition: the returned factors are pairwise coprime, have no
+/// repeated roots and multiply (each raised to its multiplicity) back to `p`, up to a constant.
+/// Returns `None` if a coefficient overflows.
+pub fn square_free(p: &UniPoly) -> Option<Vec<(UniPoly, u32)>> {
+    let mut result = Vec::new();
+    let mut c = p.gcd(&p.derivative()?)?;
+    let (mut w, _) = p.div_rem(&c)?;
+    let mut multiplicity = 1;
+    while w.degree() > 0 {
+        let y = w.gcd(&c)?;
+        let (factor, _) = w.div_rem(&y)?;
+        if factor.degree() > 0 {
+            result.push((factor, multiplicity));
+        }
+        (c, _) = c.div_rem(&y)?;
+        w = y;
+        multiplicity += 1;
+    }
+    Some(result)
+}
+
+fn divisors(n: i128) -> Vec<i128> {
+    let n = n.abs();
+    let mut small = Vec::new();
+    let mut large = Vec::new();
+    let mut d = 1;
+    while d * d <= n {
+        if n % d == 0 {
+            small.push(d);
+            if d * d != n {
+                large.push(n / d);
+            }
+        }
+        d += 1;
+    }
+    small.extend(large.into_iter().rev());
+    small
+}
+
+/// Finds a rational root of a primitive integer polynomial using the rational root theorem.
+fn rational_root(p: &UniPoly) -> Option<Rational> {
+    let constant = p.coefficients().first()?.numer();
+    if constant == 0 {
+        return Some(Rational::ZERO);
+    }
+    let leading = p.leading().numer();
+    if constant.abs() > MAX_ROOT_SEARCH || leading.abs() > MAX_ROOT_SEARCH {
+        return None;
+    }
+    let denominators = divisors(leading);
+    divisors(constant).into_iter().find_map(|n| {
+        denominators.iter().find_map(|&d| {
+            [Rational::new(n, d), Rational::new(-n, d)]
+                .into_iter()
+                // a candidate whose value overflows is not a root
+                .find(|&candidate| p.eval(candidate).is_some_and(|v| v.is_zero()))
+        })
+    })
+}
+
+/// Splits a square-free primitive polynomial into its linear factors over the rationals and
+/// whatever remains after dividing them out.
+fn split_linear_factors(p: UniPoly) -> Option<Vec<UniPoly>> {
+    let mut factors = Vec::new();
+    let mut remaining = p;
+    while remaining.degree() > 1 {
+        let Some(root) = rational_root(&remaining) else {
+            break;
+        };
+        // (d x - n) is the primitive linear factor of the root n/d
+        let linear = UniPoly::new(vec![
+            Rational::integer(-root.numer()),
+            Rational::integer(root.denom()),
+        ]);
+        let (quotient, _) = remaining.div_rem(&linear)?;
+        remaining = quotient.primitive()?.1;
+        factors.push(linear);
+    }
+    if remaining.degree() > 0 {
+        factors.push(remaining);
+    }
+    Some(factors)
+}
+
+/// Factors a polynomial over the rationals. Common monomials and the numeric content are
+/// always pulled out; univariate polynomials are further split into square-free parts and
+/// linear factors from rational roots. Irreducible quadratics and higher-degree factors
+/// without rational roots, as well as multivariate remainders, are left as they are.
+/// Fails if the degree in a generator is above [`MAX_FACTORED_DEGREE`] or a coefficient
+/// overflows along the way.
+pub fn factor_poly(p: &Poly) -> Result<Factorization, String> {
+    if p.max_degree() > MAX_FACTORED_DEGREE {
+        return Err(degree_too_high());
+    }
+    factored(p).ok_or_else(overflow)
+}
+
+fn factored(p: &Poly) -> Option<Factorization> {
+    if p.is_zero() {
+        return Some(Factorization {
+            content: Rational::ZERO,
+            factors: vec![],
+        });
+    }
+    let common = p.monomial_content();
+    let mut factors: Vec<(Poly, u32)> = common
+        .powers()
+        .map(|(g, e)| (Poly::generator(g.clone()), e))
+        .collect();
+    let rest = p
+        .terms()
+        .map(|(m, c)| Poly::term(c, m.divide(&common).unwrap_or_else(Monomial::one)))
+        .try_fold(Poly::zero(), |acc, t| acc.plus(&t))?;
+    let (content, primitive) = primitive_part(&rest)?;
+
+    let generators = primitive.generators();
+    match (generators.len(), generators.first()) {
+        (1, Some(g)) => {
+            let univariate = UniPoly::from_poly(&primitive, g).expect("a single generator");
+            for (part, k) in square_free(&univariate)? {
+                let linear = split_linear_factors(part.primitive()?.1)?;
+                factors.extend(linear.into_iter().map(|f| (f.to_poly(g), k)));
+            }
+        }
+        _ if primitive.as_constant().is_none() => factors.push((primitive, 1)),
+        _ => {}
+    }
+
+    let mut result = Factorization { content, factors };
+    result.sort_factors();
+    Some(result)
+}
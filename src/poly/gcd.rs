@@ -0,0 +1,80 @@
+use crate::expr::Expr;
+use crate::poly::factor::primitive_part;
+use crate::poly::polynomial::{Monomial, Poly};
+
+/// Leading coefficient of `p` viewed as a polynomial in `x`.
+fn leading_coefficient(p: &Poly, x: &Expr) -> Poly {
+    p.coefficients_in(x)
+        .into_iter()
+        .next_back()
+        .map(|(_, c)| c)
+        .unwrap_or_default()
+}
+
+/// Pseudo-remainder of `a` divided by `b`, both viewed as polynomials in `x`, with
+/// coefficients polynomial in the remaining generators.
+fn pseudo_remainder(a: &Poly, b: &Poly, x: &Expr) -> Option<Poly> {
+    let b_degree = b.degree_in(x);
+    let b_leading = leading_coefficient(b, x);
+    let mut remainder = a.clone();
+    while !remainder.is_zero() && remainder.degree_in(x) >= b_degree {
+        let shift = Monomial::of(x.clone(), remainder.degree_in(x) - b_degree);
+        let r_leading = leading_coefficient(&remainder, x);
+        let cancelled = r_leading.times(b)?.times_monomial(&shift)?;
+        remainder = b_leading.times(&remainder)?.minus(&cancelled)?;
+    }
+    Some(remainder)
+}
+
+/// GCD of all coefficients of `p` viewed as a polynomial in `x`.
+fn content_in(p: &Poly, x: &Expr) -> Option<Poly> {
+    p.coefficients_in(x)
+        .into_values()
+        .try_fold(Poly::zero(), |acc, c| poly_gcd(&acc, &c))
+}
+
+fn primitive_in(p: &Poly, x: &Expr) -> Option<Poly> {
+    p.exact_div(&content_in(p, x)?)
+}
+
+/// Greatest common divisor of two multivariate polynomials, normalised to integer
+/// coefficients without a common divisor and a positive leading term. The GCD of two
+/// constants is 1, as every nonzero rational divides any other.
+///
+/// This is the primitive polynomial remainder sequence, recursing into the coefficients
+/// for the content in the main generator. Returns `None` if a coefficient overflows.
+pub fn poly_gcd(a: &Poly, b: &Poly) -> Option<Poly> {
+    if a.is_zero() {
+        return Some(primitive_part(b)?.1);
+    }
+    if b.is_zero() {
+        return Some(primitive_part(a)?.1);
+    }
+    let mut generators = a.generators();
+    generators.extend(b.generators());
+    let Some(x) = generators.first() else {
+        return Some(Poly::one());
+    };
+    if a.degree_in(x) == 0 {
+        return poly_gcd(a, &content_in(b, x)?);
+    }
+    if b.degree_in(x) == 0 {
+        return poly_gcd(&content_in(a, x)?, b);
+    }
+
+    let content = poly_gcd(&content_in(a, x)?, &content_in(b, x)?)?;
+    let (mut p, mut q) = (primitive_in(a, x)?, primitive_in(b, x)?);
+    if p.degree_in(x) < q.degree_in(x) {
+        (p, q) = (q, p);
+    }
+    while !q.is_zero() {
+        if q.degree_in(x) == 0 {
+            // a remainder free of x means the primitive parts are coprime
+            return Some(content);
+        }
+        let r = pseudo_remainder(&p, &q, x)?;
+        p = q;
+        q = if r.is_zero() { r } else { primitive_in(&r, x)? };
+    }
+    Some(primitive_part(&content.times(&primitive_in(&p, x)?)?)?.1)
+}
@@ -0,0 +1,305 @@
+mod factor;
+mod gcd;
+mod polynomial;
+mod rational_function;
+mod univariate;
+
+pub(crate) use factor::MAX_FACTORED_DEGREE;
+pub use factor::{factor_poly, primitive_part, square_free, Factorization};
+pub use gcd::poly_gcd;
+pub use polynomial::{Monomial, Poly};
+pub use rational_function::RationalFunction;
+pub use univariate::UniPoly;
+
+use polynomial::{overflow, MAX_EXPANDED_EXPONENT};
+
+use crate::expr::rational::Rational;
+use crate::expr::terms::{build_sum, split_coefficient};
+use crate::expr::Expr;
+
+/// Applies `f` to the operands of a node that `Poly` treats as an atom.
+fn map_atom(e: &Expr, f: fn(&Expr) -> Result<Expr, String>) -> Result<Expr, String> {
+    Ok(match e {
+        Expr::Div(dividend, divisor) => Expr::Div(Box::new(f(dividend)?), Box::new(f(divisor)?)),
+        Expr::Pow(base, exponent) => Expr::Pow(Box::new(f(base)?), Box::new(f(exponent)?)),
+        Expr::Func(name, arg) => Expr::Func(name.clone(), Box::new(f(arg)?)),
+        other => other.clone(),
+    })
+}
+
+/// A power is only left as an atom when it has a negative or non integer exponent, or is
+/// a sum raised above [`MAX_EXPANDED_EXPONENT`]; the last one can't be expanded.
+fn expand_atom(atom: &Expr) -> Result<Expr, String> {
+    if let Expr::Pow(_, exponent) = atom {
+        let n = Rational::from_expr(exponent);
+        if n.is_some_and(|n| n.is_integer() && n.numer() > MAX_EXPANDED_EXPONENT) {
+            return Err(format!(
+                "sums are not raised to powers above {}",
+                MAX_EXPANDED_EXPONENT
+            ));
+        }
+    }
+    map_atom(atom, expanded)
+}
+
+fn expanded(e: &Expr) -> Result<Expr, String> {
+    Poly::from_expr_with(e, &expand_atom)?
+        .to_expr()
+        .ok_or_else(overflow)
+}
+
+/// Multiplies out all products and integer powers, e.g. `(x + 1)^2` becomes `x^2 + 2x + 1`.
+/// Function arguments, non integer powers and quotients are expanded separately. Fails,
+/// rather than returning a partly expanded result, if the coefficients overflow or a sum
+/// is raised above the 64th power.
+pub fn expand(e: &Expr) -> Result<Expr, String> {
+    expanded(e).map_err(|err| format!("can't expand expression: {}", err))
+}
+
+/// Expands `e` and groups its terms by powers of `var`, highest power first, e.g.
+/// `a x + b x + x^2` becomes `x^2 + (a + b)x`. Fails where [`expand`] does.
+pub fn collect(e: &Expr, var: char) -> Result<Expr, String> {
+    let x = Expr::Var(var);
+    let poly = Poly::from_expr_with(e, &expand_atom)
+        .map_err(|err| format!("can't collect expression: {}", err))?;
+    let terms = poly
+        .coefficients_in(&x)
+        .into_iter()
+        .rev()
+        .map(|(k, coefficient)| {
+            let power = Monomial::of(x.clone(), k).to_expr();
+            let written = coefficient.to_expr().ok_or_else(overflow)?;
+            Ok(if coefficient.len() == 1 {
+                // a single coefficient term merges with the power, keeping its sign
+                let (c, m) = split_coefficient(written);
+                let monomial = match (m, k) {
+                    (m, 0) => m,
+                    (Expr::Const(1), _) => power,
+                    (m, _) => Expr::Mul(Box::new(m), Box::new(power)),
+                };
+                (c, monomial)
+            } else if k == 0 {
+                (Rational::ONE, written)
+            } else {
+                (Rational::ONE, Expr::Mul(Box::new(written), Box::new(power)))
+            })
+        })
+        .collect::<Result<_, String>>()
+        .map_err(|err| format!("can't collect expression: {}", err))?;
+    build_sum(terms).ok_or_else(|| format!("can't collect expression: {}", overflow()))
+}
+
+fn factorization(e: &Expr) -> Result<Factorization, String> {
+    let whole = || factor_poly(&Poly::from_expr_with(e, &|atom| map_atom(atom, factored))?);
+    match e {
+        Expr::Mul(lhs, rhs) => factorization(lhs)?
+            .times(factorization(rhs)?)
+            .ok_or_else(overflow),
+        Expr::Neg(inner) => {
+            let mut negated = factorization(inner)?;
+            negated.content = -negated.content;
+            Ok(negated)
+        }
+        Expr::Pow(base, exponent) => match Rational::from_expr(exponent) {
+            Some(n) if n.is_integer() && (0..=i128::from(u32::MAX)).contains(&n.numer()) => {
+                factorization(base)?
+                    .pow(n.numer() as u32)
+                    .ok_or_else(overflow)
+            }
+            _ => whole(),
+        },
+        _ => whole(),
+    }
+}
+
+fn factored(e: &Expr) -> Result<Expr, String> {
+    factorization(e)?.to_expr().ok_or_else(overflow)
+}
+
+/// Factors `e` over the rationals as far as [`factor_poly`] can, e.g. `x^2 - 1` becomes
+/// `(x + 1)(x - 1)`. Factors already present in `e` are factored on their own rather than
+/// multiplied out first; arguments of functions and both sides of quotients are factored
+/// separately. Fails if the coefficients overflow.
+pub fn factor(e: &Expr) -> Result<Expr, String> {
+    factored(e).map_err(|err| format!("can't factor expression: {}", err))
+}
+
+/// Writes `e` as a single fraction in lowest terms, e.g. `1/x + 1/(x + 1)` becomes
+/// `(2x + 1)/(x^2 + x)`.
+pub fn together(e: &Expr) -> Result<Expr, String> {
+    RationalFunction::from_expr_with(e, &|atom| map_atom(atom, together_or_self))?
+        .to_expr()
+        .ok_or_else(overflow)
+}
+
+fn together_or_self(e: &Expr) -> Result<Expr, String> {
+    Ok(together(e).unwrap_or_else(|_| e.clone()))
+}
+
+/// Partial fraction decomposition of `e` as a rational function of `var`, e.g.
+/// `1/(x^2 - 1)` becomes `1 / (2 * (x - 1)) - 1 / (2 * (x + 1))`.
+pub fn apart(e: &Expr, var: char) -> Result<Expr, String> {
+    RationalFunction::from_expr(e)?.apart(&Expr::Var(var))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::syntax::*;
+    use crate::parser::parse_str;
+
+    fn parse(s: &str) -> Expr {
+        parse_str(s.into()).unwrap()
+    }
+
+    #[test]
+    fn expand_binomial_cube() {
+        let expr = expand(&X.plus(num(1)).pow(num(3)));
+        assert_eq!(expr, Ok(parse("x^3 + 3x^2 + 3x + 1")));
+    }
+
+    #[test]
+    fn expand_inside_function_arguments() {
+        let expr = expand(&sin(X.plus(num(1)).times(X.minus(num(1)))));
+        assert_eq!(expr, Ok(sin(parse("x^2 - 1"))));
+    }
+
+    #[test]
+    fn collect_groups_by_powers() {
+        let (a, b) = (var('a'), var('b'));
+        let expr = a
+            .clone()
+            .times(X)
+            .plus(b.clone().times(X))
+            .plus(X.pow(num(2)));
+        let expected = X.pow(num(2)).plus(a.plus(b).times(X));
+        assert_eq!(collect(&expr, 'x'), Ok(expected));
+    }
+
+    #[test]
+    fn factor_difference_of_squares() {
+        let expr = factor(&parse("x^2 - 1"));
+        assert_eq!(expr, Ok(X.plus(num(1)).times(X.minus(num(1)))));
+    }
+
+    #[test]
+    fn factor_repeated_roots_and_content() {
+        // 2x^3 + 4x^2 + 2x => 2x(x + 1)^2
+        let expr = factor(&parse("2x^3 + 4x^2 + 2x"));
+        let expected = num(2).times(X).times(X.plus(num(1)).pow(num(2)));
+        assert_eq!(expr, Ok(expected));
+    }
+
+    #[test]
+    fn factor_rational_roots() {
+        // 6x^2 - 5x + 1 => (2x - 1)(3x - 1)
+        let p = num(6)
+            .times(X.pow(num(2)))
+            .minus(num(5).times(X))
+            .plus(num(1));
+        let expr = factor(&p);
+        let expected = num(2)
+            .times(X)
+            .minus(num(1))
+            .times(num(3).times(X).minus(num(1)));
+        assert_eq!(expr, Ok(expected));
+    }
+
+    #[test]
+    fn factor_then_expand_is_identity() {
+        // (x - 1)^3 (x + 1)^2
+        let p = expand(
+            &X.minus(num(1))
+                .pow(num(3))
+                .times(X.plus(num(1)).pow(num(2))),
+        )
+        .unwrap();
+        assert_eq!(expand(&factor(&p).unwrap()), Ok(p));
+    }
+
+    #[test]
+    fn factor_keeps_multivariate_factors() {
+        // (2x + 2)(x + y) => 2(x + 1)(x + y)
+        let y = var('y');
+        let p = num(2).times(X).plus(num(2)).times(X.plus(y.clone()));
+        let expected = num(2).times(X.plus(num(1))).times(X.plus(y));
+        assert_eq!(factor(&p), Ok(expected));
+    }
+
+    #[test]
+    fn gcd_of_multivariate_polynomials() {
+        // gcd((x + y)^2 (x - 1), (x + y)(x + 2)) => x + y
+        let y = var('y');
+        let a = Poly::from_expr(&X.plus(y.clone()).pow(num(2)).times(X.minus(num(1)))).unwrap();
+        let b = Poly::from_expr(&X.plus(y.clone()).times(X.plus(num(2)))).unwrap();
+        assert_eq!(poly_gcd(&a, &b), Poly::from_expr(&X.plus(y)).ok());
+    }
+
+    #[test]
+    fn together_uses_common_denominator() {
+        // 1/x + 1/(x + 1) => (2x + 1)/(x^2 + x)
+        let expr = num(1).div(X).plus(num(1).div(X.plus(num(1))));
+        let expected = parse("2x + 1").div(parse("x^2 + x"));
+        assert_eq!(together(&expr), Ok(expected));
+    }
+
+    #[test]
+    fn together_cancels_common_factors() {
+        let expr = parse("x^2 - 1").div(X.minus(num(1)));
+        assert_eq!(together(&expr), Ok(X.plus(num(1))));
+    }
+
+    #[test]
+    fn apart_into_linear_factors() {
+        // (x + 3)/((x + 1)(x + 2)) => 2/(x + 1) - 1/(x + 2)
+        let expr = X.plus(num(3)).div(X.plus(num(1)).times(X.plus(num(2))));
+        let expected = num(2).div(X.plus(num(1))).minus(num(1).div(X.plus(num(2))));
+        assert_eq!(apart(&expr, 'x'), Ok(expected));
+    }
+
+    #[test]
+    fn apart_with_repeated_factors_and_polynomial_part() {
+        // (x^3 + 1)/(x^2 (x + 1)) = (x^2 - x + 1)/x^2 => 1 - 1/x + 1/x^2
+        let expr = parse("x^3 + 1").div(X.pow(num(2)).times(X.plus(num(1))));
+        let expected = num(1).minus(num(1).div(X)).plus(num(1).div(X.pow(num(2))));
+        assert_eq!(apart(&expr, 'x'), Ok(expected));
+    }
+
+    #[test]
+    fn overflowing_coefficients_are_reported() {
+        // 10^60 doesn't fit in an i128
+        let expr = num(10).pow(num(30)).times(X).plus(num(1)).pow(num(2));
+        assert!(expand(&expr).is_err());
+        assert!(factor(&num(2).times(X).pow(num(200))).is_err());
+    }
+
+    #[test]
+    fn overflowing_exponents_are_reported() {
+        assert!(expand(&parse("x^4000000000 * x^4000000000")).is_err());
+        assert!(expand(&parse("(x^3000000000)^2")).is_err());
+        assert_eq!(
+            expand(&parse("x^2000000000 * x^2000000000")),
+            Ok(X.pow(num(4_000_000_000)))
+        );
+    }
+
+    #[test]
+    fn high_degrees_are_not_factored() {
+        assert!(factor(&parse("x^4000000000 - 1")).is_err());
+        assert!(factor(&parse("x^20000 - 1")).is_err());
+        assert!(factor(&parse("x^256 - 1")).is_ok());
+        // (x^64)^5 is read as x^320
+        let expr = num(1).div(X.pow(num(64)).pow(num(5)).minus(num(1)));
+        assert!(apart(&expr, 'x').is_err());
+    }
+
+    #[test]
+    fn large_powers_of_sums_are_not_half_expanded() {
+        assert!(expand(&X.plus(num(1)).pow(num(200))).is_err());
+        assert_eq!(expand(&X.pow(num(200))), Ok(X.pow(num(200))));
+        assert_eq!(
+            expand(&var('y').times(X).pow(num(100))),
+            Ok(parse("x^100 y^100"))
+        );
+    }
+}
@@ -0,0 +1,414 @@
+use crate::expr::rational::Rational;
+use crate::expr::terms::build_sum;
+use crate::expr::Expr;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Neg;
+
+/// Largest integer exponent, positive or negative, that sums are raised to when converting
+/// an expression into a polynomial or rational function; anything above is kept as an
+/// opaque power.
+pub(crate) const MAX_EXPANDED_EXPONENT: i128 = 64;
+
+pub(crate) fn overflow() -> String {
+    "coefficients or exponents are too large".to_string()
+}
+
+/// A product of generators raised to positive integer powers, e.g. `x^2 y sin(x)`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Monomial(BTreeMap<Expr, u32>);
+
+impl Monomial {
+    pub fn one() -> Self {
+        Monomial(BTreeMap::new())
+    }
+
+    pub fn of(generator: Expr, exponent: u32) -> Self {
+        if exponent == 0 {
+            Monomial::one()
+        } else {
+            Monomial(BTreeMap::from([(generator, exponent)]))
+        }
+    }
+
+    pub fn is_one(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The total degree, saturating at `u32::MAX`.
+    pub fn degree(&self) -> u32 {
+        self.0.values().fold(0, |acc, &e| acc.saturating_add(e))
+    }
+
+    pub fn degree_in(&self, generator: &Expr) -> u32 {
+        self.0.get(generator).copied().unwrap_or(0)
+    }
+
+    pub fn powers(&self) -> impl Iterator<Item = (&Expr, u32)> {
+        self.0.iter().map(|(g, &e)| (g, e))
+    }
+
+    /// The monomial with `generator` removed.
+    pub fn without(&self, generator: &Expr) -> Self {
+        let mut powers = self.0.clone();
+        powers.remove(generator);
+        Monomial(powers)
+    }
+
+    /// Returns `None` if an exponent overflows.
+    pub fn times(&self, that: &Monomial) -> Option<Self> {
+        let mut powers = self.0.clone();
+        for (g, &e) in &that.0 {
+            let power = powers.entry(g.clone()).or_insert(0);
+            *power = power.checked_add(e)?;
+        }
+        Some(Monomial(powers))
+    }
+
+    /// `self / that`, if every exponent of `that` is covered by `self`.
+    pub fn divide(&self, that: &Monomial) -> Option<Self> {
+        let mut powers = self.0.clone();
+        for (g, &e) in &that.0 {
+            let own = powers.get_mut(g)?;
+            if *own < e {
+                return None;
+            }
+            *own -= e;
+            if *own == 0 {
+                powers.remove(g);
+            }
+        }
+        Some(Monomial(powers))
+    }
+
+    /// The largest monomial dividing both operands.
+    pub fn gcd(&self, that: &Monomial) -> Self {
+        Monomial(
+            self.0
+                .iter()
+                .filter_map(|(g, &e)| that.0.get(g).map(|&f| (g.clone(), e.min(f))))
+                .collect(),
+        )
+    }
+
+    /// Display order of monomials: higher total degree first, then higher powers of the
+    /// earlier generators first, so that `x^2 + xy + y^2` keeps that order.
+    pub fn display_cmp(&self, that: &Monomial) -> Ordering {
+        that.degree().cmp(&self.degree()).then_with(|| {
+            let generators: BTreeSet<&Expr> = self.0.keys().chain(that.0.keys()).collect();
+            generators
+                .into_iter()
+                .map(|g| that.degree_in(g).cmp(&self.degree_in(g)))
+                .find(|o| o.is_ne())
+                .unwrap_or(Ordering::Equal)
+        })
+    }
+
+    pub fn to_expr(&self) -> Expr {
+        self.0
+            .iter()
+            .map(|(g, &e)| match e {
+                1 => g.clone(),
+                _ => Expr::Pow(Box::new(g.clone()), Box::new(Expr::Const(e))),
+            })
+            .reduce(|acc, factor| Expr::Mul(Box::new(acc), Box::new(factor)))
+            .unwrap_or(Expr::Const(1))
+    }
+}
+
+/// A sparse multivariate polynomial with exact rational coefficients.
+///
+/// Generators are arbitrary expressions: variables and named constants, but also anything
+/// that is not polynomial itself, like `sin(x)` or `x^(1/2)`, which is treated as an atom.
+/// Arithmetic returns `None` when a coefficient overflows.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Poly {
+    terms: BTreeMap<Monomial, Rational>,
+}
+
+impl Poly {
+    pub fn zero() -> Self {
+        Poly::default()
+    }
+
+    pub fn one() -> Self {
+        Poly::constant(Rational::ONE)
+    }
+
+    pub fn constant(c: Rational) -> Self {
+        Poly::term(c, Monomial::one())
+    }
+
+    pub fn generator(g: Expr) -> Self {
+        Poly::term(Rational::ONE, Monomial::of(g, 1))
+    }
+
+    pub fn term(c: Rational, m: Monomial) -> Self {
+        let mut terms = BTreeMap::new();
+        if !c.is_zero() {
+            terms.insert(m, c);
+        }
+        Poly { terms }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn terms(&self) -> impl Iterator<Item = (&Monomial, Rational)> {
+        self.terms.iter().map(|(m, &c)| (m, c))
+    }
+
+    pub fn len(&self) -> usize {
+        self.terms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// The value of a polynomial without generators.
+    pub fn as_constant(&self) -> Option<Rational> {
+        match self.terms.len() {
+            0 => Some(Rational::ZERO),
+            1 => self.terms.get(&Monomial::one()).copied(),
+            _ => None,
+        }
+    }
+
+    pub fn generators(&self) -> BTreeSet<Expr> {
+        self.terms
+            .keys()
+            .flat_map(|m| m.powers().map(|(g, _)| g.clone()))
+            .collect()
+    }
+
+    pub fn total_degree(&self) -> u32 {
+        self.terms.keys().map(Monomial::degree).max().unwrap_or(0)
+    }
+
+    /// The highest power of any one generator.
+    pub fn max_degree(&self) -> u32 {
+        self.terms
+            .keys()
+            .flat_map(|m| m.powers().map(|(_, e)| e))
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn degree_in(&self, generator: &Expr) -> u32 {
+        self.terms
+            .keys()
+            .map(|m| m.degree_in(generator))
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn scale(&self, c: Rational) -> Option<Self> {
+        if c.is_zero() {
+            return Some(Poly::zero());
+        }
+        let terms = self
+            .terms
+            .iter()
+            .map(|(m, &k)| Some((m.clone(), k.checked_mul(c)?)))
+            .collect::<Option<_>>()?;
+        Some(Poly { terms })
+    }
+
+    pub fn times_monomial(&self, m: &Monomial) -> Option<Self> {
+        let terms = self
+            .terms
+            .iter()
+            .map(|(n, &k)| Some((n.times(m)?, k)))
+            .collect::<Option<_>>()?;
+        Some(Poly { terms })
+    }
+
+    pub fn pow(&self, exponent: u32) -> Option<Self> {
+        let mut result = Poly::one();
+        let mut base = self.clone();
+        let mut exponent = exponent;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.times(&base)?;
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = base.times(&base)?;
+            }
+        }
+        Some(result)
+    }
+
+    /// Groups the terms by their power of `generator`; the coefficients no longer contain it.
+    pub fn coefficients_in(&self, generator: &Expr) -> BTreeMap<u32, Poly> {
+        let mut result: BTreeMap<u32, Poly> = BTreeMap::new();
+        for (m, &c) in &self.terms {
+            // a monomial is determined by its power of `generator` and the rest, so no two
+            // terms land on the same coefficient monomial
+            let coefficient = result.entry(m.degree_in(generator)).or_default();
+            coefficient.terms.insert(m.without(generator), c);
+        }
+        result
+    }
+
+    /// The largest monomial dividing every term.
+    pub fn monomial_content(&self) -> Monomial {
+        self.terms
+            .keys()
+            .cloned()
+            .reduce(|acc, m| acc.gcd(&m))
+            .unwrap_or_default()
+    }
+
+    pub fn derivative(&self, generator: &Expr) -> Option<Self> {
+        let mut result = Poly::zero();
+        for (m, &c) in &self.terms {
+            let d = m.degree_in(generator);
+            if d > 0 {
+                let lowered = m
+                    .without(generator)
+                    .times(&Monomial::of(generator.clone(), d - 1))?;
+                result.add_term(c.checked_mul(Rational::integer(i128::from(d)))?, lowered)?;
+            }
+        }
+        Some(result)
+    }
+
+    /// The greatest term under [`Monomial::display_cmp`], a graded lexicographic order.
+    pub fn leading_term(&self) -> Option<(Rational, &Monomial)> {
+        self.terms
+            .iter()
+            .min_by(|(a, _), (b, _)| a.display_cmp(b))
+            .map(|(m, &c)| (c, m))
+    }
+
+    /// `self / divisor` if the division leaves no remainder and nothing overflows.
+    pub fn exact_div(&self, divisor: &Poly) -> Option<Poly> {
+        let (divisor_c, divisor_m) = divisor.leading_term()?;
+        let mut quotient = Poly::zero();
+        let mut remainder = self.clone();
+        while let Some((c, m)) = remainder.leading_term() {
+            let q = Poly::term(c.checked_div(divisor_c)?, m.divide(divisor_m)?);
+            remainder = remainder.minus(&q.times(divisor)?)?;
+            quotient = quotient.plus(&q)?;
+        }
+        Some(quotient)
+    }
+
+    fn add_term(&mut self, c: Rational, m: Monomial) -> Option<()> {
+        let sum = self
+            .terms
+            .get(&m)
+            .copied()
+            .unwrap_or(Rational::ZERO)
+            .checked_add(c)?;
+        if sum.is_zero() {
+            self.terms.remove(&m);
+        } else {
+            self.terms.insert(m, sum);
+        }
+        Some(())
+    }
+
+    pub fn plus(&self, that: &Poly) -> Option<Poly> {
+        let mut result = self.clone();
+        for (m, &c) in &that.terms {
+            result.add_term(c, m.clone())?;
+        }
+        Some(result)
+    }
+
+    pub fn minus(&self, that: &Poly) -> Option<Poly> {
+        self.plus(&-that.clone())
+    }
+
+    pub fn times(&self, that: &Poly) -> Option<Poly> {
+        let mut result = Poly::zero();
+        for (m, &c) in &self.terms {
+            for (n, &k) in &that.terms {
+                result.add_term(c.checked_mul(k)?, m.times(n)?)?;
+            }
+        }
+        Some(result)
+    }
+
+    /// Reads an expression as a polynomial, multiplying out products and integer powers.
+    /// Subexpressions that are not polynomial are kept as generators. Fails if a coefficient
+    /// overflows.
+    pub fn from_expr(e: &Expr) -> Result<Self, String> {
+        Poly::from_expr_with(e, &|atom| Ok(atom.clone()))
+    }
+
+    /// Like [`Poly::from_expr`], with `atom` applied to every non polynomial subexpression
+    /// before it becomes a generator.
+    pub fn from_expr_with(
+        e: &Expr,
+        atom: &dyn Fn(&Expr) -> Result<Expr, String>,
+    ) -> Result<Self, String> {
+        if let Some(r) = Rational::from_expr(e) {
+            return Ok(Poly::constant(r));
+        }
+        let read = |e: &Expr| Poly::from_expr_with(e, atom);
+        let generator = || Ok(Poly::generator(atom(e)?));
+        match e {
+            Expr::Add(lhs, rhs) => read(lhs)?.plus(&read(rhs)?).ok_or_else(overflow),
+            Expr::Sub(lhs, rhs) => read(lhs)?.minus(&read(rhs)?).ok_or_else(overflow),
+            Expr::Mul(lhs, rhs) => read(lhs)?.times(&read(rhs)?).ok_or_else(overflow),
+            Expr::Neg(inner) => Ok(-read(inner)?),
+            Expr::Div(dividend, divisor) => match Rational::from_expr(divisor) {
+                Some(d) if !d.is_zero() => read(dividend)?.scale(d.recip()).ok_or_else(overflow),
+                _ => generator(),
+            },
+            Expr::Pow(base, exponent) => match Rational::from_expr(exponent) {
+                Some(n) if n.is_integer() && (0..=MAX_EXPANDED_EXPONENT).contains(&n.numer()) => {
+                    read(base)?.pow(n.numer() as u32).ok_or_else(overflow)
+                }
+                // a single term is cheap to raise to any power its degree allows
+                Some(n) if n.is_integer() && n.numer() > 0 => {
+                    let base = read(base)?;
+                    match u32::try_from(n.numer()) {
+                        Ok(k)
+                            if base.len() == 1 && base.total_degree().checked_mul(k).is_some() =>
+                        {
+                            base.pow(k).ok_or_else(overflow)
+                        }
+                        _ => generator(),
+                    }
+                }
+                _ => generator(),
+            },
+            Expr::Const(_) => unreachable!("constants are always rational"),
+            _ => generator(),
+        }
+    }
+
+    /// Terms in the order of [`Monomial::display_cmp`].
+    pub fn sorted_terms(&self) -> Vec<(Rational, &Monomial)> {
+        let mut terms: Vec<_> = self.terms.iter().map(|(m, &c)| (c, m)).collect();
+        terms.sort_by(|(_, a), (_, b)| a.display_cmp(b));
+        terms
+    }
+
+    /// Returns `None` if a coefficient can't be written, see [`Rational::to_expr`].
+    pub fn to_expr(&self) -> Option<Expr> {
+        build_sum(
+            self.sorted_terms()
+                .into_iter()
+                .map(|(c, m)| (c, m.to_expr()))
+                .collect(),
+        )
+    }
+}
+
+impl Neg for Poly {
+    type Output = Poly;
+
+    /// Can't overflow, as no coefficient is ever `i128::MIN`.
+    fn neg(self) -> Poly {
+        Poly {
+            terms: self.terms.into_iter().map(|(m, c)| (m, -c)).collect(),
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use crate::expr::rational::Rational;
+use crate::expr::terms::build_sum;
+use crate::expr::Expr;
+use crate::poly::factor::{degree_too_high, factor_poly, primitive_part, MAX_FACTORED_DEGREE};
+use crate::poly::gcd::poly_gcd;
+use crate::poly::polynomial::{overflow, Poly, MAX_EXPANDED_EXPONENT};
+use crate::poly::univariate::UniPoly;
+
+/// A quotient of two polynomials, always kept in lowest terms: numerator and denominator
+/// share no common factor and the denominator is primitive with a positive leading term.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RationalFunction {
+    numerator: Poly,
+    denominator: Poly,
+}
+
+fn divides_by_zero() -> String {
+    "expression divides by zero".to_string()
+}
+
+impl RationalFunction {
+    /// Fails if `denominator` is zero or a coefficient overflows.
+    pub fn new(numerator: Poly, denominator: Poly) -> Result<Self, String> {
+        if denominator.is_zero() {
+            return Err(divides_by_zero());
+        }
+        if numerator.is_zero() {
+            return Ok(RationalFunction::from_poly(Poly::zero()));
+        }
+        let reduced = || {
+            let common = poly_gcd(&numerator, &denominator)?;
+            let numerator = numerator.exact_div(&common)?;
+            let (content, denominator) = primitive_part(&denominator.exact_div(&common)?)?;
+            Some(RationalFunction {
+                numerator: numerator.scale(content.recip())?,
+                denominator,
+            })
+        };
+        reduced().ok_or_else(overflow)
+    }
+
+    pub fn from_poly(p: Poly) -> Self {
+        RationalFunction {
+            numerator: p,
+            denominator: Poly::one(),
+        }
+    }
+
+    pub fn numerator(&self) -> &Poly {
+        &self.numerator
+    }
+
+    pub fn denominator(&self) -> &Poly {
+        &self.denominator
+    }
+
+    pub fn is_polynomial(&self) -> bool {
+        self.denominator == Poly::one()
+    }
+
+    pub fn plus(&self, that: &RationalFunction) -> Result<RationalFunction, String> {
+        // over the least common multiple of the denominators, to keep the gcd work small
+        let sum = || {
+            let common = poly_gcd(&self.denominator, &that.denominator)?;
+            let self_cofactor = that.denominator.exact_div(&common)?;
+            let that_cofactor = self.denominator.exact_div(&common)?;
+            let numerator = self
+                .numerator
+                .times(&self_cofactor)?
+                .plus(&that.numerator.times(&that_cofactor)?)?;
+            Some((numerator, self.denominator.times(&self_cofactor)?))
+        };
+        let (numerator, denominator) = sum().ok_or_else(overflow)?;
+        RationalFunction::new(numerator, denominator)
+    }
+
+    pub fn negated(&self) -> RationalFunction {
+        RationalFunction {
+            numerator: -self.numerator.clone(),
+            denominator: self.denominator.clone(),
+        }
+    }
+
+    pub fn times(&self, that: &RationalFunction) -> Result<RationalFunction, String> {
+        RationalFunction::new(
+            self.numerator.times(&that.numerator).ok_or_else(overflow)?,
+            self.denominator
+                .times(&that.denominator)
+                .ok_or_else(overflow)?,
+        )
+    }
+
+    /// Fails when dividing by zero.
+    pub fn divided_by(&self, that: &RationalFunction) -> Result<RationalFunction, String> {
+        RationalFunction::new(
+            self.numerator
+                .times(&that.denominator)
+                .ok_or_else(overflow)?,
+            self.denominator
+                .times(&that.numerator)
+                .ok_or_else(overflow)?,
+        )
+    }
+
+    pub fn pow(&self, exponent: i32) -> Result<RationalFunction, String> {
+        let magnitude = exponent.unsigned_abs();
+        let powered = RationalFunction {
+            numerator: self.numerator.pow(magnitude).ok_or_else(overflow)?,
+            denominator: self.denominator.pow(magnitude).ok_or_else(overflow)?,
+        };
+        if exponent < 0 {
+            RationalFunction::from_poly(Poly::one()).divided_by(&powered)
+        } else {
+            Ok(powered)
+        }
+    }
+
+    pub fn from_expr(e: &Expr) -> Result<Self, String> {
+        RationalFunction::from_expr_with(e, &|atom| Ok(atom.clone()))
+    }
+
+    /// Reads an expression as a rational function, failing if it divides by zero or its
+    /// coefficients overflow. Non rational subexpressions become generators after `atom`
+    /// is applied to them, as in [`Poly::from_expr_with`].
+    pub fn from_expr_with(
+        e: &Expr,
+        atom: &dyn Fn(&Expr) -> Result<Expr, String>,
+    ) -> Result<Self, String> {
+        if let Some(r) = Rational::from_expr(e) {
+            return Ok(RationalFunction::from_poly(Poly::constant(r)));
+        }
+        let read = |e: &Expr| RationalFunction::from_expr_with(e, atom);
+        match e {
+            Expr::Add(lhs, rhs) => read(lhs)?.plus(&read(rhs)?),
+            Expr::Sub(lhs, rhs) => read(lhs)?.plus(&read(rhs)?.negated()),
+            Expr::Mul(lhs, rhs) => read(lhs)?.times(&read(rhs)?),
+            Expr::Neg(inner) => Ok(read(inner)?.negated()),
+            Expr::Div(dividend, divisor) => read(dividend)?.divided_by(&read(divisor)?),
+            Expr::Pow(base, exponent) => match Rational::from_expr(exponent) {
+                Some(n)
+                    if n.is_integer()
+                        && (-MAX_EXPANDED_EXPONENT..=MAX_EXPANDED_EXPONENT)
+                            .contains(&n.numer()) =>
+                {
+                    read(base)?.pow(n.numer() as i32)
+                }
+                _ => Ok(RationalFunction::from_poly(Poly::generator(atom(e)?))),
+            },
+            _ => Ok(RationalFunction::from_poly(Poly::generator(atom(e)?))),
+        }
+    }
+
+    /// Returns `None` if a coefficient can't be written, see [`Rational::to_expr`].
+    pub fn to_expr(&self) -> Option<Expr> {
+        if self.is_polynomial() {
+            self.numerator.to_expr()
+        } else {
+            Some(Expr::Div(
+                Box::new(self.numerator.to_expr()?),
+                Box::new(self.denominator.to_expr()?),
+            ))
+        }
+    }
+
+    /// Partial fraction decomposition over the rationals in the single generator `x`: a
+    /// polynomial part followed by one fraction per power of each factor of the
+    /// denominator, with numerators of lower degree than their factor.
+    pub fn apart(&self, x: &Expr) -> Result<Expr, String> {
+        let not_univariate = || format!("expression is not a rational function of [{:?}] alone", x);
+        if self
+            .numerator
+            .degree_in(x)
+            .max(self.denominator.degree_in(x))
+            > MAX_FACTORED_DEGREE
+        {
+            return Err(degree_too_high());
+        }
+        let numerator = UniPoly::from_poly(&self.numerator, x).ok_or_else(not_univariate)?;
+        let denominator = UniPoly::from_poly(&self.denominator, x).ok_or_else(not_univariate)?;
+        self.decomposed(numerator, denominator, x)
+            .ok_or_else(overflow)
+    }
+
+    fn decomposed(&self, numerator: UniPoly, denominator: UniPoly, x: &Expr) -> Option<Expr> {
+        let (quotient, remainder) = numerator.div_rem(&denominator)?;
+        let mut terms: Vec<(Rational, Expr)> = quotient
+            .to_poly(x)
+            .sorted_terms()
+            .into_iter()
+            .map(|(c, m)| (c, m.to_expr()))
+            .collect();
+
+        let factorization = factor_poly(&self.denominator).ok()?;
+        let factors: Vec<(UniPoly, u32)> = factorization
+            .factors
+            .iter()
+            .map(|(f, k)| (UniPoly::from_poly(f, x).expect("a univariate factor"), *k))
+            .collect();
+        let mut numerator = remainder.scale(factorization.content.recip())?;
+        let mut denominator = factors
+            .iter()
+            .try_fold(UniPoly::constant(Rational::ONE), |acc, (f, k)| {
+                acc.times(&power(f, *k)?)
+            })?;
+
+        for (i, (f, k)) in factors.iter().enumerate() {
+            let whole = power(f, *k)?;
+            let part = if i + 1 == factors.len() {
+                numerator.clone()
+            } else {
+                // s F + t G = 1 splits n / (F G) into (n t mod F) / F + (n s mod G) / G
+                let (rest, _) = denominator.div_rem(&whole)?;
+                let (_, s, t) = whole.extended_gcd(&rest)?;
+                let part = numerator.times(&t)?.div_rem(&whole)?.1;
+                numerator = numerator.times(&s)?.div_rem(&rest)?.1;
+                denominator = rest;
+                part
+            };
+            // expand the numerator in powers of f, c0 + c1 f + c2 f^2 + ..., where each
+            // c_j becomes the fraction c_j / f^(k - j)
+            let mut digits = part;
+            let mut fractions = Vec::new();
+            for j in 0..*k {
+                let (next, digit) = digits.div_rem(f)?;
+                if !digit.is_zero() {
+                    fractions.push(fraction_term(&digit.to_poly(x), &f.to_poly(x), k - j)?);
+                }
+                digits = next;
+            }
+            terms.extend(fractions.into_iter().rev());
+        }
+        build_sum(terms)
+    }
+}
+
+fn power(f: &UniPoly, k: u32) -> Option<UniPoly> {
+    (0..k).try_fold(UniPoly::constant(Rational::ONE), |acc, _| acc.times(f))
+}
+
+/// The term `numerator / factor^k` with its sign pulled out, or `None` if a coefficient
+/// can't be written.
+fn fraction_term(numerator: &Poly, factor: &Poly, k: u32) -> Option<(Rational, Expr)> {
+    let base = match k {
+        1 => factor.to_expr()?,
+        _ => Expr::Pow(Box::new(factor.to_expr()?), Box::new(Expr::Const(k))),
+    };
+    Some(match numerator.as_constant() {
+        Some(c) => {
+            let divisor = match c.denom() {
+                1 => base,
+                d => Expr::Mul(Box::new(Rational::integer(d).to_expr()?), Box::new(base)),
+            };
+            let sign = if c.is_negative() {
+                -Rational::ONE
+            } else {
+                Rational::ONE
+            };
+            let dividend = Rational::integer(c.numer().abs()).to_expr()?;
+            (sign, Expr::Div(Box::new(dividend), Box::new(divisor)))
+        }
+        None => (
+            Rational::ONE,
+            Expr::Div(Box::new(numerator.to_expr()?), Box::new(base)),
+        ),
+    })
+}
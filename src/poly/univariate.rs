@@ -0,0 +1,214 @@
+use crate::expr::rational::{checked_lcm, gcd, Rational};
+use crate::expr::Expr;
+use crate::poly::polynomial::{Monomial, Poly};
+
+/// A dense polynomial in a single generator, coefficients stored from the constant term up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UniPoly {
+    coefficients: Vec<Rational>,
+}
+
+impl UniPoly {
+    pub fn new(coefficients: Vec<Rational>) -> Self {
+        let mut p = UniPoly { coefficients };
+        p.trim();
+        p
+    }
+
+    pub fn constant(c: Rational) -> Self {
+        UniPoly::new(vec![c])
+    }
+
+    fn trim(&mut self) {
+        while self.coefficients.last().is_some_and(Rational::is_zero) {
+            self.coefficients.pop();
+        }
+    }
+
+    pub fn coefficients(&self) -> &[Rational] {
+        &self.coefficients
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.coefficients.is_empty()
+    }
+
+    /// Degree of the polynomial, with the zero polynomial given degree 0.
+    pub fn degree(&self) -> usize {
+        self.coefficients.len().saturating_sub(1)
+    }
+
+    pub fn leading(&self) -> Rational {
+        self.coefficients.last().copied().unwrap_or(Rational::ZERO)
+    }
+
+    pub fn scale(&self, c: Rational) -> Option<Self> {
+        let coefficients = self
+            .coefficients
+            .iter()
+            .map(|&k| k.checked_mul(c))
+            .collect::<Option<_>>()?;
+        Some(UniPoly::new(coefficients))
+    }
+
+    pub fn monic(&self) -> Option<Self> {
+        if self.is_zero() {
+            Some(self.clone())
+        } else {
+            self.scale(self.leading().recip())
+        }
+    }
+
+    pub fn plus(&self, that: &UniPoly) -> Option<Self> {
+        let len = self.coefficients.len().max(that.coefficients.len());
+        let coefficients = (0..len)
+            .map(|i| self.coeff(i).checked_add(that.coeff(i)))
+            .collect::<Option<_>>()?;
+        Some(UniPoly::new(coefficients))
+    }
+
+    pub fn minus(&self, that: &UniPoly) -> Option<Self> {
+        self.plus(&that.scale(-Rational::ONE)?)
+    }
+
+    pub fn times(&self, that: &UniPoly) -> Option<Self> {
+        if self.is_zero() || that.is_zero() {
+            return Some(UniPoly::new(vec![]));
+        }
+        let mut result =
+            vec![Rational::ZERO; self.coefficients.len() + that.coefficients.len() - 1];
+        for (i, &a) in self.coefficients.iter().enumerate() {
+            for (j, &b) in that.coefficients.iter().enumerate() {
+                result[i + j] = result[i + j].checked_add(a.checked_mul(b)?)?;
+            }
+        }
+        Some(UniPoly::new(result))
+    }
+
+    fn coeff(&self, i: usize) -> Rational {
+        self.coefficients.get(i).copied().unwrap_or(Rational::ZERO)
+    }
+
+    /// Euclidean division, returning the quotient and remainder.
+    pub fn div_rem(&self, divisor: &UniPoly) -> Option<(UniPoly, UniPoly)> {
+        assert!(!divisor.is_zero(), "polynomial division by zero");
+        let mut remainder = self.coefficients.clone();
+        let d = divisor.degree();
+        if remainder.len() <= d {
+            return Some((UniPoly::new(vec![]), self.clone()));
+        }
+        let mut quotient = vec![Rational::ZERO; remainder.len() - d];
+        let lead = divisor.leading();
+        for i in (0..quotient.len()).rev() {
+            let q = remainder[i + d].checked_div(lead)?;
+            quotient[i] = q;
+            if q.is_zero() {
+                continue;
+            }
+            for (j, &c) in divisor.coefficients.iter().enumerate() {
+                remainder[i + j] = remainder[i + j].checked_sub(q.checked_mul(c)?)?;
+            }
+        }
+        Some((UniPoly::new(quotient), UniPoly::new(remainder)))
+    }
+
+    pub fn derivative(&self) -> Option<Self> {
+        let coefficients = self
+            .coefficients
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(i, &c)| c.checked_mul(Rational::integer(i as i128)))
+            .collect::<Option<_>>()?;
+        Some(UniPoly::new(coefficients))
+    }
+
+    /// Monic greatest common divisor.
+    pub fn gcd(&self, that: &UniPoly) -> Option<Self> {
+        let (mut a, mut b) = (self.clone(), that.clone());
+        while !b.is_zero() {
+            let (_, r) = a.div_rem(&b)?;
+            (a, b) = (b, r);
+        }
+        a.monic()
+    }
+
+    /// Extended Euclidean algorithm: returns `(g, s, t)` with `s * self + t * that = g`,
+    /// where `g` is the monic GCD.
+    pub fn extended_gcd(&self, that: &UniPoly) -> Option<(UniPoly, UniPoly, UniPoly)> {
+        let (mut r0, mut r1) = (self.clone(), that.clone());
+        let (mut s0, mut s1) = (UniPoly::constant(Rational::ONE), UniPoly::new(vec![]));
+        let (mut t0, mut t1) = (UniPoly::new(vec![]), UniPoly::constant(Rational::ONE));
+        while !r1.is_zero() {
+            let (q, r) = r0.div_rem(&r1)?;
+            (r0, r1) = (r1, r);
+            let s = s0.minus(&q.times(&s1)?)?;
+            (s0, s1) = (s1, s);
+            let t = t0.minus(&q.times(&t1)?)?;
+            (t0, t1) = (t1, t);
+        }
+        let normalizer = if r0.is_zero() {
+            Rational::ONE
+        } else {
+            r0.leading().recip()
+        };
+        Some((
+            r0.scale(normalizer)?,
+            s0.scale(normalizer)?,
+            t0.scale(normalizer)?,
+        ))
+    }
+
+    pub fn eval(&self, x: Rational) -> Option<Rational> {
+        self.coefficients
+            .iter()
+            .rev()
+            .try_fold(Rational::ZERO, |acc, &c| acc.checked_mul(x)?.checked_add(c))
+    }
+
+    /// Splits the polynomial into a rational content and a primitive polynomial with coprime
+    /// integer coefficients and a positive leading coefficient.
+    pub fn primitive(&self) -> Option<(Rational, UniPoly)> {
+        if self.is_zero() {
+            return Some((Rational::ONE, self.clone()));
+        }
+        let content = content(&self.coefficients, self.leading())?;
+        Some((content, self.scale(content.recip())?))
+    }
+
+    pub fn from_poly(p: &Poly, generator: &Expr) -> Option<Self> {
+        let mut coefficients = vec![Rational::ZERO; p.degree_in(generator) as usize + 1];
+        for (m, c) in p.terms() {
+            if m.powers().any(|(g, _)| g != generator) {
+                return None;
+            }
+            coefficients[m.degree_in(generator) as usize] = c;
+        }
+        Some(UniPoly::new(coefficients))
+    }
+
+    pub fn to_poly(&self, generator: &Expr) -> Poly {
+        let mut result = Poly::zero();
+        for (i, &c) in self.coefficients.iter().enumerate() {
+            // every power of the generator is a distinct monomial, so nothing is added up
+            result = result
+                .plus(&Poly::term(c, Monomial::of(generator.clone(), i as u32)))
+                .expect("distinct monomials don't add up");
+        }
+        result
+    }
+}
+
+/// The positive rational whose quotient with every coefficient is an integer, and which
+/// leaves those integers without a common divisor, signed like `leading`. `None` if the
+/// common denominator overflows.
+pub(crate) fn content(coefficients: &[Rational], leading: Rational) -> Option<Rational> {
+    let lcm_den = coefficients
+        .iter()
+        .try_fold(1i128, |acc, c| checked_lcm(acc, c.denom()))?;
+    let gcd_num = coefficients.iter().try_fold(0i128, |acc, c| {
+        let scaled = c.numer().checked_mul(lcm_den / c.denom())?;
+        (scaled != i128::MIN).then(|| gcd(acc, scaled))
+    })?;
+    Some(Rational::new(gcd_num * leading.numer().signum(), lcm_den))
+}